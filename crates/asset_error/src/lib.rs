@@ -0,0 +1,40 @@
+use std::fmt;
+
+/// Why an asset couldn't be loaded.
+#[derive(Debug)]
+pub enum AssetError {
+    /// The asset or one of its dependencies couldn't be read.
+    Io(std::io::Error),
+    /// The file is malformed.
+    Parse(String),
+    /// The file is well formed, but uses something that isn't supported.
+    Unsupported(String),
+    /// The asset was read, but doesn't hold together (e.g. a shader that doesn't validate).
+    Validation(String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io(e) => write!(f, "io error: {}", e),
+            AssetError::Parse(e) => write!(f, "parse error: {}", e),
+            AssetError::Unsupported(e) => write!(f, "unsupported: {}", e),
+            AssetError::Validation(e) => write!(f, "validation error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AssetError {
+    fn from(e: std::io::Error) -> Self {
+        AssetError::Io(e)
+    }
+}
@@ -0,0 +1,189 @@
+use std::{f32::consts::PI, path::Path};
+
+use asset_error::AssetError;
+
+use crate::image::{image_error_to_asset_error, srgb_to_linear};
+
+/// Six square faces of linear RGBA half floats, in the +X, -X, +Y, -Y, +Z, -Z order.
+pub struct Cubemap {
+    size: u32,
+    data: Vec<u16>,
+}
+
+impl Cubemap {
+    const FACE_NAMES: [&'static str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+    /// Loads either an equirectangular image (such as an HDR panorama), or a directory with one
+    /// image per face named `px`, `nx`, `py`, `ny`, `pz` and `nz`, with any extension.
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Self::load_faces_from_dir(path)
+        } else {
+            let dyn_image = image::open(path).map_err(image_error_to_asset_error)?;
+            Ok(Self::from_equirectangular(&LinearImage::new(dyn_image)))
+        }
+    }
+
+    pub fn new_dummy() -> Self {
+        Self {
+            size: 1,
+            data: vec![0; 6 * 4],
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn data(&self) -> &[u16] {
+        &self.data
+    }
+
+    fn load_faces_from_dir(dir: &Path) -> Result<Self, AssetError> {
+        let entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        let mut size = None;
+        let mut data = Vec::new();
+        for name in Self::FACE_NAMES {
+            let face_path = entries
+                .iter()
+                .map(|entry| entry.path())
+                .find(|path| path.file_stem().is_some_and(|stem| stem == name))
+                .ok_or_else(|| {
+                    AssetError::Validation(format!("no {} face in {}", name, dir.display()))
+                })?;
+            let dyn_image = image::open(face_path).map_err(image_error_to_asset_error)?;
+            let face = LinearImage::new(dyn_image);
+            if face.width != face.height || size.is_some_and(|size| size != face.width) {
+                return Err(AssetError::Validation(format!(
+                    "cubemap faces must be square and all the same size: {} face is {}x{}",
+                    name, face.width, face.height
+                )));
+            }
+            size = Some(face.width);
+            data.extend(face.pixels.iter().flatten().copied().map(f32_to_f16));
+        }
+
+        Ok(Self {
+            size: size.unwrap(),
+            data,
+        })
+    }
+
+    fn from_equirectangular(image: &LinearImage) -> Self {
+        let size = (image.width / 4).max(1);
+        let mut data = Vec::with_capacity((6 * size * size * 4) as usize);
+        for face in 0..6 {
+            for y in 0..size {
+                for x in 0..size {
+                    let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    let [dx, dy, dz] = face_direction(face, u, v);
+                    let length = (dx * dx + dy * dy + dz * dz).sqrt();
+
+                    // Longitude 0 is straight ahead along +Z, increasing towards +X.
+                    let longitude = dx.atan2(dz);
+                    let latitude = (dy / length).asin();
+                    let pixel = image.sample_bilinear(
+                        0.5 + longitude / (2.0 * PI),
+                        0.5 - latitude / PI,
+                    );
+                    data.extend(pixel.map(f32_to_f16));
+                }
+            }
+        }
+
+        Self { size, data }
+    }
+}
+
+/// Direction through a face at `u` and `v` in -1..1, v going down. Follows the usual cubemap
+/// convention, in which the faces look right from inside in a left-handed, y up system.
+fn face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
+
+struct LinearImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl LinearImage {
+    /// Float images are taken as linear already, the others as sRGB.
+    fn new(dyn_image: image::DynamicImage) -> Self {
+        let is_float = matches!(
+            dyn_image,
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+        );
+        let rgba = dyn_image.into_rgba32f();
+        let pixels = rgba
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                if is_float {
+                    [r, g, b, a]
+                } else {
+                    [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+                }
+            })
+            .collect();
+        Self {
+            width: rgba.width(),
+            height: rgba.height(),
+            pixels,
+        }
+    }
+
+    /// Wraps around horizontally and clamps vertically.
+    fn sample_bilinear(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let column = |x: f32| (x as i64).rem_euclid(self.width as i64) as u32;
+        let row = |y: f32| (y as u32).min(self.height - 1);
+        let pixel = |x: u32, y: u32| self.pixels[(y * self.width + x) as usize];
+
+        let (x0, x1) = (column(x0), column(x0 + 1.0));
+        let (y0, y1) = (row(y0), row(y0 + 1.0));
+        let top = lerp(pixel(x0, y0), pixel(x1, y0), tx);
+        let bottom = lerp(pixel(x0, y1), pixel(x1, y1), tx);
+        lerp(top, bottom, ty)
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// Negative values and NaNs become 0, values too large for a half float become its largest one.
+fn f32_to_f16(value: f32) -> u16 {
+    if value.is_nan() || value <= 0.0 {
+        return 0;
+    }
+    let bits = value.min(65504.0).to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        // Subnormal, the implicit leading one becomes explicit.
+        if exponent < -10 {
+            return 0;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return ((mantissa >> shift) + round) as u16;
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    // Rounding up may carry into the exponent, which is still right.
+    (half + round).min(0x7bff) as u16
+}
@@ -1,21 +1,58 @@
 use std::path::Path;
 
+use asset_error::AssetError;
+
+/// What the color channels of an image hold, which decides how they are averaged into mips.
+/// Alpha is always linear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Colors, averaged in linear space and stored back as sRGB.
+    Srgb,
+    /// Data like normals or roughness, averaged as is.
+    Linear,
+}
+
 pub struct Image {
     inner: image::RgbaImage,
     mips: Option<Mips>,
 }
 
 impl Image {
-    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, String> {
-        let dyn_image = image::open(path).map_err(|e| format!("{:?}", e))?;
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        let dyn_image = image::open(path).map_err(image_error_to_asset_error)?;
         Ok(Self::from_dynamic_image(dyn_image))
     }
 
-    pub fn load_from_memory(data: &[u8]) -> Result<Self, String> {
-        let dyn_image = image::load_from_memory(data).map_err(|e| format!("{:?}", e))?;
+    pub fn load_from_memory(data: &[u8]) -> Result<Self, AssetError> {
+        let dyn_image = image::load_from_memory(data).map_err(image_error_to_asset_error)?;
         Ok(Self::from_dynamic_image(dyn_image))
     }
 
+    /// Makes an image out of tightly packed RGBA8 pixels, row by row from the top.
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> Result<Self, AssetError> {
+        let inner = image::RgbaImage::from_raw(width, height, data).ok_or_else(|| {
+            AssetError::Validation(format!(
+                "not enough pixel data for a {width}x{height} image"
+            ))
+        })?;
+        Ok(Self { inner, mips: None })
+    }
+
+    /// Writes the full size image, without its mips. The format comes from the extension of
+    /// `path`. Formats without transparency, like JPEG, drop the alpha channel.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> Result<(), AssetError> {
+        let path = path.as_ref();
+        let format = image::ImageFormat::from_path(path).map_err(image_error_to_asset_error)?;
+        let result = if format == image::ImageFormat::Jpeg {
+            image::DynamicImage::ImageRgba8(self.inner.clone())
+                .into_rgb8()
+                .save_with_format(path, format)
+        } else {
+            self.inner.save_with_format(path, format)
+        };
+        result.map_err(image_error_to_asset_error)
+    }
+
     pub fn new_dummy() -> Self {
         let inner = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 128, 255]));
         Self {
@@ -31,60 +68,73 @@ impl Image {
         }
     }
 
-    pub fn make_mips(&mut self) -> Result<(), String> {
-        if !self.width().is_power_of_two() || !(self.height() == self.width()) {
-            return Err(format!("can't generate mipmaps on images that aren't square and that have non power of two dimensions: dimensions {}x{}", self.width(), self.height()));
+    /// With `premultiply_alpha`, colors are weighted by their alpha when averaged, which is what
+    /// images with transparency want. It makes no difference on opaque images.
+    /// Each level halves the width and the height, rounding down, until both are 1.
+    pub fn make_mips(
+        &mut self,
+        color_space: ColorSpace,
+        premultiply_alpha: bool,
+    ) -> Result<(), AssetError> {
+        const N: usize = 4;
+
+        fn mip_size_from_level(level0_size: usize, level: usize) -> usize {
+            (level0_size >> level).max(1)
         }
 
-        fn make_mips<const N: usize>(src_pixel_width: usize, src_data: &[u8]) -> Mips {
-            let max_level: Option<usize> = None;
-
-            fn mip_size_from_level(level0_size: usize, level: usize) -> usize {
-                level0_size >> level
-            }
-            fn data_size_from_mip_size(size: usize, pixel_byte_size: usize) -> usize {
-                size * size * pixel_byte_size
-            }
-
-            let level0_size = src_pixel_width;
-            let level_count =
-                (level0_size.ilog2() as usize + 1).min(max_level.unwrap_or(usize::MAX));
-
-            let mut data_size: usize = 0;
-            for level in 0..level_count {
-                let level_size = mip_size_from_level(level0_size, level);
-                data_size += data_size_from_mip_size(level_size, N);
-            }
-
-            let mut data: Vec<u8> = Vec::new();
-            data.resize(data_size, 128);
-
-            // level 0:
-            let level0_buffer_len = src_data.len();
-            data[..level0_buffer_len].copy_from_slice(src_data);
-
-            // level 1..n:
-            let (mut prev_mip_buffer, mut data_view) = data.split_at_mut(level0_buffer_len);
-            let mut prev_level_size = level0_size;
-            for level in 1..level_count {
-                let level_size = mip_size_from_level(level0_size, level);
-                let mip_buffer_len = data_size_from_mip_size(level_size, N);
-                let (mip_buffer, after) = data_view.split_at_mut(mip_buffer_len);
-                data_view = after;
-
-                downsample_bilinear::<N>(prev_level_size, prev_mip_buffer, mip_buffer);
+        let level0_width = self.inner.width() as usize;
+        let level0_height = self.inner.height() as usize;
+        if level0_width == 0 || level0_height == 0 {
+            return Err(AssetError::Unsupported(
+                "can't generate mipmaps on empty images".to_string(),
+            ));
+        }
+        let level_count = level0_width.max(level0_height).ilog2() as usize + 1;
 
-                prev_mip_buffer = mip_buffer;
-                prev_level_size = level_size;
-            }
+        let mut data_size: usize = 0;
+        for level in 0..level_count {
+            data_size += mip_size_from_level(level0_width, level)
+                * mip_size_from_level(level0_height, level)
+                * N;
+        }
 
-            Mips {
-                level_count: level_count as u32,
-                data,
-            }
+        let mut data: Vec<u8> = Vec::new();
+        data.resize(data_size, 128);
+
+        // level 0:
+        let src_data: &[u8] = &self.inner;
+        let level0_buffer_len = src_data.len();
+        data[..level0_buffer_len].copy_from_slice(src_data);
+
+        // level 1..n:
+        let (mut prev_mip_buffer, mut data_view) = data.split_at_mut(level0_buffer_len);
+        let mut prev_level_size = (level0_width, level0_height);
+        for level in 1..level_count {
+            let level_size = (
+                mip_size_from_level(level0_width, level),
+                mip_size_from_level(level0_height, level),
+            );
+            let mip_buffer_len = level_size.0 * level_size.1 * N;
+            let (mip_buffer, after) = data_view.split_at_mut(mip_buffer_len);
+            data_view = after;
+
+            downsample_box::<N>(
+                prev_level_size,
+                prev_mip_buffer,
+                level_size,
+                mip_buffer,
+                color_space,
+                premultiply_alpha,
+            );
+
+            prev_mip_buffer = mip_buffer;
+            prev_level_size = level_size;
         }
 
-        self.mips = Some(make_mips::<4>(self.inner.width() as usize, self.data()));
+        self.mips = Some(Mips {
+            level_count: level_count as u32,
+            data,
+        });
         Ok(())
     }
 
@@ -111,6 +161,29 @@ impl Image {
             1
         }
     }
+
+    pub fn mip_level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width() >> level).max(1), (self.height() >> level).max(1))
+    }
+
+    /// Data of the mip levels from `first_level` down to the smallest one.
+    pub fn mip_levels_data(&self, first_level: u32) -> &[u8] {
+        let offset: usize = (0..first_level)
+            .map(|level| {
+                let (width, height) = self.mip_level_size(level);
+                width as usize * height as usize * 4
+            })
+            .sum();
+        &self.data()[offset..]
+    }
+}
+
+pub(crate) fn image_error_to_asset_error(e: image::ImageError) -> AssetError {
+    match e {
+        image::ImageError::IoError(e) => AssetError::Io(e),
+        image::ImageError::Unsupported(e) => AssetError::Unsupported(e.to_string()),
+        e => AssetError::Parse(e.to_string()),
+    }
 }
 
 struct Mips {
@@ -118,15 +191,21 @@ struct Mips {
     data: Vec<u8>,
 }
 
-fn downsample_bilinear<const N: usize>(src_size: usize, src: &[u8], dst: &mut [u8]) {
-    let expected_src_len = src_size * src_size * N;
-    let dst_size = src_size / 2;
-    let expected_dst_len = dst_size * dst_size * N;
-    assert_eq!(src.len(), expected_src_len);
-    assert_eq!(dst.len(), expected_dst_len);
-
-    fn get_pixel(buf: &[u8], x: usize, y: usize, size: usize) -> [u8; 4] {
-        let row_byte_count = size * 4;
+/// Each destination pixel averages the block of source pixels it covers, which is 2x2 when
+/// the size halves evenly, and up to 3x3 when an odd size gets rounded down.
+fn downsample_box<const N: usize>(
+    (src_width, src_height): (usize, usize),
+    src: &[u8],
+    (dst_width, dst_height): (usize, usize),
+    dst: &mut [u8],
+    color_space: ColorSpace,
+    premultiply_alpha: bool,
+) {
+    assert_eq!(src.len(), src_width * src_height * N);
+    assert_eq!(dst.len(), dst_width * dst_height * N);
+
+    fn get_pixel(buf: &[u8], x: usize, y: usize, width: usize) -> [u8; 4] {
+        let row_byte_count = width * 4;
         let idx = y * row_byte_count + x * 4;
         let mut result = [0u8; 4];
         for i in 0..4 {
@@ -134,59 +213,110 @@ fn downsample_bilinear<const N: usize>(src_size: usize, src: &[u8], dst: &mut [u
         }
         result
     }
-    fn set_pixel(buf: &mut [u8], x: usize, y: usize, size: usize, pixel: [u8; 4]) {
-        let row_byte_count = size * 4;
+    fn set_pixel(buf: &mut [u8], x: usize, y: usize, width: usize, pixel: [u8; 4]) {
+        let row_byte_count = width * 4;
         let idx = y * row_byte_count + x * 4;
         for i in 0..4 {
             buf[idx + i] = pixel[i];
         }
     }
 
-    const GAMMA: f32 = 2.0;
-    fn srgb_to_rgb(color: [u8; 4]) -> [f32; 4] {
-        [
-            (color[0] as f32 / 255.0).powi(GAMMA as i32),
-            (color[1] as f32 / 255.0).powi(GAMMA as i32),
-            (color[2] as f32 / 255.0).powi(GAMMA as i32),
-            color[3] as f32 / 255.0,
-        ]
+    fn linear_to_srgb(c: f32) -> f32 {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
     }
-    fn rgb_to_srgb(color: [f32; 4]) -> [u8; 4] {
-        [
-            (color[0].powf(1.0 / GAMMA) * 255.0) as u8,
-            (color[1].powf(1.0 / GAMMA) * 255.0) as u8,
-            (color[2].powf(1.0 / GAMMA) * 255.0) as u8,
-            (color[3] * 255.0) as u8,
-        ]
+    let decode = |color: [u8; 4]| -> [f32; 4] {
+        let mut rgba = color.map(|c| c as f32 / 255.0);
+        if color_space == ColorSpace::Srgb {
+            rgba[..3].iter_mut().for_each(|c| *c = srgb_to_linear(*c));
+        }
+        rgba
+    };
+    let encode = |mut rgba: [f32; 4]| -> [u8; 4] {
+        if color_space == ColorSpace::Srgb {
+            rgba[..3].iter_mut().for_each(|c| *c = linear_to_srgb(*c));
+        }
+        rgba.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    };
+    fn premultiply([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        [r * a, g * a, b * a, a]
+    }
+    fn unpremultiply([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        if a > 0.0 {
+            [r / a, g / a, b / a, a]
+        } else {
+            [r, g, b, a]
+        }
     }
 
-    for y in 0..dst_size {
-        for x in 0..dst_size {
-            let src_x1 = x * 2;
-            let src_y1 = y * 2;
-
-            let src_x2 = src_x1 + 1;
-            let src_y2 = src_y1;
-
-            let src_x3 = src_x1;
-            let src_y3 = src_y1 + 1;
-
-            let src_x4 = src_x1 + 1;
-            let src_y4 = src_y1 + 1;
-
-            let src1 = srgb_to_rgb(get_pixel(src, src_x1, src_y1, src_size));
-            let src2 = srgb_to_rgb(get_pixel(src, src_x2, src_y2, src_size));
-            let src3 = srgb_to_rgb(get_pixel(src, src_x3, src_y3, src_size));
-            let src4 = srgb_to_rgb(get_pixel(src, src_x4, src_y4, src_size));
-
-            let average = average([src1, src2, src3, src4]);
+    // Source pixels covered by a destination pixel, along one axis.
+    let covered = |dst: usize, src_size: usize, dst_size: usize| {
+        dst * src_size / dst_size..(dst + 1) * src_size / dst_size
+    };
+
+    let downsample_rows = |first_row: usize, rows: &mut [u8]| {
+        let row_count = rows.len() / (dst_width * N);
+        for y in first_row..first_row + row_count {
+            for x in 0..dst_width {
+                let mut block = [[0.0; 4]; 9];
+                let mut block_len = 0;
+                for src_y in covered(y, src_height, dst_height) {
+                    for src_x in covered(x, src_width, dst_width) {
+                        block[block_len] = decode(get_pixel(src, src_x, src_y, src_width));
+                        block_len += 1;
+                    }
+                }
+                let pixels = &mut block[..block_len];
+
+                // Weighting colors by their alpha keeps the color of fully transparent pixels,
+                // often black, from bleeding into the edges of cutouts.
+                if premultiply_alpha {
+                    pixels.iter_mut().for_each(|pixel| *pixel = premultiply(*pixel));
+                }
+                let mut average = average(pixels);
+                if premultiply_alpha {
+                    average = unpremultiply(average);
+                }
+
+                set_pixel(rows, x, y - first_row, dst_width, encode(average));
+            }
+        }
+    };
+
+    // Destination pixels are independent, so bands of rows go to their own thread. Small levels
+    // aren't worth the threads.
+    const MIN_PIXELS_PER_THREAD: usize = 64 * 64;
+    let thread_count = std::thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(dst_width * dst_height / MIN_PIXELS_PER_THREAD)
+        .max(1);
+    if thread_count == 1 {
+        downsample_rows(0, dst);
+        return;
+    }
 
-            set_pixel(dst, x, y, dst_size, rgb_to_srgb(average));
+    let rows_per_thread = dst_height.div_ceil(thread_count);
+    std::thread::scope(|scope| {
+        for (i, rows) in dst.chunks_mut(rows_per_thread * dst_width * N).enumerate() {
+            let downsample_rows = &downsample_rows;
+            scope.spawn(move || downsample_rows(i * rows_per_thread, rows));
         }
+    });
+}
+
+// https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
     }
 }
 
-fn average<const N: usize, const M: usize>(list: [[f32; N]; M]) -> [f32; N] {
+fn average<const N: usize>(list: &[[f32; N]]) -> [f32; N] {
     let mut sum = [0f32; N];
     for arr in list {
         for i in 0..N {
@@ -195,7 +325,7 @@ fn average<const N: usize, const M: usize>(list: [[f32; N]; M]) -> [f32; N] {
     }
     let mut result = [0f32; N];
     for i in 0..N {
-        result[i] = sum[i] / M as f32;
+        result[i] = sum[i] / list.len() as f32;
     }
     result
 }
@@ -1,2 +1,4 @@
+mod cubemap;
+pub use self::cubemap::Cubemap;
 mod image;
-pub use self::image::Image;
+pub use self::image::{ColorSpace, Image};
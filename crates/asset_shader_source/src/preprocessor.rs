@@ -1,9 +1,14 @@
 use std::collections::HashSet;
 
+use asset_error::AssetError;
+
+type ImportReader = Box<dyn Fn(&str) -> Result<String, AssetError>>;
+
 pub struct Preprocessor {
     pub lines: Vec<String>,
     pub defines: HashSet<String>,
     pub imports: HashSet<String>,
+    read_import: Option<ImportReader>,
 }
 
 impl Preprocessor {
@@ -13,9 +18,20 @@ impl Preprocessor {
             lines,
             defines: Default::default(),
             imports: Default::default(),
+            read_import: None,
         }
     }
 
+    /// `#import name` is replaced by the source `read_import` gives for `name`, the first time
+    /// it's imported. Imports are an error without it.
+    pub fn with_import_reader(
+        mut self,
+        read_import: impl Fn(&str) -> Result<String, AssetError> + 'static,
+    ) -> Self {
+        self.read_import = Some(Box::new(read_import));
+        self
+    }
+
     pub fn with_defines(mut self, defines: impl IntoIterator<Item = String>) -> Self {
         self.defines = defines.into_iter().collect();
         self
@@ -29,7 +45,7 @@ impl Preprocessor {
         self.defines.contains(def)
     }
 
-    pub fn preprocess(&mut self) -> Result<(), String> {
+    pub fn preprocess(&mut self) -> Result<(), AssetError> {
         let mut if_depth: i32 = 0;
 
         let mut ignore_line = false;
@@ -69,7 +85,17 @@ impl Preprocessor {
                             self.define(define);
                         }
                         Directive::Import(import) => {
-                            todo!("import {}", import);
+                            if !ignore_line && self.imports.insert(import.to_string()) {
+                                let read_import = self.read_import.as_ref().ok_or_else(|| {
+                                    AssetError::Unsupported(format!(
+                                        "can't import {import}, imports aren't read from anywhere"
+                                    ))
+                                })?;
+                                // Spliced in where the directive was and preprocessed in turn.
+                                let imported = read_import(import)?;
+                                self.lines
+                                    .splice(i..i, imported.lines().map(str::to_string));
+                            }
                         }
                     }
                 }
@@ -124,4 +150,19 @@ mod tests {
     fn stuff() {
         // TODO
     }
+
+    #[test]
+    fn imports_once() {
+        let mut pp = Preprocessor::new("#import a\n#import b\n#ifdef C\n#import c\n#endif\nmain")
+            .with_import_reader(|name| match name {
+                "a" => Ok("#import b\na".to_string()),
+                "b" => Ok("b".to_string()),
+                _ => Err(AssetError::Parse(format!("unexpected import {name}"))),
+            });
+        pp.preprocess().unwrap();
+        assert_eq!(pp.source(), "b\na\nmain");
+
+        let mut pp = Preprocessor::new("#import a");
+        assert!(pp.preprocess().is_err());
+    }
 }
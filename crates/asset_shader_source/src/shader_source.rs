@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use asset_error::AssetError;
+
 use crate::Preprocessor;
 
 pub struct ShaderSource {
@@ -11,10 +13,29 @@ impl ShaderSource {
         Self { src }
     }
 
-    pub fn load_from_path(path: impl AsRef<Path>, defines: Vec<String>) -> Result<Self, String> {
-        let src = std::fs::read_to_string(path).map_err(|e| format!("{:?}", e))?;
+    /// `#import` directives name files next to the shader's.
+    pub fn load_from_path(
+        path: impl AsRef<Path>,
+        defines: Vec<String>,
+    ) -> Result<Self, AssetError> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Self::preprocessed(&src, defines, move |import| {
+            Ok(std::fs::read_to_string(dir.join(import))?)
+        })
+    }
 
-        let mut pp = Preprocessor::new(&src).with_defines(defines);
+    /// Like [`ShaderSource::load_from_path`], for a source that isn't in a file. Its imports come
+    /// from `read_import`.
+    pub fn preprocessed(
+        src: &str,
+        defines: Vec<String>,
+        read_import: impl Fn(&str) -> Result<String, AssetError> + 'static,
+    ) -> Result<Self, AssetError> {
+        let mut pp = Preprocessor::new(src)
+            .with_defines(defines)
+            .with_import_reader(read_import);
         pp.preprocess()?;
 
         Ok(Self::new(pp.source()))
@@ -24,18 +45,22 @@ impl ShaderSource {
         &self.src
     }
 
-    pub fn validate(&self) -> Result<(), String> {
+    pub fn validate(&self) -> Result<(), AssetError> {
         match naga::front::wgsl::parse_str(self.source()) {
             Err(parse_error) => {
                 parse_error.emit_to_stderr(self.source());
-                return Err(parse_error.emit_to_string(self.source()));
+                return Err(AssetError::Parse(
+                    parse_error.emit_to_string(self.source()),
+                ));
             }
             Ok(module) => {
                 use naga::valid::*;
                 let mut validator = Validator::new(ValidationFlags::all(), Capabilities::all());
                 if let Err(validation_error) = validator.validate(&module) {
                     validation_error.emit_to_stderr(self.source());
-                    return Err(validation_error.emit_to_string(self.source()));
+                    return Err(AssetError::Validation(
+                        validation_error.emit_to_string(self.source()),
+                    ));
                 }
             }
         }
@@ -0,0 +1,156 @@
+use glam::{Affine3A, Quat, Vec3};
+
+use crate::{arena::Handle, scene::NodeId, AssetServer, Scene};
+
+/// Keyframes animating the transforms of the nodes of one scene.
+pub struct Animation {
+    pub channels: Vec<AnimationChannel>,
+    /// Time of the last keyframe, in seconds.
+    pub duration: f32,
+}
+
+impl Animation {
+    pub fn new(channels: Vec<AnimationChannel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration: f32, &time| duration.max(time));
+        Self { channels, duration }
+    }
+
+    /// Sets the animated parts of the targets' transforms to their value at `time`. Targets
+    /// that were removed from the scene are skipped.
+    fn apply(&self, time: f32, scene: &mut Scene) {
+        for channel in &self.channels {
+            if !scene.nodes.contains(channel.target) {
+                continue;
+            }
+            let transform = &mut scene.get_mut(channel.target).transform;
+            let (mut scale, mut rotation, mut translation) =
+                transform.to_scale_rotation_translation();
+            match &channel.keyframes {
+                Keyframes::Translation(values) => {
+                    translation = channel.sample(time, values, Vec3::lerp);
+                }
+                Keyframes::Rotation(values) => {
+                    rotation = channel.sample(time, values, Quat::slerp);
+                }
+                Keyframes::Scale(values) => {
+                    scale = channel.sample(time, values, Vec3::lerp);
+                }
+            }
+            *transform = Affine3A::from_scale_rotation_translation(scale, rotation, translation);
+        }
+    }
+}
+
+pub struct AnimationChannel {
+    pub target: NodeId,
+    /// In seconds, increasing. There is one per keyframe.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+}
+
+impl AnimationChannel {
+    /// Holds the first and last keyframes before and after them.
+    fn sample<T: Copy>(&self, time: f32, values: &[T], lerp: impl Fn(T, T, f32) -> T) -> T {
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return values[0];
+        }
+        if next == self.times.len() {
+            return values[next - 1];
+        }
+
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        match self.interpolation {
+            Interpolation::Step => values[next - 1],
+            Interpolation::Linear => lerp(
+                values[next - 1],
+                values[next],
+                (time - start) / (end - start),
+            ),
+        }
+    }
+}
+
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Jumps from one keyframe to the next.
+    Step,
+    Linear,
+}
+
+/// Plays one of a scene's animations on the nodes of that scene, the one the player's node is
+/// in. Scenes loaded from glTF files get one on their root with all of their animations.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    animations: Vec<(String, Handle<Animation>)>,
+    playing: Option<usize>,
+    time: f32,
+    /// Starts over once the end is reached. Otherwise, the last keyframes are held.
+    pub looping: bool,
+    /// Multiplies the time delta, on top of the engine's timescale.
+    pub speed: f32,
+}
+
+impl AnimationPlayer {
+    pub fn new(animations: Vec<(String, Handle<Animation>)>) -> Self {
+        Self {
+            animations,
+            playing: None,
+            time: 0.0,
+            looping: true,
+            speed: 1.0,
+        }
+    }
+
+    pub fn animation_names(&self) -> impl Iterator<Item = &str> {
+        self.animations.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Plays the animation from the start.
+    pub fn play(&mut self, name: &str) {
+        let Some(index) = self.animations.iter().position(|(n, _)| n == name) else {
+            eprintln!("no animation named {}", name);
+            return;
+        };
+        self.playing = Some(index);
+        self.time = 0.0;
+    }
+
+    /// Leaves the nodes as they were last animated.
+    pub fn stop(&mut self) {
+        self.playing = None;
+    }
+
+    pub fn playing(&self) -> Option<&str> {
+        self.playing.map(|index| self.animations[index].0.as_str())
+    }
+
+    /// Seconds since the playing animation started, wrapped around if looping.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// The player must not be in the scene while it animates it.
+    pub(crate) fn advance(&mut self, delta: f32, asset_server: &AssetServer, scene: &mut Scene) {
+        let Some(index) = self.playing else {
+            return;
+        };
+        let animation = asset_server.get(self.animations[index].1);
+
+        self.time += delta * self.speed;
+        if self.looping && animation.duration > 0.0 {
+            self.time = self.time.rem_euclid(animation.duration);
+        }
+        animation.apply(self.time, scene);
+    }
+}
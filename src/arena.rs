@@ -5,31 +5,68 @@ use std::{
 
 #[derive(Debug, Clone)]
 pub struct Arena<T> {
-    slots: Vec<T>,
+    slots: Vec<Slot<T>>,
+    free_slots: Vec<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    // Bumped every time the slot is freed, so that handles to what was there before don't
+    // alias what comes next.
+    generation: u32,
+    element: Option<T>,
 }
 
 impl<T> Arena<T> {
     pub fn new() -> Self {
         Self {
             slots: Vec::default(),
+            free_slots: Vec::default(),
         }
     }
 
+    /// Reuses a freed slot if there is one.
     pub fn allocate(&mut self, t: T) -> Handle<T> {
-        let id = self.slots.len() as u32;
-        self.slots.push(t);
-        Handle {
-            id,
-            _ghost: PhantomData,
+        if let Some(id) = self.free_slots.pop() {
+            let slot = &mut self.slots[id as usize];
+            slot.element = Some(t);
+            return Handle::new(id, slot.generation);
         }
+
+        let id = self.slots.len() as u32;
+        self.slots.push(Slot {
+            generation: 0,
+            element: Some(t),
+        });
+        Handle::new(id, 0)
+    }
+
+    /// Panics if the element was already freed.
+    pub fn free(&mut self, handle: Handle<T>) -> T {
+        let slot = self.slot_mut(handle);
+        let element = slot.element.take().unwrap();
+        slot.generation += 1;
+        self.free_slots.push(handle.id);
+        element
+    }
+
+    /// Whether the handle refers to an element that wasn't freed.
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.slots
+            .get(handle.id as usize)
+            .is_some_and(|slot| slot.generation == handle.generation && slot.element.is_some())
     }
 
     pub fn get(&self, handle: Handle<T>) -> &T {
-        self.slots.get(handle.id as usize).expect("bad handle")
+        let slot = self.slots.get(handle.id as usize).expect("bad handle");
+        if slot.generation != handle.generation {
+            panic!("use of {:?} after it was freed", handle);
+        }
+        slot.element.as_ref().unwrap()
     }
 
     pub fn get_mut(&mut self, handle: Handle<T>) -> &mut T {
-        self.slots.get_mut(handle.id as usize).expect("bad handle")
+        self.slot_mut(handle).element.as_mut().unwrap()
     }
 
     pub fn replace(&mut self, handle: Handle<T>, t: T) -> T {
@@ -37,11 +74,20 @@ impl<T> Arena<T> {
         std::mem::replace(dst, t)
     }
 
+    /// Freed slots are skipped.
     pub fn elements(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
-        self.slots
-            .iter()
-            .enumerate()
-            .map(|(i, el)| (Handle::new(i as u32), el))
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            let element = slot.element.as_ref()?;
+            Some((Handle::new(i as u32, slot.generation), element))
+        })
+    }
+
+    fn slot_mut(&mut self, handle: Handle<T>) -> &mut Slot<T> {
+        let slot = self.slots.get_mut(handle.id as usize).expect("bad handle");
+        if slot.generation != handle.generation {
+            panic!("use of {:?} after it was freed", handle);
+        }
+        slot
     }
 }
 
@@ -53,68 +99,75 @@ impl<T> Default for Arena<T> {
 
 pub struct Handle<T> {
     id: u32,
+    generation: u32,
     _ghost: PhantomData<*const T>,
 }
 
 impl<T> Handle<T> {
-    fn new(id: u32) -> Self {
+    fn new(id: u32, generation: u32) -> Self {
         Handle {
             id,
+            generation,
             _ghost: PhantomData,
         }
     }
 }
 
+impl<T> Handle<T> {
+    pub fn id(self) -> u32 {
+        self.id
+    }
+}
+
 impl<T: Any> Handle<T> {
     pub fn to_type_erased(self) -> TypeErasedHandle {
         TypeErasedHandle {
             id: self.id,
+            generation: self.generation,
             erased_type_id: TypeId::of::<T>(),
         }
     }
 
     pub unsafe fn transmute<U: Any>(self) -> Handle<U> {
-        Handle {
-            id: self.id,
-            _ghost: PhantomData,
-        }
+        Handle::new(self.id, self.generation)
     }
 }
 
 impl<T> std::fmt::Debug for Handle<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Handle").field("id", &self.id).finish()
+        f.debug_struct("Handle")
+            .field("id", &self.id)
+            .field("generation", &self.generation)
+            .finish()
     }
 }
 
 impl<T> Clone for Handle<T> {
     fn clone(&self) -> Self {
-        Self {
-            id: self.id,
-            _ghost: self._ghost,
-        }
+        *self
     }
 }
 impl<T> Copy for Handle<T> {}
 impl<T> PartialEq for Handle<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.id.eq(&other.id)
+        (self.id, self.generation).eq(&(other.id, other.generation))
     }
 }
 impl<T> Eq for Handle<T> {}
 impl<T> PartialOrd for Handle<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.id.partial_cmp(&other.id)
+        Some(self.cmp(other))
     }
 }
 impl<T> Ord for Handle<T> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        (self.id, self.generation).cmp(&(other.id, other.generation))
     }
 }
 impl<T> std::hash::Hash for Handle<T> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.id.hash(state)
+        self.id.hash(state);
+        self.generation.hash(state);
     }
 }
 unsafe impl<T> Send for Handle<T> {}
@@ -122,13 +175,14 @@ unsafe impl<T> Send for Handle<T> {}
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TypeErasedHandle {
     id: u32,
+    generation: u32,
     erased_type_id: TypeId,
 }
 
 impl TypeErasedHandle {
     pub fn downcast<T: Any>(self) -> Result<Handle<T>, Self> {
         if self.erased_type_id == TypeId::of::<T>() {
-            Ok(Handle::new(self.id))
+            Ok(Handle::new(self.id, self.generation))
         } else {
             Err(self)
         }
@@ -139,10 +193,7 @@ impl TypeErasedHandle {
     }
 
     pub unsafe fn transmute<T: Any>(self) -> Handle<T> {
-        Handle {
-            id: self.id,
-            _ghost: PhantomData,
-        }
+        Handle::new(self.id, self.generation)
     }
 }
 
@@ -1,14 +1,19 @@
 use std::{
     any::{Any, TypeId},
-    collections::{HashMap, HashSet, VecDeque},
-    sync::{mpsc, Mutex, RwLock},
+    collections::{HashMap, HashSet},
+    sync::mpsc,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, RwLock},
     thread,
     time::Duration,
 };
 
 use crate::{
     arena::{Arena, Handle, TypeErasedHandle},
-    Image, Material, Mesh, Scene, ShaderSource, Timestamp,
+    Animation, AssetError, Cubemap, Image, Material, Mesh, Scene, ShaderSource, Timestamp,
 };
 
 mod gltf;
@@ -20,6 +25,7 @@ pub struct AssetServer {
     metadata: HashMap<TypeErasedHandle, Metadata>,
     changes: AssetChanges,
     last_changes_check: Timestamp,
+    generated_mesh_lods: u32,
     //
     work_sender: mpsc::Sender<Work>,
     work_result_receiver: mpsc::Receiver<WorkResult>,
@@ -35,13 +41,18 @@ impl AssetServer {
     pub fn new() -> Self {
         let (work_sender, work_receiver) = mpsc::channel();
         let (work_result_sender, work_result_receiver) = mpsc::channel();
+        // The web can't spawn threads, assets are loaded synchronously there.
+        #[cfg(not(target_arch = "wasm32"))]
         let _ = Self::make_work_threads(work_receiver, work_result_sender);
+        #[cfg(target_arch = "wasm32")]
+        let _ = (work_receiver, work_result_sender);
 
         Self {
             arenas: Default::default(),
             metadata: Default::default(),
             changes: Default::default(),
             last_changes_check: Default::default(),
+            generated_mesh_lods: 0,
             //
             work_sender,
             work_result_receiver,
@@ -76,6 +87,19 @@ impl AssetServer {
         typed_handle
     }
 
+    /// Frees the asset's slot so it can be reused. Using the handle afterwards panics.
+    pub fn remove<A: Asset>(&mut self, handle: Handle<A>) -> A {
+        let generic_handle = unsafe { handle.transmute() };
+        let boxed_asset = self.get_arena_mut::<A>().free(generic_handle);
+        self.metadata.remove(&handle.to_type_erased());
+        self.changes.assets.remove(&handle.to_type_erased());
+        self.changes.removed.insert(handle.to_type_erased());
+        *boxed_asset
+            .into_any()
+            .downcast()
+            .expect("handle type and asset type should match such that this doesnt blow up")
+    }
+
     pub fn load<A: Asset + Loadable>(&mut self, path: &str) -> Handle<A> {
         self.load_with_options(path, "")
     }
@@ -99,12 +123,18 @@ impl AssetServer {
             .expect("assets without path cannot be reloaded");
         let load_options = self.asset_load_options(handle);
         let mut loader = A::new_loader(load_options);
-        if loader.only_sync() {
-            if let Ok(boxed_asset) = loader.load_from_path(path) {
-                self.set_asset(handle.to_type_erased(), boxed_asset);
-                self.finish_asset_reload(handle);
-            } else {
-                eprintln!("AssetServer::reload(): asset failed to load: {}", path);
+        if loader.only_sync() || cfg!(target_arch = "wasm32") {
+            match loader.load_from_path(path) {
+                Ok(boxed_asset) => {
+                    self.set_asset(handle.to_type_erased(), boxed_asset);
+                    self.finish_asset_reload(handle);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "AssetServer::reload(): asset failed to load: {}: {}",
+                        path, e
+                    );
+                }
             }
         } else {
             self.work_sender
@@ -118,7 +148,29 @@ impl AssetServer {
         self.set_asset_timestamp(handle, Timestamp::now());
     }
 
-    pub fn load_scene(&mut self, path: &str) -> Result<Handle<Scene>, String> {
+    /// Reloads every asset of the type that was loaded from a file, changed or not.
+    pub fn reload_all<A: Asset + Loadable>(&mut self) {
+        let handles: Vec<_> = self
+            .iter_assets::<A>()
+            .map(|(handle, _)| handle)
+            .filter(|&handle| self.asset_path(handle).is_some())
+            .collect();
+        for handle in handles {
+            self.reload(handle);
+        }
+    }
+
+    pub fn generated_mesh_lods(&self) -> u32 {
+        self.generated_mesh_lods
+    }
+
+    /// Levels of detail generated for the meshes of the scenes loaded afterwards, none by default.
+    /// See [`Mesh::generate_lods`].
+    pub fn set_generated_mesh_lods(&mut self, count: u32) {
+        self.generated_mesh_lods = count;
+    }
+
+    pub fn load_scene(&mut self, path: &str) -> Result<Handle<Scene>, AssetError> {
         gltf::GtlfLoader::new(path, self)?.load()
     }
 
@@ -128,11 +180,21 @@ impl AssetServer {
 
     pub fn update(&mut self) {
         while let Ok((handle, result)) = self.work_result_receiver.try_recv() {
+            let generic_handle = unsafe { handle.transmute() };
+            let removed = !self
+                .arenas
+                .get(&handle.erased_type_id())
+                .is_some_and(|arena| arena.contains(generic_handle));
+            if removed {
+                continue;
+            }
             let asset = result.unwrap();
             self.set_asset(handle, asset);
 
             if let Ok(handle) = handle.downcast::<Image>() {
                 self.finish_asset_reload(handle);
+            } else if let Ok(handle) = handle.downcast::<Cubemap>() {
+                self.finish_asset_reload(handle);
             }
         }
 
@@ -264,6 +326,7 @@ impl AssetServer {
         self.changes.assets.insert(handle.to_type_erased());
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     fn make_work_threads(
         work_receiver: mpsc::Receiver<Work>,
         result_sender: mpsc::Sender<WorkResult>,
@@ -334,6 +397,7 @@ impl Drop for AssetServer {
 pub trait Asset: Any + Send {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
 
 pub trait Loadable {
@@ -346,7 +410,9 @@ impl IsAsset for Scene {}
 impl IsAsset for Mesh {}
 impl IsAsset for Material {}
 impl IsAsset for Image {}
+impl IsAsset for Cubemap {}
 impl IsAsset for ShaderSource {}
+impl IsAsset for Animation {}
 
 impl<T: IsAsset + Any> Asset for T {
     fn as_any(&self) -> &dyn Any {
@@ -356,6 +422,10 @@ impl<T: IsAsset + Any> Asset for T {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
 }
 
 struct Metadata {
@@ -375,13 +445,15 @@ impl Metadata {
 }
 
 pub trait Loader: Send {
-    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, String>;
+    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, AssetError>;
 
     fn only_sync(&self) -> bool {
         false
     }
 }
 
+// Only sent to the work threads, which the web doesn't have.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
 enum Work {
     Terminate,
     LoadFromPath {
@@ -391,11 +463,13 @@ enum Work {
     },
 }
 
-type WorkResult = (TypeErasedHandle, Result<Box<dyn Asset>, String>);
+type WorkResult = (TypeErasedHandle, Result<Box<dyn Asset>, AssetError>);
 
 #[derive(Default)]
 pub struct AssetChanges {
     pub assets: HashSet<TypeErasedHandle>,
+    /// Removed from the asset server, whatever was made out of them can go too.
+    pub removed: HashSet<TypeErasedHandle>,
 }
 
 impl AssetChanges {
@@ -408,4 +482,10 @@ impl AssetChanges {
     pub fn contains<A: Asset>(&self, handle: Handle<A>) -> bool {
         self.assets.contains(&handle.to_type_erased())
     }
+
+    pub fn iter_removed<A: Asset>(&self) -> impl Iterator<Item = Handle<A>> + '_ {
+        self.removed
+            .iter()
+            .filter_map(|type_erased_handle| type_erased_handle.downcast().ok())
+    }
 }
@@ -1,20 +1,26 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     mem::size_of,
     path::{Path, PathBuf},
 };
 
-use glam::{Affine3A, Quat, Vec3};
+use glam::{Affine3A, Quat, Vec2, Vec3};
 use gltf::{
     buffer::{self, Source},
+    camera::Projection,
+    khr_lights_punctual::Kind,
     Gltf, Semantic,
 };
 
 use crate::{
-    arena::Handle, material::BillboardMode, renderer::Vertex, scene::NodeId, AssetServer, Image,
-    Material, Mesh, Node, Scene, Submesh,
+    arena::Handle, renderer::Vertex, scene::NodeId, AlphaMode, Animation, AnimationChannel,
+    AnimationPlayer, AssetError, AssetServer, Camera, Color, ColorSpace, Image, Interpolation,
+    Keyframes, Light, Material, Mesh, Node, Scene, Submesh,
 };
 
+// glTF point lights without a range reach infinitely far, which the renderer doesn't support.
+const DEFAULT_LIGHT_RANGE: f32 = 10.0;
+
 pub struct GtlfLoader<'a> {
     read: Read,
     write: Write<'a>,
@@ -32,12 +38,16 @@ struct Write<'a> {
     material_ids_map: HashMap<Option<usize>, Handle<Material>>,
     meshes_ids_map: HashMap<usize, Handle<Mesh>>,
     images_ids_map: HashMap<usize, Handle<Image>>,
+    nodes_ids_map: HashMap<usize, NodeId>,
 }
 
 impl<'a> GtlfLoader<'a> {
-    pub fn new(path: impl AsRef<Path>, asset_server: &'a mut AssetServer) -> Result<Self, String> {
+    pub fn new(
+        path: impl AsRef<Path>,
+        asset_server: &'a mut AssetServer,
+    ) -> Result<Self, AssetError> {
         let path = path.as_ref();
-        let gltf = Gltf::open(path).map_err(|e| format!("{:?}", e))?;
+        let gltf = Gltf::open(path).map_err(gltf_error_to_asset_error)?;
         let builtin_bin = gltf.blob.clone();
 
         Ok(Self {
@@ -52,25 +62,51 @@ impl<'a> GtlfLoader<'a> {
                 material_ids_map: Default::default(),
                 meshes_ids_map: Default::default(),
                 images_ids_map: Default::default(),
+                nodes_ids_map: Default::default(),
             },
         })
     }
 
-    pub fn load(&'a mut self) -> Result<Handle<Scene>, String> {
+    pub fn load(&'a mut self) -> Result<Handle<Scene>, AssetError> {
         self.write.load(&self.read)
     }
 }
 
 impl<'a> Write<'a> {
-    pub fn load(&mut self, read: &'a Read) -> Result<Handle<Scene>, String> {
+    pub fn load(&mut self, read: &'a Read) -> Result<Handle<Scene>, AssetError> {
+        // Textures holding data rather than colors get their mips averaged linearly.
+        let linear_texture_ids: HashSet<usize> = read
+            .gltf
+            .materials()
+            .flat_map(|gltf_material| {
+                let metallic_roughness = gltf_material
+                    .pbr_metallic_roughness()
+                    .metallic_roughness_texture()
+                    .map(|info| info.texture().index());
+                let normal = gltf_material
+                    .normal_texture()
+                    .map(|info| info.texture().index());
+                metallic_roughness.into_iter().chain(normal)
+            })
+            .collect();
+
         // Preallocate textures/images
         for gltf_texture in read.gltf.textures() {
             let id = gltf_texture.index();
+            let color_space = if linear_texture_ids.contains(&id) {
+                ColorSpace::Linear
+            } else {
+                ColorSpace::Srgb
+            };
             let handle = match gltf_texture.source().source() {
                 gltf::image::Source::Uri { uri, .. } => {
                     let full_path = Self::make_full_path(uri, read);
+                    let options = match color_space {
+                        ColorSpace::Srgb => "",
+                        ColorSpace::Linear => "linear",
+                    };
                     self.asset_server
-                        .load(&full_path.to_string_lossy().to_string())
+                        .load_with_options(&full_path.to_string_lossy(), options)
                 }
                 gltf::image::Source::View { view, .. } => {
                     if let Source::Uri(path) = view.buffer().source() {
@@ -78,7 +114,7 @@ impl<'a> Write<'a> {
                     }
                     let bytes = self.get_bytes_from_view(&view, read)?;
                     let mut image = Image::load_from_memory(bytes)?;
-                    let _ = image.make_mips(); // NOTE this stinks, mipmaps are made on the CPU right now and it's super slow.
+                    let _ = image.make_mips(color_space, color_space == ColorSpace::Srgb); // NOTE this stinks, mipmaps are made on the CPU right now and it's super slow.
                     self.asset_server.add(image)
                 }
             };
@@ -92,22 +128,56 @@ impl<'a> Write<'a> {
         for gltf_material in read.gltf.materials() {
             let id = gltf_material.index();
             let pbr = gltf_material.pbr_metallic_roughness();
-            let handle = self.asset_server.add(Material {
-                base_color: pbr.base_color_factor().into(),
-                base_color_image: pbr.base_color_texture().and_then(|info| {
-                    let id = info.texture().index();
-                    self.images_ids_map.get(&id).copied()
-                }),
-                billboard_mode: BillboardMode::Off,
-                unlit: false,
+            let emissive = gltf_material.emissive_factor();
+            let base_color_image = pbr.base_color_texture().and_then(|info| {
+                let id = info.texture().index();
+                self.images_ids_map.get(&id).copied()
+            });
+            let metallic_roughness_image = pbr.metallic_roughness_texture().and_then(|info| {
+                let id = info.texture().index();
+                self.images_ids_map.get(&id).copied()
             });
+            let normal_image = gltf_material.normal_texture().and_then(|info| {
+                let id = info.texture().index();
+                self.images_ids_map.get(&id).copied()
+            });
+            let emissive_image = gltf_material.emissive_texture().and_then(|info| {
+                let id = info.texture().index();
+                self.images_ids_map.get(&id).copied()
+            });
+            // Texture transform rotations aren't supported.
+            let (uv_scale, uv_offset) = pbr
+                .base_color_texture()
+                .and_then(|info| info.texture_transform())
+                .map(|transform| (transform.scale().into(), transform.offset().into()))
+                .unwrap_or((Vec2::ONE, Vec2::ZERO));
+            let alpha_mode = match gltf_material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => {
+                    AlphaMode::Mask(gltf_material.alpha_cutoff().unwrap_or(0.5))
+                }
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            };
+            let handle = self.asset_server.add(
+                Material::new()
+                    .with_base_color(pbr.base_color_factor().into())
+                    .with_base_color_image(base_color_image)
+                    .with_emissive(Color::new_rgb(emissive[0], emissive[1], emissive[2]))
+                    .with_emissive_image(emissive_image)
+                    .with_metallic_roughness(pbr.metallic_factor(), pbr.roughness_factor())
+                    .with_metallic_roughness_image(metallic_roughness_image)
+                    .with_normal_image(normal_image)
+                    .with_uv_transform(uv_scale, uv_offset)
+                    .with_alpha_mode(alpha_mode),
+            );
             self.material_ids_map.insert(id, handle);
         }
 
         // Preallocate meshes
         for gltf_mesh in read.gltf.meshes() {
             let id = gltf_mesh.index();
-            let mesh = self.gltf_mesh_to_mesh(&gltf_mesh, read)?;
+            let mut mesh = self.gltf_mesh_to_mesh(&gltf_mesh, read)?;
+            mesh.generate_lods(self.asset_server.generated_mesh_lods());
             let handle = self.asset_server.add(mesh);
             self.meshes_ids_map.insert(id, handle);
         }
@@ -120,13 +190,27 @@ impl<'a> Write<'a> {
                 self.load_node_recursive(gltf_node, scene.root, &mut scene);
             }
 
+            // Animations target the nodes just loaded.
+            let mut animations = Vec::new();
+            for gltf_animation in read.gltf.animations() {
+                let animation = self.gltf_animation_to_animation(&gltf_animation, read)?;
+                let name = gltf_animation
+                    .name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| gltf_animation.index().to_string());
+                animations.push((name, self.asset_server.add(animation)));
+            }
+            if !animations.is_empty() {
+                scene.get_mut(scene.root).animation_player = Some(AnimationPlayer::new(animations));
+            }
+
             let scene_handle = self.asset_server.add(Scene::new_empty());
             scene.handle = Some(scene_handle);
             *self.asset_server.get_mut(scene_handle) = scene;
             return Ok(scene_handle);
         }
 
-        Err("no scene in file".to_string())
+        Err(AssetError::Validation("no scene in file".to_string()))
     }
 
     fn load_node_recursive(
@@ -143,8 +227,23 @@ impl<'a> Write<'a> {
         };
 
         node.transform = Self::gltf_transform_to_transform(gltf_node.transform());
+        node.name = gltf_node.name().map(str::to_string);
 
         let node_id = scene.add_child(parent, node);
+        self.nodes_ids_map.insert(gltf_node.index(), node_id);
+
+        // glTF lights and cameras face their node's -Z, the renderer's face +Z.
+        let facing_z = Affine3A::from_rotation_y(std::f32::consts::PI);
+        if let Some(gltf_light) = gltf_node.light() {
+            let light_node =
+                Node::new_light(Self::gltf_light_to_light(&gltf_light)).with_transform(facing_z);
+            scene.add_child(node_id, light_node);
+        }
+        if let Some(gltf_camera) = gltf_node.camera() {
+            let camera_node = Node::new_camera(Self::gltf_camera_to_camera(&gltf_camera))
+                .with_transform(facing_z);
+            scene.add_child(node_id, camera_node);
+        }
 
         // Handle node's children
         for gltf_child in gltf_node.children() {
@@ -152,6 +251,128 @@ impl<'a> Write<'a> {
         }
     }
 
+    fn gltf_light_to_light(gltf_light: &gltf::khr_lights_punctual::Light) -> Light {
+        let [r, g, b] = gltf_light.color();
+        let color = Color::new(r, g, b, gltf_light.intensity());
+        let light = match gltf_light.kind() {
+            Kind::Directional => Light::directional(),
+            Kind::Point => Light::point(gltf_light.range().unwrap_or(DEFAULT_LIGHT_RANGE)),
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => Light::spot(
+                gltf_light.range().unwrap_or(DEFAULT_LIGHT_RANGE),
+                inner_cone_angle,
+                outer_cone_angle,
+            ),
+        };
+        light.with_color(color)
+    }
+
+    /// Imported cameras are inactive, so that they don't take over the current camera.
+    fn gltf_camera_to_camera(gltf_camera: &gltf::Camera) -> Camera {
+        let default = Camera::default();
+        let (projection, near, far) = match gltf_camera.projection() {
+            Projection::Perspective(perspective) => (
+                crate::Projection::Perspective {
+                    vfov: perspective.yfov(),
+                },
+                perspective.znear(),
+                perspective.zfar().unwrap_or(default.far),
+            ),
+            // The aspect ratio follows the window, so only the height is kept.
+            Projection::Orthographic(orthographic) => (
+                crate::Projection::Orthographic {
+                    height: orthographic.ymag() * 2.0,
+                },
+                orthographic.znear(),
+                orthographic.zfar(),
+            ),
+        };
+        Camera {
+            projection,
+            near,
+            far,
+            active: false,
+            ..default
+        }
+    }
+
+    /// Only node transforms are animated, morph target weights are skipped. Cubic spline
+    /// keyframes are interpolated linearly, ignoring their tangents.
+    fn gltf_animation_to_animation(
+        &mut self,
+        gltf_animation: &gltf::Animation<'a>,
+        read: &'a Read,
+    ) -> Result<Animation, AssetError> {
+        let mut channels = Vec::new();
+        for gltf_channel in gltf_animation.channels() {
+            let Some(&target) = self
+                .nodes_ids_map
+                .get(&gltf_channel.target().node().index())
+            else {
+                continue;
+            };
+            let sampler = gltf_channel.sampler();
+            let (interpolation, stride, offset) = match sampler.interpolation() {
+                gltf::animation::Interpolation::Step => (Interpolation::Step, 1, 0),
+                gltf::animation::Interpolation::Linear => (Interpolation::Linear, 1, 0),
+                // Each keyframe has an in tangent, a value and an out tangent.
+                gltf::animation::Interpolation::CubicSpline => (Interpolation::Linear, 3, 1),
+            };
+
+            let (input, output) = (sampler.input(), sampler.output());
+            self.load_attribute_bin(&input, read)?;
+            self.load_attribute_bin(&output, read)?;
+            let input_bytes = self.get_attribute_bytes(&input, read)?;
+            let output_bytes = self.get_attribute_bytes(&output, read)?;
+            let times: Vec<f32> = (0..input.count())
+                .map(|i| input_bytes.read_f32(i, 0))
+                .collect();
+            let values = |components: usize| {
+                (0..times.len())
+                    .map(|i| {
+                        let mut value = [0.0; 4];
+                        for (j, component) in value.iter_mut().take(components).enumerate() {
+                            *component = output_bytes.read_f32(i * stride + offset, j);
+                        }
+                        value
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            // Converted from glTF's right handed coordinates like node transforms.
+            let keyframes = match gltf_channel.target().property() {
+                gltf::animation::Property::Translation => Keyframes::Translation(
+                    values(3)
+                        .into_iter()
+                        .map(|[x, y, z, _]| Vec3::new(-x, y, z))
+                        .collect(),
+                ),
+                gltf::animation::Property::Rotation => Keyframes::Rotation(
+                    values(4)
+                        .into_iter()
+                        .map(|[x, y, z, w]| Quat::from_xyzw(x, -y, -z, w))
+                        .collect(),
+                ),
+                gltf::animation::Property::Scale => Keyframes::Scale(
+                    values(3)
+                        .into_iter()
+                        .map(|[x, y, z, _]| Vec3::new(x, y, z))
+                        .collect(),
+                ),
+                gltf::animation::Property::MorphTargetWeights => continue,
+            };
+            channels.push(AnimationChannel {
+                target,
+                times,
+                keyframes,
+                interpolation,
+            });
+        }
+        Ok(Animation::new(channels))
+    }
+
     fn gltf_transform_to_transform(transform: gltf::scene::Transform) -> Affine3A {
         // Note: account for GLTF's right handed coords -> renderer's left handed coords conversion
         let (t, r, s) = transform.decomposed();
@@ -163,9 +384,9 @@ impl<'a> Write<'a> {
 
     fn gltf_mesh_to_mesh<'b>(
         &mut self,
-        gltf_mesh: &'b gltf::Mesh,
+        gltf_mesh: &'b gltf::Mesh<'a>,
         read: &'a Read,
-    ) -> Result<Mesh, String>
+    ) -> Result<Mesh, AssetError>
     where
         'a: 'b,
     {
@@ -189,7 +410,7 @@ impl<'a> Write<'a> {
                         None
                     }
                 })
-                .ok_or_else(|| "missing positions attribute".to_string())?;
+                .ok_or_else(|| AssetError::Validation("missing positions attribute".to_string()))?;
 
             assert!(positions_accessor.data_type() == gltf::accessor::DataType::F32);
             assert!(positions_accessor.view().is_some());
@@ -204,69 +425,91 @@ impl<'a> Write<'a> {
                 self.load_external_bin(path, read)?;
             }
 
-            // ### normal attribute
-            let normals_accessor = gltf_primitive
-                .attributes()
-                .find_map(|(sem, accessor)| {
-                    if sem == Semantic::Normals {
-                        Some(accessor)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| "missing normals attribute".to_string())?;
-
-            assert!(normals_accessor.data_type() == gltf::accessor::DataType::F32);
-            assert!(normals_accessor.view().is_some());
-            let normals_stride = normals_accessor
-                .view()
-                .unwrap()
-                .stride()
-                .unwrap_or(normals_accessor.size());
+            // ### normal attribute, optional
+            let normals_accessor = gltf_primitive.attributes().find_map(|(sem, accessor)| {
+                if sem == Semantic::Normals {
+                    Some(accessor)
+                } else {
+                    None
+                }
+            });
+            if let Some(accessor) = &normals_accessor {
+                self.load_attribute_bin(accessor, read)?;
+            }
 
-            let normals_view = normals_accessor.view().unwrap();
-            if let buffer::Source::Uri(path) = normals_view.buffer().source() {
-                self.load_external_bin(path, read)?;
+            // ### uv attribute, optional
+            let uvs_accessor = gltf_primitive.attributes().find_map(|(sem, accessor)| {
+                if sem == Semantic::TexCoords(0) {
+                    Some(accessor)
+                } else {
+                    None
+                }
+            });
+            if let Some(accessor) = &uvs_accessor {
+                self.load_attribute_bin(accessor, read)?;
             }
 
-            // ### uv attribute
-            let uvs_accessor = gltf_primitive
-                .attributes()
-                .find_map(|(sem, accessor)| {
-                    if sem == Semantic::TexCoords(0) {
-                        Some(accessor)
-                    } else {
-                        None
-                    }
-                })
-                .ok_or_else(|| "missing uvs attribute".to_string())?;
+            // ### second uv attribute, optional
+            let uvs2_accessor = gltf_primitive.attributes().find_map(|(sem, accessor)| {
+                if sem == Semantic::TexCoords(1) {
+                    Some(accessor)
+                } else {
+                    None
+                }
+            });
+            if let Some(accessor) = &uvs2_accessor {
+                self.load_attribute_bin(accessor, read)?;
+            }
 
-            assert!(uvs_accessor.data_type() == gltf::accessor::DataType::F32);
-            assert!(uvs_accessor.view().is_some());
-            let uvs_stride = uvs_accessor
-                .view()
-                .unwrap()
-                .stride()
-                .unwrap_or(uvs_accessor.size());
+            // ### color attribute, optional
+            let colors_accessor = gltf_primitive.attributes().find_map(|(sem, accessor)| {
+                if sem == Semantic::Colors(0) {
+                    Some(accessor)
+                } else {
+                    None
+                }
+            });
+            if let Some(accessor) = &colors_accessor {
+                self.load_attribute_bin(accessor, read)?;
+            }
 
-            let uvs_view = uvs_accessor.view().unwrap();
-            if let buffer::Source::Uri(path) = uvs_view.buffer().source() {
-                self.load_external_bin(path, read)?;
+            // ### tangent attribute, optional
+            let tangents_accessor = gltf_primitive.attributes().find_map(|(sem, accessor)| {
+                if sem == Semantic::Tangents {
+                    Some(accessor)
+                } else {
+                    None
+                }
+            });
+            if let Some(accessor) = &tangents_accessor {
+                self.load_attribute_bin(accessor, read)?;
             }
+            let normals = match &normals_accessor {
+                Some(accessor) => Some(self.get_attribute_bytes(accessor, read)?),
+                None => None,
+            };
+            let uvs = match &uvs_accessor {
+                Some(accessor) => Some(self.get_attribute_bytes(accessor, read)?),
+                None => None,
+            };
+            let uvs2 = match &uvs2_accessor {
+                Some(accessor) => Some(self.get_attribute_bytes(accessor, read)?),
+                None => None,
+            };
+            let colors = match &colors_accessor {
+                Some(accessor) => Some(self.get_attribute_bytes(accessor, read)?),
+                None => None,
+            };
+            let tangents = match &tangents_accessor {
+                Some(accessor) => Some(self.get_attribute_bytes(accessor, read)?),
+                None => None,
+            };
 
             let positions_bin =
                 self.get_bin_from_buffer_source(positions_view.buffer().source(), read)?;
             let positions_bytes = &positions_bin
                 [positions_view.offset()..positions_view.offset() + positions_view.length()];
 
-            let normals_bin =
-                self.get_bin_from_buffer_source(normals_view.buffer().source(), read)?;
-            let normals_bytes =
-                &normals_bin[normals_view.offset()..normals_view.offset() + normals_view.length()];
-
-            let uvs_bin = self.get_bin_from_buffer_source(uvs_view.buffer().source(), read)?;
-            let uvs_bytes = &uvs_bin[uvs_view.offset()..uvs_view.offset() + uvs_view.length()];
-
             let mut vertices = Vec::new();
             for i in 0..positions_accessor.count() {
                 let position_idx = i * positions_stride + positions_accessor.offset();
@@ -280,45 +523,63 @@ impl<'a> Write<'a> {
                     ];
                     f32::from_le_bytes(coord_bytes)
                 };
-                let normal_idx = i * normals_stride + normals_accessor.offset();
-                let read_n_coord = |j: usize| {
-                    let coord_idx = normal_idx + j * size_of::<f32>();
-                    let coord_bytes = [
-                        normals_bytes[coord_idx],
-                        normals_bytes[coord_idx + 1],
-                        normals_bytes[coord_idx + 2],
-                        normals_bytes[coord_idx + 3],
-                    ];
-                    f32::from_le_bytes(coord_bytes)
-                };
-                let uv_idx = i * uvs_stride + uvs_accessor.offset();
-                let read_uv_coord = |j: usize| {
-                    let coord_idx = uv_idx + j * size_of::<f32>();
-                    let coord_bytes = [
-                        uvs_bytes[coord_idx],
-                        uvs_bytes[coord_idx + 1],
-                        uvs_bytes[coord_idx + 2],
-                        uvs_bytes[coord_idx + 3],
-                    ];
-                    f32::from_le_bytes(coord_bytes)
-                };
 
                 // Note: X coordinate is negated to convert from GLTF's right handed coordinate system to our left handed one.
                 let position = [-read_pos_coord(0), read_pos_coord(1), read_pos_coord(2)];
-                let normal = [-read_n_coord(0), read_n_coord(1), read_n_coord(2)];
-                let uv = [read_uv_coord(0), read_uv_coord(1)];
+                let normal = normals
+                    .map(|normals| {
+                        [
+                            -normals.read_f32(i, 0),
+                            normals.read_f32(i, 1),
+                            normals.read_f32(i, 2),
+                        ]
+                    })
+                    .unwrap_or_default();
+                let uv = uvs
+                    .map(|uvs| [uvs.read_f32(i, 0), uvs.read_f32(i, 1)])
+                    .unwrap_or_default();
+                // Mirroring X also flips the handedness.
+                let tangent = tangents
+                    .map(|tangents| {
+                        [
+                            -tangents.read_f32(i, 0),
+                            tangents.read_f32(i, 1),
+                            tangents.read_f32(i, 2),
+                            -tangents.read_f32(i, 3),
+                        ]
+                    })
+                    .unwrap_or_default();
+
+                let uv2 = uvs2
+                    .map(|uvs2| [uvs2.read_f32(i, 0), uvs2.read_f32(i, 1)])
+                    .unwrap_or_default();
+                // Colors without alpha are opaque.
+                let color = colors
+                    .map(|colors| {
+                        [0, 1, 2, 3].map(|j| {
+                            if j < colors.components {
+                                colors.read_f32(i, j)
+                            } else {
+                                1.0
+                            }
+                        })
+                    })
+                    .unwrap_or(Color::WHITE.to_array());
 
                 vertices.push(Vertex {
                     position,
                     normal,
                     uv,
+                    tangent,
+                    uv2,
+                    color,
                 });
             }
 
             // ## Get indices data
             let indices_accessor = gltf_primitive
                 .indices()
-                .ok_or_else(|| "missing primitve indices".to_string())?;
+                .ok_or_else(|| AssetError::Validation("missing primitve indices".to_string()))?;
             let indices_view = indices_accessor.view().unwrap();
             if let buffer::Source::Uri(path) = indices_view.buffer().source() {
                 self.load_external_bin(path, read)?;
@@ -341,25 +602,84 @@ impl<'a> Write<'a> {
                     .chunks_exact(4)
                     .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
                     .collect::<Vec<u32>>(),
-                t => return Err(format!("unsuported index type: {:?}", t)),
+                t => {
+                    return Err(AssetError::Unsupported(format!(
+                        "unsuported index type: {:?}",
+                        t
+                    )))
+                }
             };
 
-            let submesh = Submesh {
+            let mut submesh = Submesh {
                 vertices,
                 indices,
                 material: Some(material),
+                custom_attributes: Vec::new(),
             };
+            // Positions are already in our coordinate system, so are the generated normals.
+            if normals_accessor.is_none() {
+                submesh.generate_normals();
+            }
+            // Without texture coordinates, there's nothing to orient tangents with.
+            if tangents_accessor.is_none() && uvs_accessor.is_some() {
+                submesh.generate_tangents();
+            }
             submeshes.push(submesh);
         }
 
-        Ok(Mesh { submeshes })
+        Ok(Mesh {
+            submeshes,
+            lods: Vec::new(),
+        })
+    }
+
+    /// Makes sure the buffer of a float or normalized integer vector attribute is loaded.
+    fn load_attribute_bin(
+        &mut self,
+        accessor: &gltf::Accessor<'a>,
+        read: &'a Read,
+    ) -> Result<(), AssetError> {
+        let normalized_integer = accessor.normalized()
+            && matches!(
+                accessor.data_type(),
+                gltf::accessor::DataType::U8 | gltf::accessor::DataType::U16
+            );
+        if accessor.data_type() != gltf::accessor::DataType::F32 && !normalized_integer {
+            return Err(AssetError::Unsupported(format!(
+                "unsupported attribute type: {:?}",
+                accessor.data_type()
+            )));
+        }
+        let view = accessor
+            .view()
+            .ok_or_else(|| AssetError::Unsupported("sparse attributes".to_string()))?;
+        if let buffer::Source::Uri(path) = view.buffer().source() {
+            self.load_external_bin(path, read)?;
+        }
+        Ok(())
+    }
+
+    /// Call load_attribute_bin before to make sure the attribute's buffer is loaded.
+    fn get_attribute_bytes(
+        &self,
+        accessor: &gltf::Accessor<'a>,
+        read: &'a Read,
+    ) -> Result<AttributeBytes<'_>, AssetError> {
+        let view = accessor.view().unwrap();
+        Ok(AttributeBytes {
+            bytes: self.get_bytes_from_view(&view, read)?,
+            stride: view.stride().unwrap_or(accessor.size()),
+            offset: accessor.offset(),
+            data_type: accessor.data_type(),
+            components: accessor.dimensions().multiplicity(),
+        })
     }
 
     fn get_bytes_from_view(
         &self,
         view: &buffer::View<'a>,
         read: &'a Read,
-    ) -> Result<&[u8], String> {
+    ) -> Result<&[u8], AssetError> {
         let bin = self.get_bin_from_buffer_source(view.buffer().source(), read)?;
         let bytes = &bin[view.offset()..view.offset() + view.length()];
         Ok(bytes)
@@ -369,13 +689,11 @@ impl<'a> Write<'a> {
         &self,
         source: buffer::Source<'a>,
         read: &'a Read,
-    ) -> Result<&[u8], String> {
+    ) -> Result<&[u8], AssetError> {
         match source {
-            Source::Bin => read
-                .builtin_bin
-                .as_ref()
-                .map(|v| &v[..])
-                .ok_or("expected builtin bin but it's missing".to_string()),
+            Source::Bin => read.builtin_bin.as_ref().map(|v| &v[..]).ok_or_else(|| {
+                AssetError::Validation("expected builtin bin but it's missing".to_string())
+            }),
             Source::Uri(path) => {
                 let full_path = Self::make_full_path(path, read);
                 Ok(self
@@ -386,11 +704,12 @@ impl<'a> Write<'a> {
         }
     }
 
-    fn load_external_bin(&mut self, path: &str, read: &'a Read) -> Result<&[u8], String> {
+    fn load_external_bin(&mut self, path: &str, read: &'a Read) -> Result<&[u8], AssetError> {
         let full_path = Self::make_full_path(path, read);
 
         if !self.external_bins.contains_key(&full_path) {
-            let bin = std::fs::read(&full_path).map_err(|e| format!("{:?}: {:?}", e, full_path))?;
+            let bin = std::fs::read(&full_path)
+                .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {:?}", e, full_path)))?;
             self.external_bins.insert(full_path.clone(), bin);
         }
         Ok(self.external_bins.get(&full_path).unwrap())
@@ -404,3 +723,38 @@ impl<'a> Write<'a> {
         full_path
     }
 }
+
+#[derive(Clone, Copy)]
+struct AttributeBytes<'a> {
+    bytes: &'a [u8],
+    stride: usize,
+    offset: usize,
+    data_type: gltf::accessor::DataType,
+    components: usize,
+}
+
+impl AttributeBytes<'_> {
+    /// Normalized integers are mapped to 0..1.
+    fn read_f32(&self, element: usize, component: usize) -> f32 {
+        let start = element * self.stride + self.offset + component * self.data_type.size();
+        let bytes = &self.bytes[start..start + self.data_type.size()];
+        match self.data_type {
+            gltf::accessor::DataType::U8 => bytes[0] as f32 / u8::MAX as f32,
+            gltf::accessor::DataType::U16 => {
+                u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / u16::MAX as f32
+            }
+            _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+fn gltf_error_to_asset_error(e: gltf::Error) -> AssetError {
+    match e {
+        gltf::Error::Io(e) => AssetError::Io(e),
+        gltf::Error::Validation(_) => AssetError::Validation(e.to_string()),
+        gltf::Error::UnsupportedImageEncoding | gltf::Error::UnsupportedScheme => {
+            AssetError::Unsupported(e.to_string())
+        }
+        e => AssetError::Parse(e.to_string()),
+    }
+}
@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use asset_image::Image;
 use glam::{Affine3A, Mat3A, Quat, UVec2, Vec2, Vec3, Vec3A};
-use renderer::{Color, Engine, Light, Node, ToneMapping};
+use renderer::{
+    Camera, Color, Engine, GamepadAxis, GamepadButton, Light, Node, NodeData, PauseMode,
+    PresentMode, Projection, ToneMapping, UpscaleFilter,
+};
+use wgpu::{FilterMode, TextureFormat};
 use winit::{
     dpi::PhysicalSize,
     event::{DeviceEvent, ElementState, Event, KeyEvent, MouseButton, WindowEvent},
@@ -11,6 +15,10 @@ use winit::{
     window::WindowBuilder,
 };
 
+// The day one is what the visual server starts with.
+const AMBIENT_LIGHT_DAY: Color = Color::new(0.3, 0.5, 0.9, 0.04);
+const AMBIENT_LIGHT_NIGHT: Color = Color::new(0.2, 0.25, 0.6, 0.005);
+
 fn main() {
     let event_loop = EventLoop::new().unwrap();
     let window = Arc::new(
@@ -25,7 +33,7 @@ fn main() {
     // Load font
     let font_handle = eng.asset_server.load::<Image>("data/sdffont.png");
     eng.visual_server
-        .set_font_image(font_handle, &eng.asset_server);
+        .set_font_image(font_handle, Default::default(), &eng.asset_server);
 
     // Load scene
     let scene = eng
@@ -39,6 +47,7 @@ fn main() {
         // .load_scene("data/scenes/checker-world.glb")
         .unwrap();
     eng.scene = eng.asset_server.get(scene).clone();
+    let imported_cameras: Vec<_> = eng.scene.cameras().collect();
 
     // Make ui
     make_ui(&mut eng.scene);
@@ -49,14 +58,14 @@ fn main() {
         .load_scene("data/scenes/flight/FlightHelmet.gltf")
         .unwrap();
     let helmet_scene = eng.asset_server.get(helmet).clone();
-    eng.scene.add_child(
+    let helmet = eng.scene.add_child(
         eng.scene.root,
         Node::new_scene(helmet_scene)
             .with_transform(Affine3A::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
     );
 
     // Setup first person camera
-    eng.scene.add_child(
+    let camera = eng.scene.add_child(
         eng.scene.root,
         Node::new_camera(Default::default())
             .with_transform(
@@ -64,14 +73,20 @@ fn main() {
                     * Affine3A::from_rotation_y(-0.8),
             )
             .with_update(|this, ctx| {
-                // Mouse look
+                // Mouse and right stick look
                 let look_speed = Vec2::new(6.0, 6.0);
-                let delta_yaw = ctx.input.delta_view.x * look_speed.x;
+                let stick_look = Vec2::new(
+                    ctx.input.gamepad_axis(GamepadAxis::RightStickX),
+                    -ctx.input.gamepad_axis(GamepadAxis::RightStickY),
+                ) * 0.4
+                    * ctx.time.delta;
+                let delta_view = ctx.input.delta_view + stick_look;
+                let delta_yaw = delta_view.x * look_speed.x;
                 this.transform.matrix3 = Mat3A::from_rotation_y(delta_yaw) * this.transform.matrix3;
 
                 let (_, rot, _) = this.transform.to_scale_rotation_translation();
                 let (_, cur_pitch, _) = rot.to_euler(glam::EulerRot::YXZ);
-                let delta_pitch = ctx.input.delta_view.y * look_speed.y;
+                let delta_pitch = delta_view.y * look_speed.y;
                 let target_pitch = cur_pitch + delta_pitch;
                 let correct_pitch = target_pitch.clamp(-1.55, 1.55);
                 let correct_delta_pitch = correct_pitch - cur_pitch;
@@ -81,16 +96,58 @@ fn main() {
                 ));
                 this.transform.matrix3 = pitch_rot * this.transform.matrix3;
 
-                // WASD move
-                let speed = if ctx.input.fast { 5.0 } else { 1.5 };
-                let linvel = ctx.input.movement * speed * ctx.time.delta;
+                // WASD and left stick move
+                let fast = ctx.input.fast || ctx.input.is_gamepad_pressed(GamepadButton::LeftStick);
+                let speed = if fast { 5.0 } else { 1.5 };
+                let stick_movement = Vec3::new(
+                    ctx.input.gamepad_axis(GamepadAxis::LeftStickX),
+                    0.0,
+                    ctx.input.gamepad_axis(GamepadAxis::LeftStickY),
+                );
+                let linvel = (ctx.input.movement + stick_movement) * speed * ctx.time.delta;
                 let movement = this.transform.matrix3 * linvel;
                 this.transform.translation += Vec3A::from(movement);
+
+                // Scroll zoom
+                if let NodeData::Camera(Camera {
+                    projection: Projection::Perspective { vfov },
+                    ..
+                }) = &mut this.data
+                {
+                    *vfov = (*vfov * 0.9f32.powf(ctx.input.scroll_delta.y)).clamp(0.1, 2.5);
+                }
             }),
     );
 
+    // Fixed cameras, press C to cycle through them
+    let overview_camera = eng.scene.add_child(
+        eng.scene.root,
+        Node::new_camera(Default::default()).with_transform(
+            Affine3A::look_to_lh(
+                Vec3::new(-10.0, 8.0, 0.0),
+                Vec3::new(1.0, -0.6, 0.0),
+                Vec3::Y,
+            )
+            .inverse(),
+        ),
+    );
+    let helmet_camera = eng.scene.add_child(
+        eng.scene.root,
+        Node::new_camera(Default::default()).with_transform(
+            Affine3A::look_to_lh(Vec3::new(0.0, 0.5, -1.5), Vec3::new(0.0, 0.0, 1.0), Vec3::Y)
+                .inverse(),
+        ),
+    );
+    // Cameras that came with the scene come after ours.
+    let cameras: Vec<_> = [camera, overview_camera, helmet_camera]
+        .into_iter()
+        .chain(imported_cameras)
+        .map(|id| eng.scene.make_unique_node_id(id))
+        .collect();
+    eng.set_active_camera(Some(cameras[0]));
+
     // Lights
-    let dirlight = eng.scene.add_child(
+    let dirlight_node = eng.scene.add_child(
         eng.scene.root,
         Node::new_light(Light::directional().with_color(Color::new(1.0, 0.9, 0.8, 3.5)))
             .with_transform(
@@ -106,14 +163,43 @@ fn main() {
                 node.transform = Affine3A::from_rotation_y(angle) * node.transform;
             }),
     );
-    let dirlight = eng.scene.make_unique_node_id(dirlight);
+    let dirlight = eng.scene.make_unique_node_id(dirlight_node);
 
-    // = Point light =
+    // = Point light, press T to toggle it =
     eng.scene.add_child(
         eng.scene.root,
-        Node::new_light(Light::point(4.0).with_color(Color::new(1.0, 0.01, 0.005, 2.0)))
-            .with_transform(Affine3A::from_translation(Vec3::new(0.0, 1.0, 1.0))),
+        Node::new_light(
+            Light::point(4.0)
+                .with_color(Color::new(1.0, 0.01, 0.005, 2.0))
+                .with_casts_shadows(false),
+        )
+        .with_transform(Affine3A::from_translation(Vec3::new(0.0, 1.0, 1.0)))
+        .with_update(|node, ctx| {
+            if let NodeData::Light(light) = &mut node.data {
+                if ctx.input.is_just_pressed(KeyCode::KeyT) {
+                    light.enabled = !light.enabled;
+                }
+            }
+        }),
+    );
+
+    //= Other scenes, press 1, 2 and 3 to switch between them and sponza =
+    let helmet_viewer = make_viewer_scene(
+        &mut eng,
+        "data/scenes/flight/FlightHelmet.gltf",
+        Vec3::new(0.0, 0.3, -1.2),
+    );
+    let suzanne_viewer = make_viewer_scene(
+        &mut eng,
+        "data/scenes/suzanne/suzanne.gltf",
+        Vec3::new(0.0, 0.0, -4.0),
     );
+    let scene_keys = [
+        (KeyCode::Digit1, eng.active_scene()),
+        (KeyCode::Digit2, eng.add_scene(helmet_viewer)),
+        (KeyCode::Digit3, eng.add_scene(suzanne_viewer)),
+    ];
+    let sponza = eng.active_scene();
 
     event_loop
         .run(move |event, elwt| {
@@ -184,6 +270,61 @@ fn main() {
                                 eng.visual_server.set_render_size_factor(0.25);
                             }
 
+                            if let Some(&(_, index)) =
+                                scene_keys.iter().find(|(key, _)| key == keycode)
+                            {
+                                if let Err(e) = eng.switch_scene(index) {
+                                    eprintln!("{}", e);
+                                } else if index == sponza {
+                                    eng.set_active_camera(Some(cameras[0]));
+                                }
+                            }
+
+                            // The nodes below are sponza's.
+                            if eng.active_scene() != sponza {
+                                return;
+                            }
+
+                            if *keycode == KeyCode::KeyB {
+                                // Toggle a close focus depth of field
+                                if let NodeData::Camera(camera) =
+                                    &mut eng.scene.get_mut(camera).data
+                                {
+                                    camera.focus_distance = 1.5;
+                                    camera.aperture = if camera.aperture > 0.0 { 0.0 } else { 1.0 };
+                                }
+                            }
+
+                            if *keycode == KeyCode::KeyL {
+                                let node = eng.scene.get_mut(dirlight_node);
+                                node.pause = match node.pause {
+                                    PauseMode::Off => PauseMode::Node,
+                                    _ => PauseMode::Off,
+                                };
+                            }
+
+                            if *keycode == KeyCode::KeyC {
+                                let current = cameras
+                                    .iter()
+                                    .position(|&c| Some(c) == eng.active_camera())
+                                    .unwrap_or(0);
+                                let next = cameras[(current + 1) % cameras.len()];
+                                eng.set_active_camera(Some(next));
+                            }
+
+                            if *keycode == KeyCode::KeyG {
+                                let render_root = match eng.render_root() {
+                                    Some(_) => None,
+                                    None => Some(helmet),
+                                };
+                                eng.set_render_root(render_root);
+                            }
+
+                            if *keycode == KeyCode::KeyV {
+                                let clean_render = !eng.visual_server.clean_render();
+                                eng.visual_server.set_clean_render(clean_render);
+                            }
+
                             if *keycode == KeyCode::KeyH {
                                 eng.visual_server.unset_fullscreen_texture();
                             } else if *keycode == KeyCode::KeyJ {
@@ -194,6 +335,9 @@ fn main() {
                             }
                         }
                     }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        eng.input.add_scroll(*delta);
+                    }
                     WindowEvent::CursorMoved { position, .. } => {
                         let pointer_pos = Vec2::new(position.x as f32, position.y as f32);
                         eng.input.pointer_pos = pointer_pos;
@@ -259,6 +403,26 @@ fn physical_size_to_uvec2(size: PhysicalSize<u32>) -> UVec2 {
     UVec2::new(size.width, size.height)
 }
 
+/// Loads a scene with a camera at `camera_position` looking at the origin, and a sun.
+fn make_viewer_scene(eng: &mut Engine, path: &str, camera_position: Vec3) -> renderer::Scene {
+    let handle = eng.asset_server.load_scene(path).unwrap();
+    let mut scene = eng.asset_server.get(handle).clone();
+    scene.add_child(
+        scene.root,
+        Node::new_camera(Default::default()).with_transform(
+            Affine3A::look_to_lh(camera_position, -camera_position, Vec3::Y).inverse(),
+        ),
+    );
+    scene.add_child(
+        scene.root,
+        Node::new_light(Light::directional().with_color(Color::new(1.0, 0.9, 0.8, 3.5)))
+            .with_transform(
+                Affine3A::look_to_lh(Vec3::ZERO, Vec3::new(0.3, -1.0, 0.4), Vec3::Y).inverse(),
+            ),
+    );
+    scene
+}
+
 fn make_ui(scene: &mut renderer::Scene) {
     use renderer::ui::helpers::*;
     use renderer::ui::*;
@@ -281,28 +445,226 @@ fn make_ui(scene: &mut renderer::Scene) {
             if ctx.input.is_just_pressed(KeyCode::Tab) {
                 let uibox = node.as_uibox_mut().unwrap();
                 uibox.hide = !uibox.hide;
+                uibox.layout_dirty = true;
+            }
+            if ctx.input.is_just_pressed(KeyCode::F5) {
+                ctx.visual_server.reload_shaders(ctx.asset_server);
+            }
+            if ctx.input.is_just_pressed(KeyCode::F12) {
+                match ctx.visual_server.save_screenshot("screenshot.png") {
+                    Ok(()) => println!("saved screenshot.png"),
+                    Err(e) => eprintln!("{}", e),
+                }
             }
         }),
         |b| {
             b //
                 .note("press TAB to toggle")
+                .note("press F5 to reload shaders")
+                .note("press F12 to save a screenshot")
+                .note("press T to toggle the point light")
+                .container(
+                    Node::new_uibox(UiBox {
+                        layout: Layout {
+                            h_extend: true,
+                            height: 22.0,
+                            ..Default::default()
+                        },
+                        style: Style {
+                            font_size: 12.0,
+                            ..Default::default()
+                        },
+                        text: Some(String::new()),
+                        ..Default::default()
+                    })
+                    .with_update(|node, ctx| {
+                        let stats = ctx.visual_server.stats();
+                        node.as_uibox_mut().unwrap().text = Some(format!(
+                            "VRAM: {} MB, {} triangles",
+                            stats.vram_bytes / (1024 * 1024),
+                            stats.triangles
+                        ));
+                    }),
+                    |_| {},
+                )
+                .container(
+                    Node::new_uibox(UiBox {
+                        layout: Layout {
+                            h_extend: true,
+                            height: 22.0,
+                            ..Default::default()
+                        },
+                        style: Style {
+                            font_size: 12.0,
+                            ..Default::default()
+                        },
+                        text: Some(String::new()),
+                        ..Default::default()
+                    })
+                    .with_update(|node, ctx| {
+                        let stats = ctx.scene_stats;
+                        node.as_uibox_mut().unwrap().text = Some(format!(
+                            "Nodes: {}, Meshes: {}, Lights: {}",
+                            stats.nodes, stats.meshes, stats.lights
+                        ));
+                    }),
+                    |_| {},
+                )
                 .title("Antialiasing")
                 .button_group(|b| {
                     b.button(
                         "None",
-                        Some(|ctx| ctx.visual_server.set_msaa(1)),
+                        Some(|ctx| {
+                            ctx.visual_server.set_msaa(1);
+                            ctx.visual_server.set_taa(false);
+                        }),
                         Some(|node, ctx| {
                             node.as_uibox_mut().unwrap().active =
-                                ctx.visual_server.msaa_sample_count() == 1;
+                                ctx.visual_server.msaa_sample_count() == 1
+                                    && !ctx.visual_server.taa();
                         }),
                     )
                     .button(
                         "MSAAx4",
-                        Some(|ctx| ctx.visual_server.set_msaa(4)),
+                        Some(|ctx| {
+                            ctx.visual_server.set_msaa(4);
+                            ctx.visual_server.set_taa(false);
+                        }),
                         Some(|node, ctx| {
                             node.as_uibox_mut().unwrap().active =
                                 ctx.visual_server.msaa_sample_count() == 4;
                         }),
+                    )
+                    .button(
+                        "TAA",
+                        Some(|ctx| {
+                            ctx.visual_server.set_msaa(1);
+                            ctx.visual_server.set_taa(true);
+                        }),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active = ctx.visual_server.taa();
+                        }),
+                    );
+                })
+                .title("Still frame accumulation")
+                .button_group(|b| {
+                    b.button(
+                        "Off",
+                        Some(|ctx| {
+                            ctx.visual_server.set_camera_jitter(false);
+                            ctx.visual_server.set_accumulation(false);
+                        }),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active = !ctx.visual_server.accumulation();
+                        }),
+                    )
+                    .button(
+                        "On",
+                        Some(|ctx| {
+                            ctx.visual_server.set_camera_jitter(true);
+                            ctx.visual_server.set_accumulation(true);
+                        }),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active = ctx.visual_server.accumulation();
+                        }),
+                    );
+                })
+                .title("Render format")
+                .button_group(|b| {
+                    b.button(
+                        "8 bit",
+                        Some(|ctx| {
+                            ctx.visual_server
+                                .set_render_format(TextureFormat::Rgba8UnormSrgb)
+                        }),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.render_format() == TextureFormat::Rgba8UnormSrgb;
+                        }),
+                    )
+                    .button(
+                        "16 bit float",
+                        Some(|ctx| {
+                            ctx.visual_server
+                                .set_render_format(TextureFormat::Rgba16Float)
+                        }),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.render_format() == TextureFormat::Rgba16Float;
+                        }),
+                    );
+                })
+                .title("Shadow filtering")
+                .button_group(|b| {
+                    b.button(
+                        "Hard",
+                        Some(|ctx| ctx.visual_server.set_shadow_map_filter(FilterMode::Nearest)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.shadow_map_filter() == FilterMode::Nearest;
+                        }),
+                    )
+                    .button(
+                        "Linear",
+                        Some(|ctx| ctx.visual_server.set_shadow_map_filter(FilterMode::Linear)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.shadow_map_filter() == FilterMode::Linear;
+                        }),
+                    );
+                })
+                .title("Frame latency")
+                .button_group(|b| {
+                    b.button(
+                        "1",
+                        Some(|ctx| ctx.visual_server.set_max_frame_latency(1)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.max_frame_latency() == 1;
+                        }),
+                    )
+                    .button(
+                        "2",
+                        Some(|ctx| ctx.visual_server.set_max_frame_latency(2)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.max_frame_latency() == 2;
+                        }),
+                    )
+                    .button(
+                        "3",
+                        Some(|ctx| ctx.visual_server.set_max_frame_latency(3)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.max_frame_latency() == 3;
+                        }),
+                    );
+                })
+                .title("Present mode")
+                .button_group(|b| {
+                    b.button(
+                        "Fifo",
+                        Some(|ctx| ctx.visual_server.set_present_mode(PresentMode::Fifo)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.present_mode() == PresentMode::Fifo;
+                        }),
+                    )
+                    .button(
+                        "Mailbox",
+                        Some(|ctx| ctx.visual_server.set_present_mode(PresentMode::Mailbox)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.present_mode() == PresentMode::Mailbox;
+                        }),
+                    )
+                    .button(
+                        "Immediate",
+                        Some(|ctx| ctx.visual_server.set_present_mode(PresentMode::Immediate)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.present_mode() == PresentMode::Immediate;
+                        }),
                     );
                 })
                 .title("Resolution factor")
@@ -340,6 +702,55 @@ fn make_ui(scene: &mut renderer::Scene) {
                         }),
                     );
                 })
+                .title("Upscale filter")
+                .button_group(|b| {
+                    b.button(
+                        "Nearest",
+                        Some(|ctx| ctx.visual_server.set_upscale_filter(UpscaleFilter::Nearest)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.upscale_filter() == UpscaleFilter::Nearest;
+                        }),
+                    )
+                    .button(
+                        "Bilinear",
+                        Some(|ctx| {
+                            ctx.visual_server
+                                .set_upscale_filter(UpscaleFilter::Bilinear)
+                        }),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.upscale_filter() == UpscaleFilter::Bilinear;
+                        }),
+                    )
+                    .button(
+                        "Sharpen",
+                        Some(|ctx| ctx.visual_server.set_upscale_filter(UpscaleFilter::Sharpen)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.upscale_filter() == UpscaleFilter::Sharpen;
+                        }),
+                    );
+                })
+                .title("Ambient light")
+                .button_group(|b| {
+                    b.button(
+                        "Day",
+                        Some(|ctx| ctx.visual_server.set_ambient_light(AMBIENT_LIGHT_DAY)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.ambient_light() == AMBIENT_LIGHT_DAY;
+                        }),
+                    )
+                    .button(
+                        "Night",
+                        Some(|ctx| ctx.visual_server.set_ambient_light(AMBIENT_LIGHT_NIGHT)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.ambient_light() == AMBIENT_LIGHT_NIGHT;
+                        }),
+                    );
+                })
                 .title("Tone mapping")
                 .button_list(|b| {
                     b.button(
@@ -357,6 +768,106 @@ fn make_ui(scene: &mut renderer::Scene) {
                             node.as_uibox_mut().unwrap().active =
                                 ctx.visual_server.tone_mapping() == ToneMapping::Reinhard;
                         }),
+                    )
+                    .button(
+                        "ACES filmic",
+                        Some(|ctx| ctx.visual_server.set_tone_mapping(ToneMapping::AcesFilmic)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.tone_mapping() == ToneMapping::AcesFilmic;
+                        }),
+                    )
+                    .button(
+                        "Uncharted 2",
+                        Some(|ctx| ctx.visual_server.set_tone_mapping(ToneMapping::Uncharted2)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.tone_mapping() == ToneMapping::Uncharted2;
+                        }),
+                    );
+                })
+                .title("Exposure")
+                .button_group(|b| {
+                    b.button(
+                        "Fixed",
+                        Some(|ctx| ctx.visual_server.set_auto_exposure(false)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                !ctx.visual_server.auto_exposure();
+                        }),
+                    )
+                    .button(
+                        "Auto",
+                        Some(|ctx| ctx.visual_server.set_auto_exposure(true)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active = ctx.visual_server.auto_exposure();
+                        }),
+                    );
+                })
+                .title("Bloom")
+                .button_group(|b| {
+                    b.button(
+                        "Off",
+                        Some(|ctx| ctx.visual_server.set_bloom(false)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active = !ctx.visual_server.bloom();
+                        }),
+                    )
+                    .button(
+                        "On",
+                        Some(|ctx| ctx.visual_server.set_bloom(true)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active = ctx.visual_server.bloom();
+                        }),
+                    );
+                })
+                .title("Shadow cascades")
+                .button_group(|b| {
+                    b.button(
+                        "Live",
+                        Some(|ctx| ctx.visual_server.freeze_shadow_cascades(false)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                !ctx.visual_server.shadow_cascades_frozen();
+                        }),
+                    )
+                    .button(
+                        "Frozen",
+                        Some(|ctx| ctx.visual_server.freeze_shadow_cascades(true)),
+                        Some(|node, ctx| {
+                            node.as_uibox_mut().unwrap().active =
+                                ctx.visual_server.shadow_cascades_frozen();
+                        }),
+                    );
+                })
+                .title("Shadow map")
+                .button_group(|b| {
+                    b.button(
+                        "Hide",
+                        Some(|ctx| ctx.visual_server.unset_fullscreen_texture()),
+                        None,
+                    )
+                    .button(
+                        "Next light",
+                        Some(|ctx| {
+                            let mut lights: Vec<_> = ctx
+                                .visual_server
+                                .light_ids()
+                                .filter(|&id| ctx.visual_server.light_casts_shadows(id))
+                                .collect();
+                            if lights.is_empty() {
+                                return;
+                            }
+                            lights.sort();
+                            let shown = ctx.visual_server.shadow_map_fullscreen_texture_light();
+                            let index = lights
+                                .iter()
+                                .position(|&id| Some(id) == shown)
+                                .map_or(0, |index| (index + 1) % lights.len());
+                            ctx.visual_server
+                                .set_shadow_map_fullscreen_texture(lights[index]);
+                        }),
+                        None,
                     );
                 });
         },
@@ -2,19 +2,36 @@ use glam::Mat4;
 
 #[derive(Debug, Clone)]
 pub struct Camera {
-    pub vfov: f32,
+    pub projection: Projection,
     pub aspect_ratio: f32,
     pub near: f32,
     pub far: f32,
+    /// Distance from the camera that is in focus.
+    pub focus_distance: f32,
+    /// How quickly things get blurry away from the focus distance. 0 keeps everything in focus.
+    pub aperture: f32,
+    /// Inactive cameras aren't rendered from, unless chosen with `Engine::set_active_camera`.
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Vertical field of view, in radians.
+    Perspective { vfov: f32 },
+    /// Height of the view, in world units. The width follows the aspect ratio.
+    Orthographic { height: f32 },
 }
 
 impl Default for Camera {
     fn default() -> Self {
         Self {
-            vfov: 1.3,
+            projection: Projection::Perspective { vfov: 1.3 },
             aspect_ratio: 1.667,
             near: 0.05,
             far: 100.0,
+            focus_distance: 10.0,
+            aperture: 0.0,
+            active: true,
         }
     }
 }
@@ -24,7 +41,30 @@ impl Camera {
         Self::default()
     }
 
+    pub fn orthographic(height: f32) -> Self {
+        Self {
+            projection: Projection::Orthographic { height },
+            ..Default::default()
+        }
+    }
+
     pub fn projection_matrix(&self) -> Mat4 {
-        Mat4::perspective_lh(self.vfov, self.aspect_ratio, self.near, self.far)
+        match self.projection {
+            Projection::Perspective { vfov } => {
+                Mat4::perspective_lh(vfov, self.aspect_ratio, self.near, self.far)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    self.far,
+                )
+            }
+        }
     }
 }
@@ -0,0 +1,24 @@
+pub use asset_image::Cubemap;
+
+use crate::{
+    asset_server::{Asset, Loadable, Loader},
+    AssetError,
+};
+
+impl Loadable for Cubemap {
+    fn new_placeholder() -> Self {
+        Self::new_dummy()
+    }
+
+    fn new_loader(_options: &str) -> Box<dyn Loader> {
+        Box::new(CubemapLoader)
+    }
+}
+
+pub struct CubemapLoader;
+
+impl Loader for CubemapLoader {
+    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, AssetError> {
+        Ok(Box::new(Cubemap::load_from_path(path)?))
+    }
+}
@@ -6,32 +6,82 @@ use winit::{keyboard::KeyCode, window::Window};
 
 use crate::{
     arena::Handle,
-    scene::{NodeData, NodeId},
-    ui, AssetServer, Input, Scene, VisualServer,
+    scene::{NodeData, NodeId, PauseMode, SceneStats, UniqueNodeId},
+    ui, AssetServer, Color, Input, Scene, VisualServer,
 };
+#[cfg(feature = "gamepad")]
+use crate::{GamepadAxis, GamepadButton};
 
 pub struct Engine {
     pub asset_server: AssetServer,
     pub visual_server: VisualServer,
     pub input: Input,
     pub display: Display,
+    /// The active scene, the one updated and rendered.
     pub scene: Scene,
+    // The inactive scenes, with a placeholder in the active one's slot.
+    scenes: Vec<Scene>,
+    active_scene: usize,
+    /// Scales the time delta given to update functions: 0 pauses, 0.5 is slow motion, 2 is
+    /// twice the speed.
     pub timescale: f32,
-    gizmo_image: Handle<Image>,
+    render_root: Option<NodeId>,
+    active_camera: Option<UniqueNodeId>,
+    pub gizmos: Gizmos,
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
 }
 
 impl Engine {
+    const MAX_TIMESCALE: f32 = 4.0;
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn new(window: &Arc<Window>) -> Self {
+        pollster::block_on(Self::new_async(window))
+    }
+
+    /// Creating the engine waits on the GPU. On the web, where the main thread can't block, this
+    /// is the only way to create it.
+    pub async fn new_async(window: &Arc<Window>) -> Self {
+        let mut asset_server = AssetServer::new();
+        let visual_server = VisualServer::new_async(window, &mut asset_server).await;
+        Self::with_servers(asset_server, visual_server)
+    }
+
+    /// An engine without a window, for rendering frames to images with
+    /// [`VisualServer::render_to_image`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_headless(size: UVec2) -> Self {
         let mut asset_server = AssetServer::new();
-        let gizmo_image = asset_server.load("data/gizmo_dummy.png");
+        let visual_server = VisualServer::new_headless(size, &mut asset_server);
+        let mut engine = Self::with_servers(asset_server, visual_server);
+        engine.display.window_inner_size = size;
+        engine
+    }
+
+    fn with_servers(mut asset_server: AssetServer, visual_server: VisualServer) -> Self {
+        let gizmo_image: Handle<Image> = asset_server.load("data/gizmo_dummy.png");
         Self {
-            visual_server: VisualServer::new(window, &mut asset_server),
+            visual_server,
             asset_server,
             input: Default::default(),
             display: Default::default(),
             scene: Scene::new_empty(),
+            scenes: vec![Scene::new_empty()],
+            active_scene: 0,
             timescale: 1.0,
-            gizmo_image,
+            render_root: None,
+            active_camera: None,
+            gizmos: Gizmos {
+                camera: Some(gizmo_image),
+                light: Some(gizmo_image),
+                empty: None,
+                size: 0.05,
+            },
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new()
+                .inspect_err(|e| eprintln!("gamepads unavailable: {}", e))
+                .ok(),
         }
     }
 
@@ -40,30 +90,104 @@ impl Engine {
         self.visual_server.set_render_size(size);
     }
 
+    pub fn render_root(&self) -> Option<NodeId> {
+        self.render_root
+    }
+
+    /// Only render the given node and its descendants. Cameras, lights and ui are kept wherever
+    /// they are.
+    pub fn set_render_root(&mut self, node_id: Option<NodeId>) {
+        self.render_root = node_id;
+        self.visual_server.reset_instances();
+    }
+
+    pub fn active_camera(&self) -> Option<UniqueNodeId> {
+        self.active_camera
+    }
+
+    /// Renders from the given camera node only, even if other cameras are active. With none,
+    /// the last active camera updated is used.
+    pub fn set_active_camera(&mut self, camera: Option<UniqueNodeId>) {
+        self.active_camera = camera;
+    }
+
+    /// Keeps a scene to switch to later and returns its index. `Engine::scene` starts at
+    /// index 0.
+    pub fn add_scene(&mut self, scene: Scene) -> usize {
+        self.scenes.push(scene);
+        self.scenes.len() - 1
+    }
+
+    pub fn active_scene(&self) -> usize {
+        self.active_scene
+    }
+
+    /// Swaps the scene at `index` into `Engine::scene`, keeping the previous one as it was
+    /// to switch back to. The render root and active camera are unset, since they are nodes
+    /// of the previous scene. Fails without changing anything if there is no scene at `index`.
+    pub fn switch_scene(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.scenes.len() {
+            return Err(format!("no such scene: {}", index));
+        }
+        if index == self.active_scene {
+            return Ok(());
+        }
+        std::mem::swap(&mut self.scene, &mut self.scenes[self.active_scene]);
+        std::mem::swap(&mut self.scene, &mut self.scenes[index]);
+        self.active_scene = index;
+
+        self.render_root = None;
+        self.active_camera = None;
+        self.visual_server
+            .reset_scene(&self.scene.meshes(), &mut self.asset_server);
+        Ok(())
+    }
+
     pub fn update(&mut self) {
         self.asset_server.update();
 
         self.notify_asset_changes();
+        self.visual_server.stream_textures(&self.asset_server);
 
         self.update_input();
 
+        let context = &mut Context {
+            asset_server: &mut self.asset_server,
+            visual_server: &mut self.visual_server,
+            display: &self.display,
+            input: &self.input,
+            time: &Time {
+                delta: (1.0 / 60.0) * self.timescale.max(0.0),
+            },
+            gizmos: &self.gizmos,
+            scene_stats: self.scene.stats(),
+        };
+
+        // Every node gets updated before any global transform is computed, so that nodes
+        // depending on others only have to be given a higher update priority.
+        Self::run_update_fns(&mut self.scene, context);
+        Self::run_animation_players(&mut self.scene, context);
+        // Removing nodes while update functions run would pull them from under the traversal.
+        self.scene.remove_queued_nodes();
+        for unique_node_id in self.scene.take_removed_nodes() {
+            context.visual_server.remove_light(unique_node_id);
+            context.visual_server.remove_mesh_instance(unique_node_id);
+            context
+                .visual_server
+                .remove_sprite(unique_node_id, context.asset_server);
+        }
         Self::update_node_recursive(
             self.scene.root,
             &mut self.scene,
             Affine3A::IDENTITY,
-            &mut Context {
-                asset_server: &mut self.asset_server,
-                visual_server: &mut self.visual_server,
-                display: &self.display,
-                input: &self.input,
-                time: &Time {
-                    delta: (1.0 / 60.0) * self.timescale,
-                },
-                gizmo_image: self.gizmo_image,
-            },
+            self.render_root.is_none(),
+            self.render_root,
+            self.active_camera,
+            context,
         );
 
         self.input.swap_maps();
+        self.input.scroll_delta = Vec2::ZERO;
     }
 
     fn notify_asset_changes(&mut self) {
@@ -74,6 +198,9 @@ impl Engine {
     }
 
     fn update_input(&mut self) {
+        #[cfg(feature = "gamepad")]
+        self.poll_gamepads();
+
         if self.display.window_inner_size.y > 0 {
             let delta_view = self.input.pointer_delta / self.display.window_inner_size.y as f32;
             self.input.delta_view = delta_view;
@@ -91,53 +218,177 @@ impl Engine {
         self.input.pointer_delta = Vec2::ZERO;
 
         if self.input.is_pressed(KeyCode::ArrowLeft) {
-            self.timescale = f32::clamp(self.timescale - 0.05, 0.0, 1.0);
+            self.timescale = f32::clamp(self.timescale - 0.05, 0.0, Self::MAX_TIMESCALE);
         } else if self.input.is_pressed(KeyCode::ArrowRight) {
-            self.timescale = f32::clamp(self.timescale + 0.05, 0.0, 1.0);
+            self.timescale = f32::clamp(self.timescale + 0.05, 0.0, Self::MAX_TIMESCALE);
+        }
+    }
+
+    /// The input of every connected gamepad goes to the same axes and buttons.
+    #[cfg(feature = "gamepad")]
+    fn poll_gamepads(&mut self) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+        while let Some(gilrs::Event { event, .. }) = gilrs.next_event() {
+            match event {
+                gilrs::EventType::ButtonPressed(button, _) => {
+                    if let Some(button) = GamepadButton::from_gilrs(button) {
+                        self.input.gamepad_buttons.insert(button, true);
+                    }
+                }
+                gilrs::EventType::ButtonReleased(button, _) => {
+                    if let Some(button) = GamepadButton::from_gilrs(button) {
+                        self.input.gamepad_buttons.insert(button, false);
+                    }
+                }
+                // Analog triggers are reported as buttons with a value.
+                gilrs::EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => {
+                    self.input.axis.insert(GamepadAxis::LeftTrigger, value);
+                }
+                gilrs::EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => {
+                    self.input.axis.insert(GamepadAxis::RightTrigger, value);
+                }
+                gilrs::EventType::AxisChanged(axis, value, _) => {
+                    if let Some(axis) = GamepadAxis::from_gilrs(axis) {
+                        self.input.axis.insert(axis, value);
+                    }
+                }
+                gilrs::EventType::Disconnected => {
+                    self.input.axis.clear();
+                    self.input.gamepad_buttons.clear();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn run_update_fns(scene: &mut Scene, context: &mut Context) {
+        fn gather_nodes(node_id: NodeId, scene: &Scene, nodes: &mut Vec<NodeId>) {
+            if scene.get(node_id).pause == PauseMode::Subtree {
+                return;
+            }
+            nodes.push(node_id);
+            // Ui nodes below the ui root get updated by the ui.
+            if scene.get(node_id).as_uibox().is_some() {
+                return;
+            }
+            for &child_id in scene.children_of(node_id) {
+                gather_nodes(child_id, scene, nodes);
+            }
+        }
+        let mut nodes = Vec::new();
+        gather_nodes(scene.root, scene, &mut nodes);
+
+        // Stable sort, so that scene order is kept between nodes of the same priority.
+        nodes.sort_by_key(|&node_id| scene.get(node_id).update_priority);
+
+        for node_id in nodes {
+            let node = scene.get_mut(node_id);
+
+            if node.pause == PauseMode::Off {
+                if let Some(update_fn) = node.update_fn.take() {
+                    update_fn(node, context);
+                    node.update_fn = Some(update_fn);
+                }
+            }
+
+            if let NodeData::Scene(subscene) = &mut node.data {
+                Self::run_update_fns(subscene, context);
+            }
+        }
+    }
+
+    fn run_animation_players(scene: &mut Scene, context: &mut Context) {
+        fn animate_recursive(node_id: NodeId, scene: &mut Scene, context: &mut Context) {
+            let node = scene.get_mut(node_id);
+            if node.pause == PauseMode::Subtree {
+                return;
+            }
+            if node.pause == PauseMode::Off {
+                // Taken out of the scene, since it might animate its own node.
+                if let Some(mut player) = node.animation_player.take() {
+                    player.advance(context.time.delta, context.asset_server, scene);
+                    scene.get_mut(node_id).animation_player = Some(player);
+                }
+            }
+            if let NodeData::Scene(subscene) = &mut scene.get_mut(node_id).data {
+                Engine::run_animation_players(subscene, context);
+            }
+
+            for child_id in scene.children_of(node_id).to_vec() {
+                animate_recursive(child_id, scene, context);
+            }
         }
+        animate_recursive(scene.root, scene, context);
     }
 
     fn update_node_recursive(
         node_id: NodeId,
         scene: &mut Scene,
         parent_global_transform: Affine3A,
+        rendered: bool,
+        render_root: Option<NodeId>,
+        active_camera: Option<UniqueNodeId>,
         context: &mut Context,
     ) {
+        let rendered = rendered || render_root == Some(node_id);
         let unique_node_id = scene.make_unique_node_id(node_id);
         let node = scene.nodes.get_mut(node_id);
 
-        if let Some(update_fn) = node.update_fn.take() {
-            update_fn(node, context);
-            node.update_fn = Some(update_fn);
-        }
-
         let node_global_transform = parent_global_transform * node.transform;
 
         match &mut node.data {
-            NodeData::Empty => (),
+            NodeData::Empty => {
+                Self::set_gizmo(
+                    unique_node_id,
+                    node_global_transform,
+                    context.gizmos.empty.filter(|_| rendered),
+                    Color::WHITE,
+                    context,
+                );
+            }
             NodeData::Camera(camera) => {
-                camera.aspect_ratio = context.display.window_aspect_ratio();
-                context
-                    .visual_server
-                    .set_camera(&node_global_transform, camera);
+                let viewing = active_camera.map_or(camera.active, |id| id == unique_node_id);
+                if viewing {
+                    camera.aspect_ratio = context.display.window_aspect_ratio();
+                    context
+                        .visual_server
+                        .set_camera(&node_global_transform, camera);
+                }
+                // The camera being rendered from would have its gizmo right in its face.
+                Self::set_gizmo(
+                    unique_node_id,
+                    node_global_transform,
+                    context.gizmos.camera.filter(|_| rendered && !viewing),
+                    Color::WHITE,
+                    context,
+                );
             }
             NodeData::Light(light) => {
-                context
-                    .visual_server
-                    .set_light(unique_node_id, node_global_transform, light);
-                context.visual_server.set_sprite(
+                // Lights still light the render root, only their gizmo gets hidden.
+                if light.enabled {
+                    context
+                        .visual_server
+                        .set_light(unique_node_id, node_global_transform, light);
+                } else {
+                    context.visual_server.remove_light(unique_node_id);
+                }
+                Self::set_gizmo(
                     unique_node_id,
                     node_global_transform,
-                    context.gizmo_image,
+                    context.gizmos.light.filter(|_| rendered),
                     light.color,
-                    context.asset_server,
+                    context,
                 );
             }
-            NodeData::Mesh(mesh_handle) => {
+            NodeData::Mesh(_) if !rendered => (),
+            NodeData::Mesh(mesh_instance) => {
                 context.visual_server.set_mesh_instance(
                     unique_node_id,
                     node_global_transform,
-                    *mesh_handle,
+                    mesh_instance.mesh,
+                    mesh_instance.tint,
                     context.asset_server,
                 );
             }
@@ -146,6 +397,9 @@ impl Engine {
                     subscene.root,
                     subscene,
                     node_global_transform,
+                    rendered,
+                    None,
+                    active_camera,
                     context,
                 );
             }
@@ -160,18 +414,60 @@ impl Engine {
 
         let children = scene.children_of(node_id).to_vec();
         for child_id in children {
-            Self::update_node_recursive(child_id, scene, node_global_transform, context);
+            Self::update_node_recursive(
+                child_id,
+                scene,
+                node_global_transform,
+                rendered,
+                render_root,
+                active_camera,
+                context,
+            );
+        }
+    }
+
+    fn set_gizmo(
+        unique_node_id: UniqueNodeId,
+        transform: Affine3A,
+        image: Option<Handle<Image>>,
+        color: Color,
+        context: &mut Context,
+    ) {
+        match image {
+            Some(image) => context.visual_server.set_sprite(
+                unique_node_id,
+                transform,
+                context.gizmos.size,
+                image,
+                color,
+                context.asset_server,
+            ),
+            None => context
+                .visual_server
+                .remove_sprite(unique_node_id, context.asset_server),
         }
     }
 }
 
+/// Icons shown at the position of nodes that don't render anything themselves.
+pub struct Gizmos {
+    /// None to not show a gizmo for cameras.
+    pub camera: Option<Handle<Image>>,
+    pub light: Option<Handle<Image>>,
+    pub empty: Option<Handle<Image>>,
+    /// Height of the gizmos, as a fraction of the window height.
+    pub size: f32,
+}
+
 pub struct Context<'a> {
     pub asset_server: &'a mut AssetServer,
     pub visual_server: &'a mut VisualServer,
     pub display: &'a Display,
     pub input: &'a Input,
     pub time: &'a Time,
-    pub gizmo_image: Handle<Image>,
+    pub gizmos: &'a Gizmos,
+    /// Counts of the scene's nodes, as of the start of this update.
+    pub scene_stats: SceneStats,
 }
 
 pub struct Time {
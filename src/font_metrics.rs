@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use asset_error::AssetError;
+use glam::Vec2;
+
+use crate::{renderer::visual_server::FontAtlasLayout, AtlasRect};
+
+/// Where the glyphs of a font are in its image, and how they are placed. Lengths are fractions
+/// of the font size, which is the height of a line.
+#[derive(Debug, Clone)]
+pub struct FontMetrics {
+    glyphs: HashMap<u8, GlyphMetrics>,
+    /// Drawn for characters the font doesn't have.
+    fallback: GlyphMetrics,
+    /// From the top of the line to the baseline.
+    baseline: f32,
+    /// How far the glyphs reach above and below the baseline.
+    ascent: f32,
+    descent: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphMetrics {
+    /// From the start of this glyph to the start of the next one.
+    pub advance: f32,
+    /// From the start of the glyph, on the top of the line, to the top left of its quad.
+    pub offset: Vec2,
+    pub size: Vec2,
+    pub uv: AtlasRect,
+}
+
+impl FontMetrics {
+    // Monospaced glyph cells are stretched to this width.
+    const MONOSPACE_ADVANCE: f32 = 1.1667 * 0.5;
+
+    /// Glyphs of the same width, laid out in a grid of cells spanning a whole line each.
+    /// Characters past the last cell use the last cell. The cells don't tell where the baseline
+    /// is, so it's taken at their bottom.
+    pub(crate) fn monospace(layout: FontAtlasLayout) -> Self {
+        let last_glyph = layout.glyph_count().saturating_sub(1);
+        let columns = layout.columns();
+        let cell_size = Vec2::new(1.0 / columns as f32, 1.0 / layout.rows() as f32);
+        let glyph = |c: u8| {
+            let index = (c as u32).min(last_glyph);
+            let uv_min = Vec2::new((index % columns) as f32, (index / columns) as f32) * cell_size;
+            GlyphMetrics {
+                advance: Self::MONOSPACE_ADVANCE,
+                offset: Vec2::ZERO,
+                size: Vec2::new(Self::MONOSPACE_ADVANCE, 1.0),
+                uv: AtlasRect {
+                    uv_min,
+                    uv_max: uv_min + cell_size,
+                },
+            }
+        };
+        Self {
+            glyphs: (0..=u8::MAX).map(|c| (c, glyph(c))).collect(),
+            fallback: glyph(b'?'),
+            baseline: 1.0,
+            ascent: 1.0,
+            descent: 0.0,
+        }
+    }
+
+    /// Reads a font description in the text format of AngelCode's BMFont. Only single page fonts
+    /// are supported.
+    pub fn load(path: &str) -> Result<Self, AssetError> {
+        Self::parse_fnt(&std::fs::read_to_string(path)?)
+    }
+
+    fn parse_fnt(src: &str) -> Result<Self, AssetError> {
+        let mut line_height = None;
+        let mut baseline = None;
+        let mut image_size = None;
+        let mut glyphs = HashMap::new();
+        for line in src.lines() {
+            let mut words = line.split_whitespace();
+            let tag = words.next();
+            let attributes: HashMap<&str, &str> =
+                words.filter_map(|word| word.split_once('=')).collect();
+            let attribute = |name: &str| -> Result<f32, AssetError> {
+                attributes
+                    .get(name)
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| AssetError::Parse(format!("bad or missing '{}' in fnt", name)))
+            };
+            match tag {
+                Some("common") => {
+                    line_height = Some(attribute("lineHeight")?);
+                    baseline = Some(attribute("base")? / attribute("lineHeight")?);
+                    image_size = Some(Vec2::new(attribute("scaleW")?, attribute("scaleH")?));
+                    if attribute("pages")? > 1.0 {
+                        return Err(AssetError::Unsupported(
+                            "fnt fonts with several pages".to_string(),
+                        ));
+                    }
+                }
+                Some("char") => {
+                    let (Some(line_height), Some(image_size)) = (line_height, image_size) else {
+                        return Err(AssetError::Parse("fnt char before common".to_string()));
+                    };
+                    // Only the characters text can be made of.
+                    let Ok(id) = u8::try_from(attribute("id")? as u32) else {
+                        continue;
+                    };
+                    let position = Vec2::new(attribute("x")?, attribute("y")?);
+                    let size = Vec2::new(attribute("width")?, attribute("height")?);
+                    glyphs.insert(
+                        id,
+                        GlyphMetrics {
+                            advance: attribute("xadvance")? / line_height,
+                            offset: Vec2::new(attribute("xoffset")?, attribute("yoffset")?)
+                                / line_height,
+                            size: size / line_height,
+                            uv: AtlasRect {
+                                uv_min: position / image_size,
+                                uv_max: (position + size) / image_size,
+                            },
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let Some(baseline) = baseline else {
+            return Err(AssetError::Parse("fnt without common".to_string()));
+        };
+        let fallback = glyphs.get(&b'?').copied().unwrap_or(GlyphMetrics {
+            advance: 0.0,
+            offset: Vec2::ZERO,
+            size: Vec2::ZERO,
+            uv: AtlasRect {
+                uv_min: Vec2::ZERO,
+                uv_max: Vec2::ZERO,
+            },
+        });
+        // Without glyphs, the text is as tall as the line.
+        let top = glyphs.values().map(|g| g.offset.y).reduce(f32::min);
+        let bottom = glyphs
+            .values()
+            .map(|g| g.offset.y + g.size.y)
+            .reduce(f32::max);
+        Ok(Self {
+            glyphs,
+            fallback,
+            baseline,
+            ascent: baseline - top.unwrap_or(0.0),
+            descent: bottom.unwrap_or(1.0) - baseline,
+        })
+    }
+
+    pub fn glyph(&self, c: u8) -> &GlyphMetrics {
+        self.glyphs.get(&c).unwrap_or(&self.fallback)
+    }
+
+    pub fn baseline(&self) -> f32 {
+        self.baseline
+    }
+
+    pub fn ascent(&self) -> f32 {
+        self.ascent
+    }
+
+    pub fn descent(&self) -> f32 {
+        self.descent
+    }
+}
@@ -0,0 +1,42 @@
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::Aabb;
+
+/// The volume a camera sees, as six planes facing inwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from the rows of the matrix, for a depth range of 0 to 1.
+    pub fn from_projection_view(projection_view: Mat4) -> Self {
+        let [row0, row1, row2, row3] = [0, 1, 2, 3].map(|i| projection_view.row(i));
+        let planes = [
+            row3 + row0, // Left
+            row3 - row0, // Right
+            row3 + row1, // Bottom
+            row3 - row1, // Top
+            row2,        // Near
+            row3 - row2, // Far
+        ]
+        .map(|plane| plane / plane.xyz().length());
+        Self { planes }
+    }
+
+    /// Left, right, bottom, top, near and far planes. A point `p` is on the inner side of a
+    /// plane when `plane.xyz().dot(p) + plane.w >= 0.0`.
+    pub fn planes(&self) -> [Vec4; 6] {
+        self.planes
+    }
+
+    /// Conservative: some boxes just outside the corners of the frustum count as intersecting.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            // The corner furthest along the plane's normal.
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -1,23 +1,41 @@
-pub use asset_image::Image;
+pub use asset_image::{ColorSpace, Image};
 
-use crate::asset_server::{Asset, Loadable, Loader};
+use crate::{
+    asset_server::{Asset, Loadable, Loader},
+    AssetError,
+};
 
 impl Loadable for Image {
     fn new_placeholder() -> Self {
         Self::new_dummy()
     }
 
-    fn new_loader(_options: &str) -> Box<dyn Loader> {
-        Box::new(ImageLoader)
+    fn new_loader(options: &str) -> Box<dyn Loader> {
+        Box::new(ImageLoader::new(options))
     }
 }
 
-pub struct ImageLoader;
+/// Loads images holding colors by default. With the "linear" option, the image is taken to hold
+/// data instead, like a normal map, see [`ColorSpace::Linear`].
+pub struct ImageLoader {
+    color_space: ColorSpace,
+}
+
+impl ImageLoader {
+    pub fn new(options: &str) -> Self {
+        let color_space = if options.split(',').any(|o| o.trim() == "linear") {
+            ColorSpace::Linear
+        } else {
+            ColorSpace::Srgb
+        };
+        Self { color_space }
+    }
+}
 
 impl Loader for ImageLoader {
-    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, String> {
+    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, AssetError> {
         let mut image = Image::load_from_path(path)?;
-        let _ = image.make_mips();
+        let _ = image.make_mips(self.color_space, self.color_space == ColorSpace::Srgb);
         Ok(Box::new(image))
     }
 }
@@ -1,17 +1,27 @@
 use std::collections::HashMap;
 
 use glam::{Vec2, Vec3};
-use winit::{event::MouseButton, keyboard::KeyCode};
+use winit::{
+    event::{MouseButton, MouseScrollDelta},
+    keyboard::KeyCode,
+};
 
 #[derive(Debug, Default)]
 pub struct Input {
     pub keymap: HashMap<KeyCode, bool>,
     pub previous_keymap: HashMap<KeyCode, bool>,
     pub buttonmap: HashMap<MouseButton, bool>,
+    pub previous_buttonmap: HashMap<MouseButton, bool>,
     pub mod_shift: bool,
     pub pointer_pos: Vec2,
     pub pointer_delta: Vec2,
     pub pointer_grabbed: bool,
+    /// In lines scrolled since the last update, positive y being up, away from the user.
+    pub scroll_delta: Vec2,
+    /// Of all the connected gamepads together. Only filled in with the `gamepad` feature.
+    pub axis: HashMap<GamepadAxis, f32>,
+    pub gamepad_buttons: HashMap<GamepadButton, bool>,
+    pub previous_gamepad_buttons: HashMap<GamepadButton, bool>,
     //
     pub delta_view: Vec2,
     pub movement: Vec3,
@@ -19,6 +29,18 @@ pub struct Input {
 }
 
 impl Input {
+    // Touchpads scroll by pixels, mouse wheels by lines.
+    const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+    pub fn add_scroll(&mut self, delta: MouseScrollDelta) {
+        self.scroll_delta += match delta {
+            MouseScrollDelta::LineDelta(x, y) => Vec2::new(x, y),
+            MouseScrollDelta::PixelDelta(position) => {
+                Vec2::new(position.x as f32, position.y as f32) / Self::PIXELS_PER_SCROLL_LINE
+            }
+        };
+    }
+
     pub fn is_pressed(&self, key: KeyCode) -> bool {
         self.keymap.get(&key).copied().unwrap_or_default()
     }
@@ -32,10 +54,54 @@ impl Input {
         }
     }
 
+    pub fn is_just_released(&self, key: KeyCode) -> bool {
+        let was_pressed = self.previous_keymap.get(&key).copied().unwrap_or_default();
+        was_pressed && !self.is_pressed(key)
+    }
+
     pub fn is_button_pressed(&self, button: MouseButton) -> bool {
         self.buttonmap.get(&button).copied().unwrap_or_default()
     }
 
+    pub fn is_button_just_pressed(&self, button: MouseButton) -> bool {
+        let was_pressed = self
+            .previous_buttonmap
+            .get(&button)
+            .copied()
+            .unwrap_or_default();
+        !was_pressed && self.is_button_pressed(button)
+    }
+
+    pub fn is_button_just_released(&self, button: MouseButton) -> bool {
+        let was_pressed = self
+            .previous_buttonmap
+            .get(&button)
+            .copied()
+            .unwrap_or_default();
+        was_pressed && !self.is_button_pressed(button)
+    }
+
+    /// Sticks go from -1 to 1, up and right being positive. Triggers go from 0 to 1.
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.axis.get(&axis).copied().unwrap_or_default()
+    }
+
+    pub fn is_gamepad_pressed(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons
+            .get(&button)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn gamepad_just_pressed(&self, button: GamepadButton) -> bool {
+        let was_pressed = self
+            .previous_gamepad_buttons
+            .get(&button)
+            .copied()
+            .unwrap_or_default();
+        !was_pressed && self.is_gamepad_pressed(button)
+    }
+
     pub fn axis_strength(&self, positive: KeyCode, negtive: KeyCode) -> f32 {
         let positive_strength = self.is_pressed(positive) as u8 as f32;
         let negative_strength = self.is_pressed(negtive) as u8 as f32;
@@ -45,5 +111,82 @@ impl Input {
     pub fn swap_maps(&mut self) {
         self.previous_keymap.clear();
         self.previous_keymap.extend(self.keymap.iter());
+        self.previous_buttonmap.clear();
+        self.previous_buttonmap.extend(self.buttonmap.iter());
+        self.previous_gamepad_buttons.clear();
+        self.previous_gamepad_buttons
+            .extend(self.gamepad_buttons.iter());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// Named after their position, like the face buttons: South is A on Xbox controllers and Cross
+/// on PlayStation ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadAxis {
+    pub(crate) fn from_gilrs(axis: gilrs::Axis) -> Option<Self> {
+        Some(match axis {
+            gilrs::Axis::LeftStickX => Self::LeftStickX,
+            gilrs::Axis::LeftStickY => Self::LeftStickY,
+            gilrs::Axis::RightStickX => Self::RightStickX,
+            gilrs::Axis::RightStickY => Self::RightStickY,
+            gilrs::Axis::LeftZ => Self::LeftTrigger,
+            gilrs::Axis::RightZ => Self::RightTrigger,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadButton {
+    pub(crate) fn from_gilrs(button: gilrs::Button) -> Option<Self> {
+        Some(match button {
+            gilrs::Button::South => Self::South,
+            gilrs::Button::East => Self::East,
+            gilrs::Button::North => Self::North,
+            gilrs::Button::West => Self::West,
+            gilrs::Button::LeftTrigger => Self::LeftBumper,
+            gilrs::Button::RightTrigger => Self::RightBumper,
+            gilrs::Button::LeftTrigger2 => Self::LeftTrigger,
+            gilrs::Button::RightTrigger2 => Self::RightTrigger,
+            gilrs::Button::Select => Self::Select,
+            gilrs::Button::Start => Self::Start,
+            gilrs::Button::LeftThumb => Self::LeftStick,
+            gilrs::Button::RightThumb => Self::RightStick,
+            gilrs::Button::DPadUp => Self::DPadUp,
+            gilrs::Button::DPadDown => Self::DPadDown,
+            gilrs::Button::DPadLeft => Self::DPadLeft,
+            gilrs::Button::DPadRight => Self::DPadRight,
+            _ => return None,
+        })
     }
 }
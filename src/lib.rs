@@ -1,13 +1,17 @@
 mod engine;
-pub use engine::Engine;
+pub use engine::{Engine, Gizmos};
 
 pub mod arena;
 
 mod renderer;
-pub use self::renderer::visual_server::ToneMapping;
+pub use self::renderer::visual_server::{
+    ColorLoadOp, DrawOrder, FontAtlasLayout, PresentMode, TextAlign, TextDescriptor, ToneMapping,
+    UpscaleFilter,
+};
 pub use self::renderer::VisualServer;
 
 mod asset_server;
+pub use asset_error::AssetError;
 pub use asset_server::AssetServer;
 
 mod color;
@@ -17,27 +21,46 @@ mod timestamp;
 pub use timestamp::Timestamp;
 
 mod material;
-pub use material::Material;
+pub use material::{AlphaMode, BillboardMode, Material, TextureMapping};
 
 mod mesh;
-pub use mesh::{Mesh, Submesh};
+mod mesh_simplification;
+pub use mesh::{Mesh, MeshLod, Submesh};
+
+mod animation;
+pub use animation::{Animation, AnimationChannel, AnimationPlayer, Interpolation, Keyframes};
 
 mod image;
-pub use image::Image;
+pub use image::{ColorSpace, Image};
+
+mod cubemap;
+pub use cubemap::Cubemap;
+
+mod font_metrics;
+pub use font_metrics::{FontMetrics, GlyphMetrics};
+
+mod texture_atlas;
+pub use texture_atlas::{AtlasRect, TextureAtlas};
 
 pub mod shader_source;
 pub use shader_source::ShaderSource;
 
 mod scene;
-pub use scene::{Node, NodeData, Scene};
+pub use scene::{MeshInstance, Node, NodeData, PauseMode, Scene, SceneStats, UniqueNodeId};
 
 pub mod ui;
 
 mod camera;
-pub use camera::Camera;
+pub use camera::{Camera, Projection};
+
+mod aabb;
+pub use aabb::Aabb;
+
+mod frustum;
+pub use frustum::Frustum;
 
 mod light;
 pub use light::Light;
 
 mod input;
-pub use input::Input;
+pub use input::{GamepadAxis, GamepadButton, Input};
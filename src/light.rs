@@ -4,6 +4,10 @@ use crate::Color;
 pub struct Light {
     pub color: Color,
     pub kind: LightKind,
+    /// Disabled lights don't light anything, but their node stays in the scene.
+    pub enabled: bool,
+    /// Lights that don't cast shadows don't get a shadow map.
+    pub casts_shadows: bool,
 }
 
 impl Light {
@@ -21,14 +25,50 @@ impl Light {
         }
     }
 
+    /// Angles are between the light's direction and the edges of the cone, in radians. The light
+    /// fades out from the inner angle to the outer one.
+    pub fn spot(range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            },
+            ..Default::default()
+        }
+    }
+
     pub fn with_color(mut self, color: Color) -> Self {
         self.color = color;
         self
     }
 
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn with_casts_shadows(mut self, casts_shadows: bool) -> Self {
+        self.casts_shadows = casts_shadows;
+        self
+    }
+
+    /// Cosines of the inner and outer angles of spot lights.
+    pub fn spot_cos_angles(&self) -> Option<(f32, f32)> {
+        match &self.kind {
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+                ..
+            } => Some((inner_angle.cos(), outer_angle.cos())),
+            _ => None,
+        }
+    }
+
     pub fn radius(&self) -> Option<f32> {
         match &self.kind {
             LightKind::Point { radius } => Some(*radius),
+            LightKind::Spot { range, .. } => Some(*range),
             _ => None,
         }
     }
@@ -39,6 +79,8 @@ impl Default for Light {
         Self {
             color: Color::WHITE,
             kind: LightKind::Point { radius: 1.0 },
+            enabled: true,
+            casts_shadows: true,
         }
     }
 }
@@ -46,7 +88,14 @@ impl Default for Light {
 #[derive(Clone)]
 pub enum LightKind {
     Directional,
-    Point { radius: f32 },
+    Point {
+        radius: f32,
+    },
+    Spot {
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
 }
 
 impl LightKind {
@@ -54,6 +103,7 @@ impl LightKind {
         match &self {
             LightKind::Directional { .. } => 0,
             LightKind::Point { .. } => 1,
+            LightKind::Spot { .. } => 2,
         }
     }
 }
@@ -1,10 +1,30 @@
-use crate::{arena::Handle, image::Image, Color};
+use glam::Vec2;
+
+use crate::{arena::Handle, image::Image, shader_source::ShaderSource, Color};
 
 pub struct Material {
     pub base_color: Color,
     pub base_color_image: Option<Handle<Image>>,
+    pub emissive: Color,
+    /// Multiplied by `emissive`.
+    pub emissive_image: Option<Handle<Image>>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Roughness is read from the green channel and metallic from the blue one, multiplied by
+    /// `roughness` and `metallic`.
+    pub metallic_roughness_image: Option<Handle<Image>>,
+    /// Tangent space normals, only used on vertices that have tangents.
+    pub normal_image: Option<Handle<Image>>,
+    /// Texture coordinates are scaled, then offset before sampling.
+    pub uv_scale: Vec2,
+    pub uv_offset: Vec2,
+    pub texture_mapping: TextureMapping,
     pub billboard_mode: BillboardMode,
+    pub alpha_mode: AlphaMode,
     pub unlit: bool,
+    /// Replaces the vertex stage of render_mesh.wgsl, with the same bindings, entry points and
+    /// output. It can also read the submesh's custom attributes as `@location(15) vec4f`.
+    pub vertex_shader: Option<Handle<ShaderSource>>,
 }
 
 impl Default for Material {
@@ -12,14 +32,122 @@ impl Default for Material {
         Self {
             base_color: Color::WHITE,
             base_color_image: None,
-            billboard_mode: BillboardMode::Off,
+            emissive: Color::BLACK,
+            emissive_image: None,
+            metallic: 0.0,
+            roughness: 1.0,
+            metallic_roughness_image: None,
+            normal_image: None,
+            uv_scale: Vec2::ONE,
+            uv_offset: Vec2::ZERO,
+            texture_mapping: Default::default(),
+            billboard_mode: Default::default(),
+            alpha_mode: Default::default(),
             unlit: false,
+            vertex_shader: None,
         }
     }
 }
 
+impl Material {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_base_color(mut self, base_color: Color) -> Self {
+        self.base_color = base_color;
+        self
+    }
+
+    pub fn with_base_color_image(mut self, image: Option<Handle<Image>>) -> Self {
+        self.base_color_image = image;
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Color) -> Self {
+        self.emissive = emissive;
+        self
+    }
+
+    pub fn with_emissive_image(mut self, image: Option<Handle<Image>>) -> Self {
+        self.emissive_image = image;
+        self
+    }
+
+    pub fn with_metallic_roughness(mut self, metallic: f32, roughness: f32) -> Self {
+        self.metallic = metallic;
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_metallic_roughness_image(mut self, image: Option<Handle<Image>>) -> Self {
+        self.metallic_roughness_image = image;
+        self
+    }
+
+    pub fn with_normal_image(mut self, image: Option<Handle<Image>>) -> Self {
+        self.normal_image = image;
+        self
+    }
+
+    pub fn with_uv_transform(mut self, scale: Vec2, offset: Vec2) -> Self {
+        self.uv_scale = scale;
+        self.uv_offset = offset;
+        self
+    }
+
+    pub fn with_texture_mapping(mut self, texture_mapping: TextureMapping) -> Self {
+        self.texture_mapping = texture_mapping;
+        self
+    }
+
+    pub fn with_billboard_mode(mut self, billboard_mode: BillboardMode) -> Self {
+        self.billboard_mode = billboard_mode;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    pub fn unlit(mut self) -> Self {
+        self.unlit = true;
+        self
+    }
+
+    pub fn with_vertex_shader(mut self, vertex_shader: Option<Handle<ShaderSource>>) -> Self {
+        self.vertex_shader = vertex_shader;
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextureMapping {
+    #[default]
+    Uv,
+    /// Projects the texture along the world axes, blended by the surface normal. For meshes
+    /// without usable texture coordinates. The uv scale and offset apply to world positions.
+    Triplanar,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum BillboardMode {
+    #[default]
     Off,
+    /// Faces the camera, keeping the model's position and scale.
     On,
     FixedSize,
 }
+
+/// How the alpha of the base color is used.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with an alpha under the cutoff are discarded, the others are opaque.
+    Mask(f32),
+    /// Blended over what's behind, after every opaque mesh is drawn.
+    Blend,
+}
@@ -1,15 +1,141 @@
-use glam::{Vec2, Vec3};
+use std::f32::consts::{PI, TAU};
 
-use crate::{arena::Handle, renderer::Vertex, Material};
+use glam::{Affine3A, Vec2, Vec3, Vec4};
+
+use crate::{arena::Handle, mesh_simplification, renderer::Vertex, Aabb, Material};
 
 pub struct Mesh {
     pub submeshes: Vec<Submesh>,
+    /// Simpler versions of the mesh, drawn instead of it when it's small on screen.
+    pub lods: Vec<MeshLod>,
+}
+
+pub struct MeshLod {
+    pub submeshes: Vec<Submesh>,
+    /// Drawn when the bounds of the mesh cover less than this fraction of the screen's height.
+    /// The level with the smallest screen size still above the mesh's is picked.
+    pub screen_size: f32,
 }
 
 pub struct Submesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub material: Option<Handle<Material>>,
+    /// For the custom vertex shader of the material, one per vertex, or none at all. They are
+    /// zero when missing.
+    pub custom_attributes: Vec<[f32; 4]>,
+}
+
+impl Submesh {
+    fn new_empty() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            material: None,
+            custom_attributes: Vec::new(),
+        }
+    }
+
+    /// Adds a grid of `columns` by `rows` quads. `position_normal` is given texture coordinates
+    /// going right and down the grid as seen from its front.
+    fn add_grid(
+        &mut self,
+        columns: u32,
+        rows: u32,
+        position_normal: impl Fn(Vec2) -> (Vec3, Vec3),
+    ) {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+                let (position, normal) = position_normal(uv);
+                self.vertices.push(Vertex::new(position, normal, uv));
+            }
+        }
+
+        let row_len = columns + 1;
+        for row in 0..rows {
+            for column in 0..columns {
+                let top_left = first + row * row_len + column;
+                let (top_right, bottom_left) = (top_left + 1, top_left + row_len);
+                let bottom_right = bottom_left + 1;
+                // Same winding as the quad.
+                self.indices.extend([
+                    top_left,
+                    bottom_left,
+                    top_right,
+                    top_right,
+                    bottom_left,
+                    bottom_right,
+                ]);
+            }
+        }
+    }
+
+    /// A simpler version of the submesh, with about `ratio` of its triangles. Edges are collapsed
+    /// where it changes the shape the least, open edges like texture seams are kept.
+    pub fn simplified(&self, ratio: f32) -> Submesh {
+        let triangle_count = (self.indices.len() / 3) as f32 * ratio.clamp(0.0, 1.0);
+        mesh_simplification::simplify(self, triangle_count as usize)
+    }
+
+    /// Computes smooth normals by averaging the normals of the triangles around each vertex,
+    /// weighted by their area, replacing the existing ones.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let indices = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+            let [p0, p1, p2] = indices.map(|i| Vec3::from(self.vertices[i].position));
+            // Front faces wind clockwise around their normal in our left handed system.
+            let normal = (p2 - p0).cross(p1 - p0);
+            for i in indices {
+                normals[i] += normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize_or_zero().to_array();
+        }
+    }
+
+    /// Computes the tangents of the vertices from the positions and texture coordinates of their
+    /// triangles, replacing the existing ones.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let indices = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+            let [p0, p1, p2] = indices.map(|i| Vec3::from(self.vertices[i].position));
+            // Normal maps expect the bitangent to point up in the image, towards decreasing v.
+            let [uv0, uv1, uv2] =
+                indices.map(|i| Vec2::from(self.vertices[i].uv) * Vec2::new(1.0, -1.0));
+
+            let (edge1, edge2) = (p1 - p0, p2 - p0);
+            let (delta_uv1, delta_uv2) = (uv1 - uv0, uv2 - uv0);
+            let det = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+            if det == 0.0 {
+                continue;
+            }
+            let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / det;
+            let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / det;
+            for i in indices {
+                tangents[i] += tangent;
+                bitangents[i] += bitangent;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = Vec3::from(vertex.normal);
+            let tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.tangent = tangent.extend(handedness).to_array();
+        }
+    }
 }
 
 impl Mesh {
@@ -22,17 +148,323 @@ impl Mesh {
         // 2---3       -1       1
 
         let normal = Vec3::NEG_Z;
+        let mut submesh = Submesh {
+            vertices: vec![
+                Vertex::new((-0.5, 0.5, 0.0).into(), normal, Vec2::new(0.0, 0.0)),
+                Vertex::new((0.5, 0.5, 0.0).into(), normal, Vec2::new(1.0, 0.0)),
+                Vertex::new((-0.5, -0.5, 0.0).into(), normal, Vec2::new(0.0, 1.0)),
+                Vertex::new((0.5, -0.5, 0.0).into(), normal, Vec2::new(1.0, 1.0)),
+            ],
+            indices: vec![0, 2, 1, 1, 2, 3],
+            material: None,
+            custom_attributes: Vec::new(),
+        };
+        submesh.generate_tangents();
+        Self {
+            submeshes: vec![submesh],
+            lods: Vec::new(),
+        }
+    }
+
+    /// A cube centered on the origin, with its own vertices on each face for hard edges.
+    pub fn cube(size: f32) -> Self {
+        let mut submesh = Submesh::new_empty();
+        // Each face's normal and the direction that is up when looking at it from outside.
+        let faces = [
+            (Vec3::X, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Y),
+            (Vec3::Y, Vec3::Z),
+            (Vec3::NEG_Y, Vec3::NEG_Z),
+            (Vec3::Z, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::Y),
+        ];
+        for (normal, up) in faces {
+            let right = normal.cross(up);
+            submesh.add_grid(1, 1, |uv| {
+                let position = (normal * 0.5 + right * (uv.x - 0.5) + up * (0.5 - uv.y)) * size;
+                (position, normal)
+            });
+        }
+        submesh.generate_tangents();
+        Self {
+            submeshes: vec![submesh],
+            lods: Vec::new(),
+        }
+    }
+
+    /// A sphere centered on the origin, with `rings` from pole to pole and `sectors` around the
+    /// y axis. Texture coordinates are equirectangular.
+    pub fn uv_sphere(radius: f32, rings: u32, sectors: u32) -> Self {
+        let (rings, sectors) = (rings.max(2), sectors.max(3));
+        let mut submesh = Submesh::new_empty();
+        submesh.add_grid(sectors, rings, |uv| {
+            let (sin_theta, cos_theta) = (uv.y * PI).sin_cos();
+            let (sin_phi, cos_phi) = (uv.x * TAU).sin_cos();
+            let normal = Vec3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi);
+            (normal * radius, normal)
+        });
+        // In the grid's first and last rows, one triangle of each quad has two corners on the
+        // pole and is degenerate.
+        let row_triangles = 2 * sectors as usize;
+        submesh.indices = submesh
+            .indices
+            .chunks_exact(3)
+            .enumerate()
+            .filter(|&(i, _)| {
+                let (row, second_of_quad) = (i / row_triangles, i % 2 == 1);
+                let on_top_pole = row == 0 && !second_of_quad;
+                let on_bottom_pole = row == rings as usize - 1 && second_of_quad;
+                !on_top_pole && !on_bottom_pole
+            })
+            .flat_map(|(_, triangle)| triangle.iter().copied())
+            .collect();
+        submesh.generate_tangents();
+        Self {
+            submeshes: vec![submesh],
+            lods: Vec::new(),
+        }
+    }
+
+    /// A square in the xz plane facing up, centered on the origin, cut `subdivisions` times along
+    /// each side.
+    pub fn plane(size: f32, subdivisions: u32) -> Self {
+        let mut submesh = Submesh::new_empty();
+        let quads = subdivisions + 1;
+        submesh.add_grid(quads, quads, |uv| {
+            let position = Vec3::new(uv.x - 0.5, 0.0, 0.5 - uv.y) * size;
+            (position, Vec3::Y)
+        });
+        submesh.generate_tangents();
         Self {
-            submeshes: vec![Submesh {
-                vertices: vec![
-                    Vertex::new((-0.5, 0.5, 0.0).into(), normal, Vec2::new(0.0, 0.0)),
-                    Vertex::new((0.5, 0.5, 0.0).into(), normal, Vec2::new(1.0, 0.0)),
-                    Vertex::new((-0.5, -0.5, 0.0).into(), normal, Vec2::new(0.0, 1.0)),
-                    Vertex::new((0.5, -0.5, 0.0).into(), normal, Vec2::new(1.0, 1.0)),
-                ],
-                indices: vec![0, 2, 1, 1, 2, 3],
-                material: None,
-            }],
+            submeshes: vec![submesh],
+            lods: Vec::new(),
         }
     }
+
+    /// A capped cylinder centered on the origin, along the y axis.
+    pub fn cylinder(radius: f32, height: f32, sectors: u32) -> Self {
+        let sectors = sectors.max(3);
+        let mut submesh = Submesh::new_empty();
+        let around = |u: f32| {
+            let (sin_phi, cos_phi) = (u * TAU).sin_cos();
+            Vec3::new(sin_phi, 0.0, -cos_phi)
+        };
+        submesh.add_grid(sectors, 1, |uv| {
+            let normal = around(uv.x);
+            let position = normal * radius + Vec3::Y * height * (0.5 - uv.y);
+            (position, normal)
+        });
+
+        for normal in [Vec3::Y, Vec3::NEG_Y] {
+            let center = submesh.vertices.len() as u32;
+            let cap_center = normal * height * 0.5;
+            submesh
+                .vertices
+                .push(Vertex::new(cap_center, normal, Vec2::splat(0.5)));
+            for i in 0..=sectors {
+                let direction = around(i as f32 / sectors as f32);
+                let uv = Vec2::new(0.5 + direction.x * 0.5, 0.5 - direction.z * normal.y * 0.5);
+                submesh
+                    .vertices
+                    .push(Vertex::new(cap_center + direction * radius, normal, uv));
+            }
+            for i in 0..sectors {
+                let (a, b) = (center + 1 + i, center + 2 + i);
+                // Seen from its outside, the bottom cap winds the other way around.
+                if normal == Vec3::Y {
+                    submesh.indices.extend([center, a, b]);
+                } else {
+                    submesh.indices.extend([center, b, a]);
+                }
+            }
+        }
+        submesh.generate_tangents();
+        Self {
+            submeshes: vec![submesh],
+            lods: Vec::new(),
+        }
+    }
+
+    /// Adds a level of detail, drawn below `screen_size`. See [`MeshLod::screen_size`].
+    pub fn with_lod(mut self, lod: Mesh, screen_size: f32) -> Self {
+        self.lods.push(MeshLod {
+            submeshes: lod.submeshes,
+            screen_size,
+        });
+        self
+    }
+
+    /// Replaces the levels of detail by up to `count` simplified versions of the mesh, each with
+    /// half the triangles of the previous one, drawn below half its screen size, starting from
+    /// half the screen's height. Stops early once the mesh can't get any simpler.
+    pub fn generate_lods(&mut self, count: u32) {
+        let index_count =
+            |submeshes: &[Submesh]| submeshes.iter().map(|s| s.indices.len()).sum::<usize>();
+        let mut lods: Vec<MeshLod> = Vec::new();
+        for level in 1..=count {
+            let previous = lods.last().map_or(&self.submeshes, |lod| &lod.submeshes);
+            let submeshes: Vec<Submesh> = previous.iter().map(|s| s.simplified(0.5)).collect();
+            if index_count(&submeshes) == index_count(previous) {
+                break;
+            }
+            lods.push(MeshLod {
+                submeshes,
+                screen_size: 0.5f32.powi(level as i32),
+            });
+        }
+        self.lods = lods;
+    }
+
+    /// Bounds of the vertices of every submesh, None if there are no vertices.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(
+            self.submeshes
+                .iter()
+                .flat_map(|submesh| &submesh.vertices)
+                .map(|vertex| Vec3::from(vertex.position)),
+        )
+    }
+
+    /// Bakes each transform into its mesh's vertices and combines everything into a single mesh,
+    /// with one submesh per material.
+    pub fn merge(meshes: &[(Mesh, Affine3A)]) -> Self {
+        let mut submeshes: Vec<Submesh> = Vec::new();
+
+        for (mesh, transform) in meshes {
+            let normal_matrix = transform.matrix3.inverse().transpose();
+            // Mirroring transforms flip the triangles' winding, and the tangents' handedness.
+            let flip_winding = transform.matrix3.determinant() < 0.0;
+            let handedness = if flip_winding { -1.0 } else { 1.0 };
+
+            for submesh in &mesh.submeshes {
+                let merged = match submeshes
+                    .iter_mut()
+                    .position(|merged| merged.material == submesh.material)
+                {
+                    Some(index) => &mut submeshes[index],
+                    None => {
+                        submeshes.push(Submesh {
+                            vertices: Vec::new(),
+                            indices: Vec::new(),
+                            material: submesh.material,
+                            custom_attributes: Vec::new(),
+                        });
+                        submeshes.last_mut().unwrap()
+                    }
+                };
+
+                let index_offset = merged.vertices.len() as u32;
+                // Submeshes without custom attributes get zeros if merged with some that have
+                // them.
+                if !submesh.custom_attributes.is_empty() || !merged.custom_attributes.is_empty() {
+                    let attributes = &mut merged.custom_attributes;
+                    attributes.resize(index_offset as usize, [0.0; 4]);
+                    attributes.extend_from_slice(&submesh.custom_attributes);
+                    attributes.resize(index_offset as usize + submesh.vertices.len(), [0.0; 4]);
+                }
+                merged
+                    .vertices
+                    .extend(submesh.vertices.iter().map(|vertex| {
+                        let position = transform.transform_point3(vertex.position.into());
+                        let normal =
+                            (normal_matrix * Vec3::from(vertex.normal)).normalize_or_zero();
+                        let tangent = Vec4::from(vertex.tangent);
+                        let tangent = (transform.matrix3 * tangent.truncate())
+                            .normalize_or_zero()
+                            .extend(tangent.w * handedness);
+                        Vertex {
+                            position: position.to_array(),
+                            normal: normal.to_array(),
+                            tangent: tangent.to_array(),
+                            ..*vertex
+                        }
+                    }));
+                for triangle in submesh.indices.chunks_exact(3) {
+                    let triangle = if flip_winding {
+                        [triangle[0], triangle[2], triangle[1]]
+                    } else {
+                        [triangle[0], triangle[1], triangle[2]]
+                    };
+                    merged
+                        .indices
+                        .extend(triangle.iter().map(|index| index + index_offset));
+                }
+            }
+        }
+
+        Self {
+            submeshes,
+            lods: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Vertex and index counts of the mesh's single submesh, after checking that its indices are
+    /// in range and its normals unit length.
+    fn checked_counts(mesh: &Mesh) -> (usize, usize) {
+        let [submesh] = mesh.submeshes.as_slice() else {
+            panic!("expected a single submesh");
+        };
+        for vertex in &submesh.vertices {
+            let length = Vec3::from(vertex.normal).length();
+            assert!((length - 1.0).abs() < 1e-5, "normal of length {length}");
+        }
+        let vertex_count = submesh.vertices.len();
+        assert!(submesh.indices.iter().all(|&i| (i as usize) < vertex_count));
+        assert_eq!(submesh.indices.len() % 3, 0);
+        (vertex_count, submesh.indices.len())
+    }
+
+    #[test]
+    fn quad() {
+        assert_eq!(checked_counts(&Mesh::quad()), (4, 6));
+    }
+
+    #[test]
+    fn cube() {
+        assert_eq!(checked_counts(&Mesh::cube(2.0)), (6 * 4, 6 * 6));
+    }
+
+    #[test]
+    fn uv_sphere() {
+        // Each ring of quads has two triangles per sector, minus one per sector at either pole.
+        assert_eq!(
+            checked_counts(&Mesh::uv_sphere(1.0, 8, 16)),
+            (9 * 17, (2 * 8 * 16 - 2 * 16) * 3)
+        );
+        // Clamped to 2 rings and 3 sectors.
+        assert_eq!(
+            checked_counts(&Mesh::uv_sphere(1.0, 0, 0)),
+            (3 * 4, (2 * 2 * 3 - 2 * 3) * 3)
+        );
+
+        let submesh = &Mesh::uv_sphere(1.0, 8, 16).submeshes[0];
+        for triangle in submesh.indices.chunks_exact(3) {
+            let [p0, p1, p2] =
+                [0, 1, 2].map(|i| Vec3::from(submesh.vertices[triangle[i] as usize].position));
+            assert!(
+                (p1 - p0).cross(p2 - p0).length() > 1e-6,
+                "degenerate triangle"
+            );
+        }
+    }
+
+    #[test]
+    fn plane() {
+        assert_eq!(checked_counts(&Mesh::plane(1.0, 0)), (4, 6));
+        assert_eq!(checked_counts(&Mesh::plane(1.0, 3)), (5 * 5, 4 * 4 * 6));
+    }
+
+    #[test]
+    fn cylinder() {
+        // The side is a strip of quads, each cap a fan around its center.
+        assert_eq!(
+            checked_counts(&Mesh::cylinder(1.0, 2.0, 12)),
+            (2 * 13 + 2 * (1 + 13), 12 * 6 + 2 * 12 * 3)
+        );
+    }
 }
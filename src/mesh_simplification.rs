@@ -0,0 +1,311 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use glam::{DMat4, Vec3};
+
+use crate::Submesh;
+
+/// Collapses edges of the submesh, cheapest first as measured by quadric error metrics, until
+/// it has `target_triangle_count` triangles or no edge can be collapsed anymore.
+/// https://www.cs.cmu.edu/~./garland/Papers/quadrics.pdf
+///
+/// Vertices are only ever moved onto one of their neighbors, so that their attributes need no
+/// interpolating. Vertices on open edges, like texture seams, stay where they are.
+pub fn simplify(submesh: &Submesh, target_triangle_count: usize) -> Submesh {
+    let positions: Vec<Vec3> = submesh
+        .vertices
+        .iter()
+        .map(|vertex| Vec3::from(vertex.position))
+        .collect();
+    let mut triangles: Vec<Option<[usize; 3]>> = submesh
+        .indices
+        .chunks_exact(3)
+        .map(|t| Some([t[0] as usize, t[1] as usize, t[2] as usize]))
+        .collect();
+
+    let mut vertex_triangles = vec![Vec::new(); positions.len()];
+    let mut quadrics = vec![DMat4::ZERO; positions.len()];
+    let mut edge_counts: HashMap<(usize, usize), u32> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        let triangle = triangle.unwrap();
+        let quadric = plane_quadric(triangle.map(|i| positions[i]));
+        for (i, &vertex) in triangle.iter().enumerate() {
+            vertex_triangles[vertex].push(t);
+            quadrics[vertex] += quadric;
+            let next = triangle[(i + 1) % 3];
+            *edge_counts
+                .entry((vertex.min(next), vertex.max(next)))
+                .or_default() += 1;
+        }
+    }
+    let mut locked = vec![false; positions.len()];
+    for (&(a, b), &count) in &edge_counts {
+        if count != 2 {
+            locked[a] = true;
+            locked[b] = true;
+        }
+    }
+
+    // Costs get stale as vertices collapse, those with an outdated version are skipped.
+    let mut versions = vec![0u32; positions.len()];
+    let mut collapses = BinaryHeap::new();
+    let push_collapses = |from: usize,
+                          collapses: &mut BinaryHeap<Collapse>,
+                          triangles: &[Option<[usize; 3]>],
+                          vertex_triangles: &[Vec<usize>],
+                          quadrics: &[DMat4],
+                          versions: &[u32]| {
+        for to in neighbors(from, triangles, vertex_triangles) {
+            for (from, to) in [(from, to), (to, from)] {
+                if locked[from] {
+                    continue;
+                }
+                let quadric = quadrics[from] + quadrics[to];
+                let position = positions[to].as_dvec3().extend(1.0);
+                collapses.push(Collapse {
+                    cost: position.dot(quadric * position),
+                    from,
+                    to,
+                    versions: (versions[from], versions[to]),
+                });
+            }
+        }
+    };
+    for vertex in 0..positions.len() {
+        push_collapses(
+            vertex,
+            &mut collapses,
+            &triangles,
+            &vertex_triangles,
+            &quadrics,
+            &versions,
+        );
+    }
+
+    let mut triangle_count = triangles.len();
+    while triangle_count > target_triangle_count {
+        let Some(collapse) = collapses.pop() else {
+            break;
+        };
+        let Collapse { from, to, .. } = collapse;
+        if collapse.versions != (versions[from], versions[to])
+            || !can_collapse(from, to, &positions, &triangles, &vertex_triangles)
+        {
+            continue;
+        }
+
+        for t in std::mem::take(&mut vertex_triangles[from]) {
+            let Some(triangle) = triangles[t] else {
+                continue;
+            };
+            if triangle.contains(&to) {
+                triangles[t] = None;
+                triangle_count -= 1;
+            } else {
+                triangles[t] =
+                    Some(triangle.map(|vertex| if vertex == from { to } else { vertex }));
+                vertex_triangles[to].push(t);
+            }
+        }
+        vertex_triangles[to].retain(|&t| triangles[t].is_some());
+        quadrics[to] = quadrics[to] + quadrics[from];
+        versions[from] += 1;
+        versions[to] += 1;
+        push_collapses(
+            to,
+            &mut collapses,
+            &triangles,
+            &vertex_triangles,
+            &quadrics,
+            &versions,
+        );
+    }
+
+    // Only keep the vertices still in use.
+    let mut remap = vec![None; positions.len()];
+    let mut simplified = Submesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        material: submesh.material,
+        custom_attributes: Vec::new(),
+    };
+    for &vertex in triangles.iter().flatten().flatten() {
+        let index = *remap[vertex].get_or_insert_with(|| {
+            simplified.vertices.push(submesh.vertices[vertex]);
+            if let Some(attribute) = submesh.custom_attributes.get(vertex) {
+                simplified.custom_attributes.push(*attribute);
+            }
+            simplified.vertices.len() as u32 - 1
+        });
+        simplified.indices.push(index);
+    }
+    simplified
+}
+
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+// Ordered by cost, cheapest first out of the max heap.
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+/// Squared distance to the plane of the triangle, weighted by its area.
+fn plane_quadric([p0, p1, p2]: [Vec3; 3]) -> DMat4 {
+    let cross = (p1 - p0).cross(p2 - p0).as_dvec3();
+    let area = cross.length() * 0.5;
+    let normal = cross.normalize_or_zero();
+    let plane = normal.extend(-normal.dot(p0.as_dvec3()));
+    let plane_outer = |x: f64| plane * x;
+    DMat4::from_cols(
+        plane_outer(plane.x),
+        plane_outer(plane.y),
+        plane_outer(plane.z),
+        plane_outer(plane.w),
+    ) * area
+}
+
+fn neighbors(
+    vertex: usize,
+    triangles: &[Option<[usize; 3]>],
+    vertex_triangles: &[Vec<usize>],
+) -> HashSet<usize> {
+    vertex_triangles[vertex]
+        .iter()
+        .filter_map(|&t| triangles[t])
+        .flatten()
+        .filter(|&neighbor| neighbor != vertex)
+        .collect()
+}
+
+/// Whether moving `from` onto `to` keeps the surface manifold and doesn't flip any triangle.
+fn can_collapse(
+    from: usize,
+    to: usize,
+    positions: &[Vec3],
+    triangles: &[Option<[usize; 3]>],
+    vertex_triangles: &[Vec<usize>],
+) -> bool {
+    // The edge's two triangles must be the only ones the vertices share neighbors through,
+    // otherwise the surface would fold onto itself.
+    let shared_neighbors = neighbors(from, triangles, vertex_triangles)
+        .intersection(&neighbors(to, triangles, vertex_triangles))
+        .count();
+    if shared_neighbors > 2 {
+        return false;
+    }
+
+    let normal = |triangle: [usize; 3], moved: Option<usize>| {
+        let [p0, p1, p2] = triangle.map(|vertex| match moved {
+            Some(moved) if vertex == from => positions[moved],
+            _ => positions[vertex],
+        });
+        (p1 - p0).cross(p2 - p0).normalize_or_zero()
+    };
+    vertex_triangles[from]
+        .iter()
+        .filter_map(|&t| triangles[t])
+        .filter(|triangle| !triangle.contains(&to))
+        .all(|triangle| normal(triangle, None).dot(normal(triangle, Some(to))) > 0.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Mesh;
+
+    use super::*;
+
+    fn triangles(submesh: &Submesh) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        submesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]].map(|i| Vec3::from(submesh.vertices[i as usize].position)))
+    }
+
+    /// Simplifies the submesh down to `target_triangle_count` and checks that it got there, that
+    /// the vertices on open edges didn't move and that no triangle turned away from `outward`.
+    fn check_simplify(
+        submesh: &Submesh,
+        target_triangle_count: usize,
+        outward: fn([Vec3; 3]) -> Vec3,
+    ) {
+        let simplified = simplify(submesh, target_triangle_count);
+        assert!(simplified.indices.len() / 3 <= target_triangle_count);
+        assert!(simplified.indices.len() / 3 > 0);
+
+        let mut edge_counts: HashMap<(u32, u32), u32> = HashMap::new();
+        for t in submesh.indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (t[i], t[(i + 1) % 3]);
+                *edge_counts.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        let open_vertices = edge_counts
+            .iter()
+            .filter(|(_, &count)| count != 2)
+            .flat_map(|(&(a, b), _)| [a, b]);
+        for vertex in open_vertices {
+            let position = submesh.vertices[vertex as usize].position;
+            assert!(
+                simplified.vertices.iter().any(|v| v.position == position),
+                "open edge vertex at {position:?} moved"
+            );
+        }
+
+        let facing = |triangle: [Vec3; 3]| {
+            let [p0, p1, p2] = triangle;
+            (p1 - p0).cross(p2 - p0).dot(outward(triangle))
+        };
+        let winding = facing(triangles(submesh).next().unwrap()).signum();
+        assert!(triangles(submesh).all(|t| facing(t) * winding > 0.0));
+        assert!(
+            triangles(&simplified).all(|t| facing(t) * winding > 0.0),
+            "triangle flipped"
+        );
+    }
+
+    #[test]
+    fn plane() {
+        let mesh = Mesh::plane(2.0, 7);
+        check_simplify(&mesh.submeshes[0], 8 * 8 * 2 / 2, |_| Vec3::Y);
+    }
+
+    #[test]
+    fn uv_sphere() {
+        let mesh = Mesh::uv_sphere(1.0, 16, 32);
+        let triangle_count = mesh.submeshes[0].indices.len() / 3;
+        check_simplify(&mesh.submeshes[0], triangle_count / 4, |[p0, p1, p2]| {
+            p0 + p1 + p2
+        });
+    }
+
+    #[test]
+    fn cube_has_no_lods() {
+        // Every face has its own vertices, so all of them are on open edges and locked.
+        let mut mesh = Mesh::cube(1.0);
+        mesh.generate_lods(3);
+        assert!(mesh.lods.is_empty());
+    }
+}
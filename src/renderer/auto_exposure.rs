@@ -0,0 +1,249 @@
+use crate::{
+    arena::Handle, asset_server::AssetChanges, shader_source::ShaderSource, AssetServer, Timestamp,
+};
+
+use super::{
+    backend::{Backend, ShowTextureUniform},
+    render_graph::{PassContext, PostPass, Slot},
+    visual_server::RenderTarget,
+};
+
+pub struct AutoExposure {
+    pipeline: wgpu::ComputePipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    uniform: AutoExposureUniform,
+    uniform_buffer: wgpu::Buffer,
+    exposure_buffer: wgpu::Buffer,
+    shader_source: Handle<ShaderSource>,
+    shader: wgpu::ShaderModule,
+    last_update: Timestamp,
+}
+
+impl AutoExposure {
+    pub fn new(
+        render_target: &RenderTarget,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) -> Self {
+        let shader_source =
+            asset_server.load::<ShaderSource>("src/renderer/shaders/auto_exposure.wgsl");
+        let shader = backend.create_shader_module(
+            "auto exposure shader",
+            asset_server.get(shader_source).source(),
+        );
+
+        let bind_group_layout =
+            backend
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("auto exposure bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+
+        let pipeline_layout =
+            backend
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("auto exposure pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let uniform = AutoExposureUniform::default();
+        let uniform_buffer = backend.create_uniform_buffer(uniform);
+        let exposure_buffer = backend.create_storage_buffer(1.0f32);
+
+        let bind_group = build_bind_group(
+            &bind_group_layout,
+            &uniform_buffer,
+            &exposure_buffer,
+            render_target,
+            backend,
+        );
+        let pipeline = build_pipeline(&pipeline_layout, &shader, backend);
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            bind_group_layout,
+            bind_group,
+            uniform,
+            uniform_buffer,
+            exposure_buffer,
+            shader_source,
+            shader,
+            last_update: Timestamp::now(),
+        }
+    }
+}
+
+impl PostPass for AutoExposure {
+    fn reads(&self) -> &[Slot] {
+        &[Slot::Color]
+    }
+
+    fn writes(&self) -> &[Slot] {
+        &[Slot::Exposure]
+    }
+
+    fn update_render_target(&mut self, render_target: &RenderTarget, backend: &mut Backend) {
+        self.bind_group = build_bind_group(
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.exposure_buffer,
+            render_target,
+            backend,
+        );
+    }
+
+    fn notify_asset_changes(
+        &mut self,
+        changes: &AssetChanges,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) {
+        if changes.contains(self.shader_source) {
+            let source = asset_server.get(self.shader_source);
+            self.shader = backend.create_shader_module("auto exposure shader", source.source());
+
+            self.pipeline = build_pipeline(&self.pipeline_layout, &self.shader, backend);
+        }
+    }
+
+    /// Measures the average luminance of the render target and adapts its exposure towards it.
+    /// The adapted exposure is copied into the render target's uniform, to be applied before tone mapping.
+    fn render(&mut self, context: &mut PassContext) {
+        let PassContext {
+            encoder,
+            render_target,
+            backend,
+            ..
+        } = context;
+        self.uniform.delta_time = self.last_update.seconds_since() as f32;
+        self.last_update = Timestamp::now();
+        backend.update_uniform_buffer(&self.uniform_buffer, self.uniform);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("auto exposure compute pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+
+        encoder.copy_buffer_to_buffer(
+            &self.exposure_buffer,
+            0,
+            &render_target.backend_uniform_buffer,
+            ShowTextureUniform::EXPOSURE_OFFSET,
+            std::mem::size_of::<f32>() as wgpu::BufferAddress,
+        );
+    }
+
+    fn vram_usage(&self) -> u64 {
+        self.uniform_buffer.size() + self.exposure_buffer.size()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct AutoExposureUniform {
+    delta_time: f32,
+    adaptation_speed: f32,
+    key_value: f32,
+    min_exposure: f32,
+    max_exposure: f32,
+    _padding: [f32; 3],
+}
+
+impl Default for AutoExposureUniform {
+    fn default() -> Self {
+        Self {
+            delta_time: 0.0,
+            adaptation_speed: 1.5,
+            key_value: 0.18, // Middle grey
+            min_exposure: 0.1,
+            max_exposure: 10.0,
+            _padding: Default::default(),
+        }
+    }
+}
+
+fn build_bind_group(
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    exposure_buffer: &wgpu::Buffer,
+    render_target: &RenderTarget,
+    backend: &mut Backend,
+) -> wgpu::BindGroup {
+    backend
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("auto exposure bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(render_target.texture.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: exposure_buffer.as_entire_binding(),
+                },
+            ],
+        })
+}
+
+fn build_pipeline(
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    backend: &mut Backend,
+) -> wgpu::ComputePipeline {
+    backend
+        .device
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("auto exposure pipeline"),
+            layout: Some(layout),
+            module: shader,
+            entry_point: "cs_main",
+        })
+}
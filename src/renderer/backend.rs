@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
+#[cfg(not(target_arch = "wasm32"))]
+use asset_image::Image;
 use glam::UVec2;
-use pollster::FutureExt;
+use log::warn;
 use wgpu::{util::DeviceExt, BindGroupDescriptor};
 
-use super::visual_server::RenderTarget;
+use super::visual_server::{PresentMode, RenderTarget, ToneMapping, UpscaleFilter};
 
 // Note:
 // Interesting reads
@@ -13,13 +15,22 @@ use super::visual_server::RenderTarget;
 
 pub struct Backend {
     render_size: UVec2,
+    anisotropy_clamp: u16,
+    /// 1 on backends without anisotropic filtering.
+    max_anisotropy_clamp: u16,
+    samplers: HashMap<SamplerKey, Arc<wgpu::Sampler>>,
     //
-    pub surface: wgpu::Surface<'static>,
+    /// None when headless, frames are then only read back.
+    pub surface: Option<wgpu::Surface<'static>>,
     pub surface_config: wgpu::SurfaceConfiguration,
+    supported_present_modes: Vec<wgpu::PresentMode>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     //
     show_texture_pipeline: wgpu::RenderPipeline,
+    /// Like `show_texture_pipeline`, for [`Backend::IMAGE_FORMAT`] rather than the surface format.
+    #[cfg(not(target_arch = "wasm32"))]
+    show_texture_image_pipeline: wgpu::RenderPipeline,
     pub show_texture_bind_group_layout: wgpu::BindGroupLayout,
     //
     material_bind_group_layout: wgpu::BindGroupLayout,
@@ -29,27 +40,56 @@ pub struct Backend {
 impl Backend {
     // NOTE: Read up on "reversed depth buffer trick". Might be interesting.
     pub const DEPTH_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // Shadow maps are sampled as a regular texture array by the mesh shader, which can't be
+    // multisampled. Soften shadows through the shadow filter radius setting instead.
+    pub const SHADOW_MAP_SAMPLE_COUNT: u32 = 1;
+    /// Frames read back by [`Backend::render_to_image`] are rendered in this format, whatever the
+    /// surface's is.
+    pub const IMAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+    /// Used when available, with a fallback otherwise.
+    const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER;
+
+    /// The browser's main thread can't block, so on the web this is awaited instead of blocked
+    /// on, through `wasm_bindgen_futures::spawn_local` for instance.
+    pub async fn new(window: &Arc<winit::window::Window>) -> Self {
+        let render_size = (window.inner_size().width, window.inner_size().height).into();
+        Self::new_with_window(Some(window), render_size).await
+    }
 
-    pub fn new(window: &Arc<winit::window::Window>) -> Self {
-        let _ = env_logger::try_init();
+    /// Renders without a window nor a surface, see [`Backend::render_to_image`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_headless(render_size: UVec2) -> Self {
+        Self::new_with_window(None, render_size.max(UVec2::ONE)).await
+    }
 
-        let render_size: UVec2 = (window.inner_size().width, window.inner_size().height).into();
+    async fn new_with_window(
+        window: Option<&Arc<winit::window::Window>>,
+        render_size: UVec2,
+    ) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = env_logger::try_init();
 
+        // The web has no environment variables to pick from. WebGPU is used where the browser
+        // supports it, WebGL otherwise.
+        #[cfg(not(target_arch = "wasm32"))]
+        let backends = wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all);
+        #[cfg(target_arch = "wasm32")]
+        let backends = wgpu::Backends::BROWSER_WEBGPU | wgpu::Backends::GL;
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all),
+            backends,
             ..Default::default()
         });
         // # Safety
         // The surface must not outlive the window that created it.
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = window.map(|window| instance.create_surface(window.clone()).unwrap());
 
         // An adapter represents an actual GPUxRendererAPI combo.
         let adapter: wgpu::Adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
+                compatible_surface: surface.as_ref(),
                 ..Default::default()
             })
-            .block_on()
+            .await
             .unwrap();
 
         println!(
@@ -60,24 +100,60 @@ impl Backend {
 
         // A device represents a logical graphics/compute device.
         // A queue is a handle to a command queue for a device, to which commands can be submitted.
+        // Downlevel backends like WebGL lack some features and go under the default limits, so
+        // features are only requested when available and limits are the adapter's own.
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER,
+                    required_features: adapter.features() & Self::OPTIONAL_FEATURES,
+                    required_limits: adapter.limits(),
                     ..Default::default()
                 },
                 None,
             )
-            .block_on()
+            .await
             .unwrap();
 
-        let surface_capabilities = surface.get_capabilities(&adapter);
-        let surface_format = surface_capabilities
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_capabilities.formats[0]);
+        // Integrated GPUs can support 16x anisotropy but pay a lot for it, and some downlevel
+        // backends don't support anisotropic filtering at all.
+        let max_anisotropy_clamp = if adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING)
+        {
+            16
+        } else {
+            1
+        };
+        let anisotropy_clamp = if adapter.get_info().device_type == wgpu::DeviceType::DiscreteGpu {
+            16
+        } else {
+            4
+        }
+        .min(max_anisotropy_clamp);
+
+        let (surface_format, alpha_mode, supported_present_modes) = match &surface {
+            Some(surface) => {
+                let surface_capabilities = surface.get_capabilities(&adapter);
+                let surface_format = surface_capabilities
+                    .formats
+                    .iter()
+                    .copied()
+                    .find(|f| f.is_srgb())
+                    .unwrap_or(surface_capabilities.formats[0]);
+                (
+                    surface_format,
+                    surface_capabilities.alpha_modes[0],
+                    surface_capabilities.present_modes,
+                )
+            }
+            // Nothing is presented, it's only the format of the images read back.
+            None => (
+                Self::IMAGE_FORMAT,
+                wgpu::CompositeAlphaMode::Opaque,
+                vec![wgpu::PresentMode::Fifo],
+            ),
+        };
 
         // A surface config is used to define how to create the surface's SurfaceTexture.
         let surface_config = wgpu::SurfaceConfiguration {
@@ -85,13 +161,15 @@ impl Backend {
             format: surface_format,
             width: render_size.x,
             height: render_size.y,
-            present_mode: wgpu::PresentMode::AutoVsync, // surface_capabilities.present_modes[0],
+            present_mode: wgpu::PresentMode::Fifo,
             desired_maximum_frame_latency: 2,
-            alpha_mode: surface_capabilities.alpha_modes[0],
+            alpha_mode,
             view_formats: vec![],
         };
 
-        surface.configure(&device, &surface_config);
+        if let Some(surface) = &surface {
+            surface.configure(&device, &surface_config);
+        }
 
         // Render pipeline stuff
         let material_bind_group_layout =
@@ -124,6 +202,36 @@ impl Backend {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             });
         let model_bind_group_layout =
@@ -131,10 +239,10 @@ impl Backend {
                 label: Some("model bind group layout"),
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
+                        has_dynamic_offset: true,
                         min_binding_size: None,
                     },
                     count: None,
@@ -184,47 +292,33 @@ impl Backend {
                 bind_group_layouts: &[&show_texture_bind_group_layout],
                 push_constant_ranges: &[],
             });
-        let show_texture_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("show texture pipeline"),
-                layout: Some(&show_texture_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleStrip,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-            });
+        let show_texture_pipeline = create_show_texture_pipeline(
+            &device,
+            &show_texture_pipeline_layout,
+            &shader,
+            surface_config.format,
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        let show_texture_image_pipeline = create_show_texture_pipeline(
+            &device,
+            &show_texture_pipeline_layout,
+            &shader,
+            Self::IMAGE_FORMAT,
+        );
 
         Self {
             render_size,
+            anisotropy_clamp,
+            max_anisotropy_clamp,
+            samplers: HashMap::new(),
             surface,
             surface_config,
+            supported_present_modes,
             device,
             queue,
             show_texture_pipeline,
+            #[cfg(not(target_arch = "wasm32"))]
+            show_texture_image_pipeline,
             show_texture_bind_group_layout,
             material_bind_group_layout,
             model_bind_group_layout,
@@ -235,6 +329,50 @@ impl Backend {
         self.render_size
     }
 
+    pub fn anisotropy_clamp(&self) -> u16 {
+        self.anisotropy_clamp
+    }
+
+    /// Only affects samplers created afterwards. Stays at 1 if the backend doesn't support
+    /// anisotropic filtering.
+    pub fn set_anisotropy_clamp(&mut self, anisotropy_clamp: u16) {
+        self.anisotropy_clamp = anisotropy_clamp.clamp(1, self.max_anisotropy_clamp);
+    }
+
+    pub fn max_frame_latency(&self) -> u32 {
+        self.surface_config.desired_maximum_frame_latency
+    }
+
+    /// How many frames can be queued for the GPU before presenting blocks. Lower means less
+    /// input latency, higher smoother frame pacing. Not every backend honors it.
+    pub fn set_max_frame_latency(&mut self, max_frame_latency: u32) {
+        self.surface_config.desired_maximum_frame_latency = max_frame_latency.max(1);
+        self.configure_surface();
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        match self.surface_config.present_mode {
+            wgpu::PresentMode::Mailbox => PresentMode::Mailbox,
+            wgpu::PresentMode::Immediate => PresentMode::Immediate,
+            _ => PresentMode::Fifo,
+        }
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        let present_mode = match present_mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        };
+        self.surface_config.present_mode = if self.supported_present_modes.contains(&present_mode) {
+            present_mode
+        } else {
+            warn!("{:?} present mode unsupported, using Fifo", present_mode);
+            wgpu::PresentMode::Fifo
+        };
+        self.configure_surface();
+    }
+
     pub fn set_render_size(&mut self, render_size: UVec2) {
         if render_size.x == 0 || render_size.y == 0 {
             return;
@@ -243,7 +381,13 @@ impl Backend {
         self.render_size = render_size;
         self.surface_config.width = render_size.x;
         self.surface_config.height = render_size.y;
-        self.surface.configure(&self.device, &self.surface_config);
+        self.configure_surface();
+    }
+
+    fn configure_surface(&self) {
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
+        }
     }
 
     pub fn create_shader_module(&mut self, label: &str, source: &str) -> wgpu::ShaderModule {
@@ -284,6 +428,32 @@ impl Backend {
             })
     }
 
+    pub fn create_storage_buffer(&mut self, data: impl Uniform) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("storage buffer"),
+                contents: bytemuck::cast_slice(&[data]),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            })
+    }
+
+    /// For uniforms bound at dynamic offsets, with `contents` holding all of them.
+    pub fn create_dynamic_uniform_buffer(&mut self, contents: &[u8]) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("dynamic uniform buffer"),
+                contents,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+    }
+
+    /// Dynamic offsets into uniform buffers must be multiples of this.
+    pub fn uniform_offset_alignment(&self) -> u64 {
+        self.device.limits().min_uniform_buffer_offset_alignment as u64
+    }
+
     pub fn update_uniform_buffer(&mut self, buffer: &wgpu::Buffer, uniform: impl Uniform) {
         self.queue
             .write_buffer(buffer, 0, bytemuck::cast_slice(&[uniform]));
@@ -293,9 +463,16 @@ impl Backend {
         &mut self,
         uniform_buffer: &wgpu::Buffer,
         base_color_texture: &wgpu::Texture,
+        metallic_roughness_texture: &wgpu::Texture,
+        normal_texture: &wgpu::Texture,
+        emissive_texture: &wgpu::Texture,
         sampler: &wgpu::Sampler,
     ) -> wgpu::BindGroup {
         let base_color_texture_view = base_color_texture.create_view(&Default::default());
+        let metallic_roughness_texture_view =
+            metallic_roughness_texture.create_view(&Default::default());
+        let normal_texture_view = normal_texture.create_view(&Default::default());
+        let emissive_texture_view = emissive_texture.create_view(&Default::default());
         self.device.create_bind_group(&BindGroupDescriptor {
             label: Some("material bind group"),
             layout: &self.material_bind_group_layout,
@@ -312,17 +489,38 @@ impl Backend {
                     binding: 2,
                     resource: wgpu::BindingResource::Sampler(sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&metallic_roughness_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&emissive_texture_view),
+                },
             ],
         })
     }
 
-    pub fn create_model_bind_group(&mut self, uniform_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+    /// Binds `uniform_size` bytes of the buffer, at the dynamic offset given when drawing.
+    pub fn create_model_bind_group(
+        &mut self,
+        uniform_buffer: &wgpu::Buffer,
+        uniform_size: u64,
+    ) -> wgpu::BindGroup {
         self.device.create_bind_group(&BindGroupDescriptor {
             label: Some("model bind group"),
             layout: &self.model_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(uniform_size),
+                }),
             }],
         })
     }
@@ -330,25 +528,58 @@ impl Backend {
     pub fn create_light_bind_group(
         &mut self,
         uniform_buffer: &wgpu::Buffer,
-        shadow_map: &wgpu::Texture,
+        shadow_maps: &[&wgpu::Texture],
         sampler: &wgpu::Sampler,
         layout: &wgpu::BindGroupLayout,
     ) -> wgpu::BindGroup {
-        let shadow_map_view = shadow_map.create_view(&Default::default());
+        let shadow_map_views: Vec<_> = shadow_maps
+            .iter()
+            .map(|shadow_map| shadow_map.create_view(&Default::default()))
+            .collect();
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ];
+        // One shadow map per cascade from binding 2 onward.
+        for (binding, view) in (2..).zip(&shadow_map_views) {
+            entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+        }
         self.device.create_bind_group(&BindGroupDescriptor {
             label: Some("light bind group"),
             layout,
+            entries: &entries,
+        })
+    }
+
+    pub fn create_skybox_bind_group(
+        &mut self,
+        cubemap: &wgpu::Texture,
+        sampler: &wgpu::Sampler,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        let cubemap_view = cubemap.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("skybox bind group"),
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: uniform_buffer.as_entire_binding(),
+                    resource: wgpu::BindingResource::TextureView(&cubemap_view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&shadow_map_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
                     resource: wgpu::BindingResource::Sampler(sampler),
                 },
             ],
@@ -411,8 +642,39 @@ impl Backend {
         )
     }
 
-    pub fn create_sampler(&mut self) -> wgpu::Sampler {
-        self.device.create_sampler(&wgpu::SamplerDescriptor {
+    /// Six layers of linear half float RGBA, to be viewed as a cube.
+    pub fn create_cubemap_texture(&mut self, size: u32, data: &[u8]) -> wgpu::Texture {
+        self.device.create_texture_with_data(
+            &self.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("cubemap texture"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::default(),
+            data,
+        )
+    }
+
+    /// Samplers are shared by everything asking for the same kind, they're only created once.
+    fn cached_sampler(&mut self, descriptor: &wgpu::SamplerDescriptor) -> Arc<wgpu::Sampler> {
+        self.samplers
+            .entry(SamplerKey::from(descriptor))
+            .or_insert_with(|| Arc::new(self.device.create_sampler(descriptor)))
+            .clone()
+    }
+
+    pub fn sampler(&mut self) -> Arc<wgpu::Sampler> {
+        self.cached_sampler(&wgpu::SamplerDescriptor {
             label: Some("sampler"),
             address_mode_u: wgpu::AddressMode::Repeat,
             address_mode_v: wgpu::AddressMode::Repeat,
@@ -420,13 +682,13 @@ impl Backend {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Linear,
-            anisotropy_clamp: 16,
+            anisotropy_clamp: self.anisotropy_clamp,
             ..Default::default()
         })
     }
 
-    pub fn create_sampler_non_filtering(&mut self) -> wgpu::Sampler {
-        self.device.create_sampler(&wgpu::SamplerDescriptor {
+    pub fn sampler_non_filtering(&mut self) -> Arc<wgpu::Sampler> {
+        self.cached_sampler(&wgpu::SamplerDescriptor {
             label: Some("non filtering sampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -438,16 +700,37 @@ impl Backend {
         })
     }
 
-    pub fn create_sampler_shadow_map(&mut self) -> wgpu::Sampler {
-        self.device.create_sampler(&wgpu::SamplerDescriptor {
+    /// Comparison sampler, which gives how much of the sampled texels pass `compare` against the
+    /// reference depth. Linear filtering blends the results of the four nearest texels.
+    /// Outside of the shadow map is lit, unless the backend can't clamp to a border color, in
+    /// which case the edges of the shadow map stretch out.
+    pub fn sampler_shadow_map(
+        &mut self,
+        filter: wgpu::FilterMode,
+        compare: wgpu::CompareFunction,
+    ) -> Arc<wgpu::Sampler> {
+        let (address_mode, border_color) = if self
+            .device
+            .features()
+            .contains(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
+        {
+            (
+                wgpu::AddressMode::ClampToBorder,
+                Some(wgpu::SamplerBorderColor::OpaqueWhite),
+            )
+        } else {
+            (wgpu::AddressMode::ClampToEdge, None)
+        };
+        self.cached_sampler(&wgpu::SamplerDescriptor {
             label: Some("shadow map sampler"),
-            address_mode_u: wgpu::AddressMode::ClampToBorder,
-            address_mode_v: wgpu::AddressMode::ClampToBorder,
-            address_mode_w: wgpu::AddressMode::ClampToBorder,
-            border_color: Some(wgpu::SamplerBorderColor::OpaqueWhite),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            border_color,
+            mag_filter: filter,
+            min_filter: filter,
             mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(compare),
             ..Default::default()
         })
     }
@@ -455,11 +738,114 @@ impl Backend {
     pub fn render(
         &mut self,
         render_3d: &RenderTarget,
-        render_2d: &RenderTarget,
+        render_2d: Option<&RenderTarget>,
     ) -> Result<(), wgpu::SurfaceError> {
-        let surface_texture = self.surface.get_current_texture()?;
+        // Headless, there's nothing to present to.
+        let Some(surface) = &self.surface else {
+            return Ok(());
+        };
+        let surface_texture = surface.get_current_texture()?;
         let surface_view = surface_texture.texture.create_view(&Default::default());
 
+        self.show_render_targets(
+            &self.show_texture_pipeline,
+            &surface_view,
+            render_3d,
+            render_2d,
+        );
+
+        surface_texture.present();
+
+        Ok(())
+    }
+
+    /// Shows the render targets like [`Backend::render`] does, into an image instead of the
+    /// surface. Waits for the GPU to be done with the frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_image(
+        &mut self,
+        render_3d: &RenderTarget,
+        render_2d: Option<&RenderTarget>,
+    ) -> Result<Image, String> {
+        let size = wgpu::Extent3d {
+            width: self.render_size.x,
+            height: self.render_size.y,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render to image texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::IMAGE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        self.show_render_targets(
+            &self.show_texture_image_pipeline,
+            &texture.create_view(&Default::default()),
+            render_3d,
+            render_2d,
+        );
+
+        // Rows of a texture copied to a buffer must be padded to a multiple of 256 bytes.
+        let block_size = Self::IMAGE_FORMAT.block_copy_size(None).unwrap();
+        let unpadded_bytes_per_row = size.width * block_size;
+        let bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("render to image buffer"),
+            size: bytes_per_row as u64 * size.height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("render to image encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| e.to_string())
+            .and_then(|result| result.map_err(|e| e.to_string()))
+            .map_err(|e| format!("failed to read back the rendered image: {}", e))?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * size.height) as usize);
+        for row in slice.get_mapped_range().chunks(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        buffer.unmap();
+        Image::from_rgba8(size.width, size.height, pixels).map_err(|e| e.to_string())
+    }
+
+    fn show_render_targets(
+        &self,
+        pipeline: &wgpu::RenderPipeline,
+        view: &wgpu::TextureView,
+        render_3d: &RenderTarget,
+        render_2d: Option<&RenderTarget>,
+    ) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -470,7 +856,7 @@ impl Backend {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("show texture render pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &surface_view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
@@ -481,20 +867,93 @@ impl Backend {
                 ..Default::default()
             });
 
-            render_pass.set_pipeline(&self.show_texture_pipeline);
+            render_pass.set_pipeline(pipeline);
 
             render_pass.set_bind_group(0, &render_3d.backend_bind_group, &[]);
             render_pass.draw(0..4, 0..1);
 
-            render_pass.set_bind_group(0, &render_2d.backend_bind_group, &[]);
-            render_pass.draw(0..4, 0..1);
+            if let Some(render_2d) = render_2d {
+                render_pass.set_bind_group(0, &render_2d.backend_bind_group, &[]);
+                render_pass.draw(0..4, 0..1);
+            }
         }
 
         self.queue.submit(Some(encoder.finish()));
+    }
+}
 
-        surface_texture.present();
+fn create_show_texture_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("show texture pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
 
-        Ok(())
+/// The parts of a sampler descriptor that tell samplers apart. Labels don't.
+#[derive(PartialEq, Eq, Hash)]
+struct SamplerKey {
+    address_modes: [wgpu::AddressMode; 3],
+    filters: [wgpu::FilterMode; 3],
+    lod_clamp: [u32; 2],
+    compare: Option<wgpu::CompareFunction>,
+    anisotropy_clamp: u16,
+    border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl From<&wgpu::SamplerDescriptor<'_>> for SamplerKey {
+    fn from(descriptor: &wgpu::SamplerDescriptor) -> Self {
+        Self {
+            address_modes: [
+                descriptor.address_mode_u,
+                descriptor.address_mode_v,
+                descriptor.address_mode_w,
+            ],
+            filters: [
+                descriptor.mag_filter,
+                descriptor.min_filter,
+                descriptor.mipmap_filter,
+            ],
+            lod_clamp: [
+                descriptor.lod_min_clamp.to_bits(),
+                descriptor.lod_max_clamp.to_bits(),
+            ],
+            compare: descriptor.compare,
+            anisotropy_clamp: descriptor.anisotropy_clamp,
+            border_color: descriptor.border_color,
+        }
     }
 }
 
@@ -508,4 +967,45 @@ impl<T> Vertexish for T where T: Clone + Copy + bytemuck::Pod + bytemuck::Zeroab
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ShowTextureUniform {
     pub tone_mapping: u32,
+    pub exposure: f32,
+    pub upscale_filter: u32,
+    /// Set by hand, unlike `exposure` which auto exposure overwrites.
+    pub manual_exposure: f32,
+}
+
+impl ShowTextureUniform {
+    pub const TONE_MAPPING_OFFSET: wgpu::BufferAddress = 0;
+    pub const EXPOSURE_OFFSET: wgpu::BufferAddress = std::mem::size_of::<u32>() as _;
+    pub const MANUAL_EXPOSURE_OFFSET: wgpu::BufferAddress = 3 * std::mem::size_of::<u32>() as u64;
+
+    pub fn new(
+        tone_mapping: ToneMapping,
+        manual_exposure: f32,
+        upscale_filter: UpscaleFilter,
+    ) -> Self {
+        Self {
+            tone_mapping: tone_mapping as u32,
+            exposure: 1.0,
+            upscale_filter: upscale_filter as u32,
+            manual_exposure,
+        }
+    }
+}
+
+/// Approximate amount of memory a texture takes, counting every mip level, layer and sample.
+pub fn texture_byte_size(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    // Formats like Depth24Plus have no defined size, assume 4 bytes.
+    let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+
+    let mip_levels_size: u64 = (0..texture.mip_level_count())
+        .map(|level| {
+            let width = (texture.width() >> level).max(1).div_ceil(block_width) as u64;
+            let height = (texture.height() >> level).max(1).div_ceil(block_height) as u64;
+            width * height * block_size
+        })
+        .sum();
+
+    mip_levels_size * texture.depth_or_array_layers() as u64 * texture.sample_count() as u64
 }
@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use glam::UVec2;
+
+use crate::{arena::Handle, asset_server::AssetChanges, shader_source::ShaderSource, AssetServer};
+
+use super::{
+    backend::{texture_byte_size, Backend},
+    fullscreen::{
+        build_pipeline, create_texture, draw_fullscreen, sampler_layout_entry, texture_layout_entry,
+    },
+    pipeline3d::ADDITIVE_BLENDING,
+    render_graph::{PassContext, PostPass, Slot},
+    visual_server::RenderTarget,
+};
+
+pub struct Bloom {
+    emissive_buffer: EmissiveBuffer,
+    blur_textures: [(wgpu::Texture, wgpu::TextureView); 2],
+    bind_groups: BindGroups,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Pipelines,
+    sampler: Arc<wgpu::Sampler>,
+    target_format: wgpu::TextureFormat,
+    shader_source: Handle<ShaderSource>,
+    shader: wgpu::ShaderModule,
+}
+
+impl Bloom {
+    pub const EMISSIVE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(
+        render_target: &RenderTarget,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) -> Self {
+        let shader_source = asset_server.load::<ShaderSource>("src/renderer/shaders/bloom.wgsl");
+        let shader =
+            backend.create_shader_module("bloom shader", asset_server.get(shader_source).source());
+
+        let bind_group_layout =
+            backend
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("bloom bind group layout"),
+                    entries: &[texture_layout_entry(0), sampler_layout_entry(1)],
+                });
+
+        let pipeline_layout =
+            backend
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("bloom pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let sampler = backend.sampler();
+
+        let emissive_buffer = EmissiveBuffer::new(render_target, backend);
+        let blur_textures = create_blur_textures(render_target.size, backend);
+        let bind_groups = BindGroups::new(
+            &emissive_buffer,
+            &blur_textures,
+            &bind_group_layout,
+            &sampler,
+            backend,
+        );
+
+        let target_format = render_target.color_format;
+        let pipelines = Pipelines::new(&pipeline_layout, &shader, target_format, backend);
+
+        Self {
+            emissive_buffer,
+            blur_textures,
+            bind_groups,
+            bind_group_layout,
+            pipeline_layout,
+            pipelines,
+            sampler,
+            target_format,
+            shader_source,
+            shader,
+        }
+    }
+
+    /// Attachment the 3d pipeline writes emissive and overbright light to, next to its color.
+    pub fn emissive_attachment(&self) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: &self.emissive_buffer.color_view,
+            resolve_target: self.emissive_buffer.resolve.as_ref().map(|(_, view)| view),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+}
+
+impl PostPass for Bloom {
+    fn reads(&self) -> &[Slot] {
+        &[Slot::Color, Slot::Emissive]
+    }
+
+    fn writes(&self) -> &[Slot] {
+        &[Slot::Color]
+    }
+
+    fn update_render_target(&mut self, render_target: &RenderTarget, backend: &mut Backend) {
+        self.emissive_buffer = EmissiveBuffer::new(render_target, backend);
+        self.blur_textures = create_blur_textures(render_target.size, backend);
+        self.bind_groups = BindGroups::new(
+            &self.emissive_buffer,
+            &self.blur_textures,
+            &self.bind_group_layout,
+            &self.sampler,
+            backend,
+        );
+
+        if self.target_format != render_target.color_format {
+            self.target_format = render_target.color_format;
+            self.pipelines = Pipelines::new(
+                &self.pipeline_layout,
+                &self.shader,
+                self.target_format,
+                backend,
+            );
+        }
+    }
+
+    fn notify_asset_changes(
+        &mut self,
+        changes: &AssetChanges,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) {
+        if changes.contains(self.shader_source) {
+            let source = asset_server.get(self.shader_source);
+            self.shader = backend.create_shader_module("bloom shader", source.source());
+
+            self.pipelines = Pipelines::new(
+                &self.pipeline_layout,
+                &self.shader,
+                self.target_format,
+                backend,
+            );
+        }
+    }
+
+    /// Blurs the emissive buffer and adds it on top of the render target.
+    fn render(&mut self, context: &mut PassContext) {
+        let PassContext {
+            encoder,
+            render_target,
+            ..
+        } = context;
+        draw_fullscreen(
+            encoder,
+            "bloom blur horizontal render pass",
+            &self.pipelines.blur_horizontal,
+            &self.bind_groups.emissive,
+            &self.blur_textures[0].1,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        );
+        draw_fullscreen(
+            encoder,
+            "bloom blur vertical render pass",
+            &self.pipelines.blur_vertical,
+            &self.bind_groups.blur_horizontal,
+            &self.blur_textures[1].1,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        );
+        draw_fullscreen(
+            encoder,
+            "bloom composite render pass",
+            &self.pipelines.composite,
+            &self.bind_groups.blur_vertical,
+            render_target.texture.view(),
+            wgpu::LoadOp::Load,
+        );
+    }
+
+    fn vram_usage(&self) -> u64 {
+        let emissive = &self.emissive_buffer;
+        texture_byte_size(&emissive.color)
+            + emissive
+                .resolve
+                .as_ref()
+                .map_or(0, |(texture, _)| texture_byte_size(texture))
+            + self
+                .blur_textures
+                .iter()
+                .map(|(texture, _)| texture_byte_size(texture))
+                .sum::<u64>()
+    }
+}
+
+struct EmissiveBuffer {
+    color: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    resolve: Option<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl EmissiveBuffer {
+    fn new(render_target: &RenderTarget, backend: &mut Backend) -> Self {
+        let (color, color_view) = create_texture(
+            "bloom emissive texture",
+            render_target.size,
+            render_target.sample_count,
+            Bloom::EMISSIVE_FORMAT,
+            backend,
+        );
+        let resolve = (render_target.sample_count > 1).then(|| {
+            create_texture(
+                "bloom emissive resolve texture",
+                render_target.size,
+                1,
+                Bloom::EMISSIVE_FORMAT,
+                backend,
+            )
+        });
+        Self {
+            color,
+            color_view,
+            resolve,
+        }
+    }
+
+    fn view(&self) -> &wgpu::TextureView {
+        match &self.resolve {
+            Some((_, resolve_view)) => resolve_view,
+            None => &self.color_view,
+        }
+    }
+}
+
+struct BindGroups {
+    emissive: wgpu::BindGroup,
+    blur_horizontal: wgpu::BindGroup,
+    blur_vertical: wgpu::BindGroup,
+}
+
+impl BindGroups {
+    fn new(
+        emissive_buffer: &EmissiveBuffer,
+        blur_textures: &[(wgpu::Texture, wgpu::TextureView); 2],
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        backend: &mut Backend,
+    ) -> Self {
+        let build = |view: &wgpu::TextureView| {
+            backend
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("bloom bind group"),
+                    layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                })
+        };
+        Self {
+            emissive: build(emissive_buffer.view()),
+            blur_horizontal: build(&blur_textures[0].1),
+            blur_vertical: build(&blur_textures[1].1),
+        }
+    }
+}
+
+struct Pipelines {
+    blur_horizontal: wgpu::RenderPipeline,
+    blur_vertical: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn new(
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        target_format: wgpu::TextureFormat,
+        backend: &mut Backend,
+    ) -> Self {
+        Self {
+            blur_horizontal: build_pipeline(
+                "bloom blur horizontal pipeline",
+                "fs_blur_horizontal",
+                Bloom::EMISSIVE_FORMAT,
+                wgpu::BlendState::REPLACE,
+                layout,
+                shader,
+                backend,
+            ),
+            blur_vertical: build_pipeline(
+                "bloom blur vertical pipeline",
+                "fs_blur_vertical",
+                Bloom::EMISSIVE_FORMAT,
+                wgpu::BlendState::REPLACE,
+                layout,
+                shader,
+                backend,
+            ),
+            composite: build_pipeline(
+                "bloom composite pipeline",
+                "fs_composite",
+                target_format,
+                ADDITIVE_BLENDING,
+                layout,
+                shader,
+                backend,
+            ),
+        }
+    }
+}
+
+// Blurring is done at half resolution, it's cheaper and spreads the glow further.
+fn create_blur_textures(
+    render_size: UVec2,
+    backend: &mut Backend,
+) -> [(wgpu::Texture, wgpu::TextureView); 2] {
+    let size = render_size / 2;
+    [
+        create_texture(
+            "bloom blur texture",
+            size,
+            1,
+            Bloom::EMISSIVE_FORMAT,
+            backend,
+        ),
+        create_texture(
+            "bloom blur texture",
+            size,
+            1,
+            Bloom::EMISSIVE_FORMAT,
+            backend,
+        ),
+    ]
+}
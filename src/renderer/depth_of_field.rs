@@ -0,0 +1,467 @@
+use std::sync::Arc;
+
+use glam::UVec2;
+
+use crate::{
+    arena::Handle, asset_server::AssetChanges, shader_source::ShaderSource, AssetServer, Camera,
+    Projection,
+};
+
+use super::{
+    backend::{texture_byte_size, Backend},
+    fullscreen::{
+        build_pipeline, create_texture, draw_fullscreen, sampler_layout_entry, texture_layout_entry,
+    },
+    render_graph::{PassContext, PostPass, Slot},
+    visual_server::RenderTarget,
+};
+
+pub struct DepthOfField {
+    uniform: DepthOfFieldUniform,
+    uniform_buffer: wgpu::Buffer,
+    blur_textures: [(wgpu::Texture, wgpu::TextureView); 2],
+    bind_groups: BindGroups,
+    blur_bind_group_layout: wgpu::BindGroupLayout,
+    blur_pipeline_layout: wgpu::PipelineLayout,
+    composite_bind_group_layout: wgpu::BindGroupLayout,
+    composite_pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Pipelines,
+    sampler: Arc<wgpu::Sampler>,
+    target_format: wgpu::TextureFormat,
+    sample_count: u32,
+    shader_source: Handle<ShaderSource>,
+    shader: wgpu::ShaderModule,
+    multisampled_shader_source: Handle<ShaderSource>,
+    multisampled_shader: wgpu::ShaderModule,
+}
+
+impl DepthOfField {
+    pub const BLUR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(
+        render_target: &RenderTarget,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) -> Self {
+        let shader_source =
+            asset_server.load::<ShaderSource>("src/renderer/shaders/depth_of_field.wgsl");
+        let shader = backend.create_shader_module(
+            "depth of field shader",
+            asset_server.get(shader_source).source(),
+        );
+        let multisampled_shader_source = asset_server.load_with_options::<ShaderSource>(
+            "src/renderer/shaders/depth_of_field.wgsl",
+            "MULTISAMPLED",
+        );
+        let multisampled_shader = backend.create_shader_module(
+            "depth of field multisampled shader",
+            asset_server.get(multisampled_shader_source).source(),
+        );
+
+        let uniform = DepthOfFieldUniform::default();
+        let uniform_buffer = backend.create_uniform_buffer(uniform);
+
+        let blur_bind_group_layout =
+            backend
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("depth of field blur bind group layout"),
+                    entries: &[texture_layout_entry(0), sampler_layout_entry(1)],
+                });
+        let blur_pipeline_layout =
+            backend
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("depth of field blur pipeline layout"),
+                    bind_group_layouts: &[&blur_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let sample_count = render_target.sample_count;
+        let (composite_bind_group_layout, composite_pipeline_layout) =
+            build_composite_layouts(sample_count, backend);
+
+        let sampler = backend.sampler();
+
+        let blur_textures = create_blur_textures(render_target.size, backend);
+        let bind_groups = BindGroups::new(
+            render_target,
+            &blur_textures,
+            &uniform_buffer,
+            &blur_bind_group_layout,
+            &composite_bind_group_layout,
+            &sampler,
+            backend,
+        );
+
+        let target_format = render_target.color_format;
+        let pipelines = Pipelines::new(
+            &blur_pipeline_layout,
+            &composite_pipeline_layout,
+            &shader,
+            if sample_count > 1 {
+                &multisampled_shader
+            } else {
+                &shader
+            },
+            target_format,
+            backend,
+        );
+
+        Self {
+            uniform,
+            uniform_buffer,
+            blur_textures,
+            bind_groups,
+            blur_bind_group_layout,
+            blur_pipeline_layout,
+            composite_bind_group_layout,
+            composite_pipeline_layout,
+            pipelines,
+            sampler,
+            target_format,
+            sample_count,
+            shader_source,
+            shader,
+            multisampled_shader_source,
+            multisampled_shader,
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera, backend: &mut Backend) {
+        self.uniform = DepthOfFieldUniform {
+            focus_distance: camera.focus_distance,
+            aperture: camera.aperture,
+            near: camera.near,
+            far: camera.far,
+            orthographic: matches!(camera.projection, Projection::Orthographic { .. }) as u32,
+            _padding: Default::default(),
+        };
+        backend.update_uniform_buffer(&self.uniform_buffer, self.uniform);
+    }
+
+    /// A camera with no aperture keeps everything in focus.
+    pub fn enabled(&self) -> bool {
+        self.uniform.aperture > 0.0
+    }
+
+    fn rebuild_pipelines(&mut self, backend: &mut Backend) {
+        self.pipelines = Pipelines::new(
+            &self.blur_pipeline_layout,
+            &self.composite_pipeline_layout,
+            &self.shader,
+            if self.sample_count > 1 {
+                &self.multisampled_shader
+            } else {
+                &self.shader
+            },
+            self.target_format,
+            backend,
+        );
+    }
+}
+
+impl PostPass for DepthOfField {
+    fn reads(&self) -> &[Slot] {
+        &[Slot::Color, Slot::Depth]
+    }
+
+    fn writes(&self) -> &[Slot] {
+        &[Slot::Color]
+    }
+
+    fn update_render_target(&mut self, render_target: &RenderTarget, backend: &mut Backend) {
+        let mut rebuild_pipelines = false;
+        if self.sample_count != render_target.sample_count {
+            self.sample_count = render_target.sample_count;
+            (
+                self.composite_bind_group_layout,
+                self.composite_pipeline_layout,
+            ) = build_composite_layouts(self.sample_count, backend);
+            rebuild_pipelines = true;
+        }
+        if self.target_format != render_target.color_format {
+            self.target_format = render_target.color_format;
+            rebuild_pipelines = true;
+        }
+
+        self.blur_textures = create_blur_textures(render_target.size, backend);
+        self.bind_groups = BindGroups::new(
+            render_target,
+            &self.blur_textures,
+            &self.uniform_buffer,
+            &self.blur_bind_group_layout,
+            &self.composite_bind_group_layout,
+            &self.sampler,
+            backend,
+        );
+
+        if rebuild_pipelines {
+            self.rebuild_pipelines(backend);
+        }
+    }
+
+    fn notify_asset_changes(
+        &mut self,
+        changes: &AssetChanges,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) {
+        let mut rebuild_pipelines = false;
+        if changes.contains(self.shader_source) {
+            let source = asset_server.get(self.shader_source);
+            self.shader = backend.create_shader_module("depth of field shader", source.source());
+            rebuild_pipelines = true;
+        }
+        if changes.contains(self.multisampled_shader_source) {
+            let source = asset_server.get(self.multisampled_shader_source);
+            self.multisampled_shader =
+                backend.create_shader_module("depth of field multisampled shader", source.source());
+            rebuild_pipelines = true;
+        }
+
+        if rebuild_pipelines {
+            self.rebuild_pipelines(backend);
+        }
+    }
+
+    /// Blurs the render target, then blends the blurred image over it where it is out of focus.
+    fn render(&mut self, context: &mut PassContext) {
+        let PassContext {
+            encoder,
+            render_target,
+            ..
+        } = context;
+        draw_fullscreen(
+            encoder,
+            "depth of field blur horizontal render pass",
+            &self.pipelines.blur_horizontal,
+            &self.bind_groups.color,
+            &self.blur_textures[0].1,
+            wgpu::LoadOp::Load,
+        );
+        draw_fullscreen(
+            encoder,
+            "depth of field blur vertical render pass",
+            &self.pipelines.blur_vertical,
+            &self.bind_groups.blur_horizontal,
+            &self.blur_textures[1].1,
+            wgpu::LoadOp::Load,
+        );
+        draw_fullscreen(
+            encoder,
+            "depth of field composite render pass",
+            &self.pipelines.composite,
+            &self.bind_groups.composite,
+            render_target.texture.view(),
+            wgpu::LoadOp::Load,
+        );
+    }
+
+    fn vram_usage(&self) -> u64 {
+        self.uniform_buffer.size()
+            + self
+                .blur_textures
+                .iter()
+                .map(|(texture, _)| texture_byte_size(texture))
+                .sum::<u64>()
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthOfFieldUniform {
+    focus_distance: f32,
+    aperture: f32,
+    near: f32,
+    far: f32,
+    /// Depth is linear with orthographic projections, it isn't with perspective ones.
+    orthographic: u32,
+    _padding: [u32; 3],
+}
+
+// The depth texture is multisampled along with the render target, which changes how the shader
+// must read it. It's read as a float texture, reading depth textures isn't supported everywhere.
+fn build_composite_layouts(
+    sample_count: u32,
+    backend: &mut Backend,
+) -> (wgpu::BindGroupLayout, wgpu::PipelineLayout) {
+    let bind_group_layout =
+        backend
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("depth of field composite bind group layout"),
+                entries: &[
+                    texture_layout_entry(0),
+                    sampler_layout_entry(1),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: sample_count > 1,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+    let pipeline_layout = backend
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("depth of field composite pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+    (bind_group_layout, pipeline_layout)
+}
+
+struct BindGroups {
+    color: wgpu::BindGroup,
+    blur_horizontal: wgpu::BindGroup,
+    composite: wgpu::BindGroup,
+    #[allow(unused)]
+    depth_view: wgpu::TextureView,
+}
+
+impl BindGroups {
+    fn new(
+        render_target: &RenderTarget,
+        blur_textures: &[(wgpu::Texture, wgpu::TextureView); 2],
+        uniform_buffer: &wgpu::Buffer,
+        blur_layout: &wgpu::BindGroupLayout,
+        composite_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        backend: &mut Backend,
+    ) -> Self {
+        let build_blur = |view: &wgpu::TextureView| {
+            backend
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("depth of field blur bind group"),
+                    layout: blur_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                })
+        };
+        let color = build_blur(render_target.texture.view());
+        let blur_horizontal = build_blur(&blur_textures[0].1);
+
+        let depth_view = render_target
+            .texture
+            .depth()
+            .create_view(&wgpu::TextureViewDescriptor {
+                aspect: wgpu::TextureAspect::DepthOnly,
+                ..Default::default()
+            });
+        let composite = backend
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("depth of field composite bind group"),
+                layout: composite_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&blur_textures[1].1),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        Self {
+            color,
+            blur_horizontal,
+            composite,
+            depth_view,
+        }
+    }
+}
+
+struct Pipelines {
+    blur_horizontal: wgpu::RenderPipeline,
+    blur_vertical: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn new(
+        blur_layout: &wgpu::PipelineLayout,
+        composite_layout: &wgpu::PipelineLayout,
+        blur_shader: &wgpu::ShaderModule,
+        composite_shader: &wgpu::ShaderModule,
+        target_format: wgpu::TextureFormat,
+        backend: &mut Backend,
+    ) -> Self {
+        Self {
+            blur_horizontal: build_pipeline(
+                "depth of field blur horizontal pipeline",
+                "fs_blur_horizontal",
+                DepthOfField::BLUR_FORMAT,
+                wgpu::BlendState::REPLACE,
+                blur_layout,
+                blur_shader,
+                backend,
+            ),
+            blur_vertical: build_pipeline(
+                "depth of field blur vertical pipeline",
+                "fs_blur_vertical",
+                DepthOfField::BLUR_FORMAT,
+                wgpu::BlendState::REPLACE,
+                blur_layout,
+                blur_shader,
+                backend,
+            ),
+            composite: build_pipeline(
+                "depth of field composite pipeline",
+                "fs_composite",
+                target_format,
+                wgpu::BlendState::ALPHA_BLENDING,
+                composite_layout,
+                composite_shader,
+                backend,
+            ),
+        }
+    }
+}
+
+// Blurring is done at half resolution, it's cheaper and blurs further.
+fn create_blur_textures(
+    render_size: UVec2,
+    backend: &mut Backend,
+) -> [(wgpu::Texture, wgpu::TextureView); 2] {
+    let size = render_size / 2;
+    let format = DepthOfField::BLUR_FORMAT;
+    [
+        create_texture("depth of field blur texture", size, 1, format, backend),
+        create_texture("depth of field blur texture", size, 1, format, backend),
+    ]
+}
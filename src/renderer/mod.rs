@@ -2,6 +2,13 @@ pub mod visual_server;
 pub use self::visual_server::VisualServer;
 
 pub mod backend;
+pub mod bloom;
+pub mod depth_of_field;
+mod fullscreen;
+pub mod render_graph;
+pub mod taa;
+
+pub mod auto_exposure;
 
 pub mod pipeline2d;
 pub mod pipeline3d;
@@ -1,4 +1,5 @@
-use glam::Vec2;
+use std::sync::Arc;
+
 use wgpu::{Color, CommandEncoder};
 
 use crate::{arena::Handle, asset_server::AssetChanges, shader_source::ShaderSource, AssetServer};
@@ -10,7 +11,7 @@ pub mod uibox_instance;
 
 use super::{
     backend::Backend,
-    visual_server::{RenderTarget, RenderTargetInfo},
+    visual_server::{RenderTarget, RenderTargetInfo, TextStyleUniform},
 };
 
 pub struct Pipeline2d {
@@ -22,20 +23,21 @@ pub struct Pipeline2d {
 
 pub struct Pipeline2dData {
     pub viewport_bind_group: wgpu::BindGroup,
-    pub instance_buffer: wgpu::Buffer,
     pub render_target_info: RenderTargetInfo,
     pub pipeline_layouts: PipelineLayouts,
     pub bind_group_layouts: BindGroupLayouts,
     pub shaders: Shaders,
     //
     pub font_texture_bind_group: wgpu::BindGroup,
-    pub sampler_bilinear: wgpu::Sampler,
+    pub ui_atlas_bind_group: wgpu::BindGroup,
+    pub sampler_bilinear: Arc<wgpu::Sampler>,
 }
 
 impl Pipeline2d {
     pub fn new(
         viewport_uniform_buffer: &wgpu::Buffer,
         font_texture: &wgpu::Texture,
+        ui_atlas_texture: &wgpu::Texture,
         render_target_info: RenderTargetInfo,
         backend: &mut Backend,
         asset_server: &mut AssetServer,
@@ -109,6 +111,44 @@ impl Pipeline2d {
                         },
                     ],
                 }),
+            text_style: backend
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("text style bind group layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                }),
+            ui_atlas: backend
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("ui atlas bind group layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                }),
             fullscreen_texture: backend.device.create_bind_group_layout(
                 &wgpu::BindGroupLayoutDescriptor {
                     label: Some("fullscreen texture bind group layout"),
@@ -142,6 +182,7 @@ impl Pipeline2d {
                     bind_group_layouts: &[
                         &bind_group_layouts.viewport,
                         &bind_group_layouts.text_font,
+                        &bind_group_layouts.text_style,
                     ],
                     push_constant_ranges: &[],
                 }),
@@ -149,7 +190,10 @@ impl Pipeline2d {
                 .device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("uibox pipeline layout"),
-                    bind_group_layouts: &[&bind_group_layouts.viewport],
+                    bind_group_layouts: &[
+                        &bind_group_layouts.viewport,
+                        &bind_group_layouts.ui_atlas,
+                    ],
                     push_constant_ranges: &[],
                 }),
             fullscreen_texture: backend.device.create_pipeline_layout(
@@ -172,36 +216,29 @@ impl Pipeline2d {
                 }],
             });
 
-        let sampler_bilinear = backend.create_sampler();
+        let sampler_bilinear = backend.sampler();
         let font_texture_bind_group = Self::build_font_texture_bind_group(
             &bind_group_layouts,
             font_texture,
             &sampler_bilinear,
             backend,
         );
-
-        let glyphs = b"Hello text!"
-            .iter()
-            .enumerate()
-            .map(|(i, &id)| {
-                GlyphInstance::new(
-                    Vec2::new(i as f32 * 12.0 + 20.0, 20.0),
-                    Vec2::new(14.0, 24.0),
-                    id,
-                )
-            })
-            .collect::<Vec<_>>();
-        let instance_buffer = backend.create_vertex_buffer(&glyphs);
+        let ui_atlas_bind_group = Self::build_ui_atlas_bind_group(
+            &bind_group_layouts,
+            ui_atlas_texture,
+            &sampler_bilinear,
+            backend,
+        );
 
         let data = Pipeline2dData {
             viewport_bind_group,
-            instance_buffer,
             render_target_info,
             pipeline_layouts,
             bind_group_layouts,
             shaders,
             //
             font_texture_bind_group,
+            ui_atlas_bind_group,
             sampler_bilinear,
         };
 
@@ -234,6 +271,19 @@ impl Pipeline2d {
         );
     }
 
+    pub fn update_ui_atlas_texture(
+        &mut self,
+        ui_atlas_texture: &wgpu::Texture,
+        backend: &mut Backend,
+    ) {
+        self.data.ui_atlas_bind_group = Self::build_ui_atlas_bind_group(
+            &self.data.bind_group_layouts,
+            ui_atlas_texture,
+            &self.data.sampler_bilinear,
+            backend,
+        );
+    }
+
     pub fn notify_asset_changes(
         &mut self,
         changes: &AssetChanges,
@@ -296,6 +346,7 @@ impl Pipeline2d {
         // Render uiboxes
         render_pass.set_pipeline(&self.render_uibox_pipeline);
         render_pass.set_bind_group(0, &self.data.viewport_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.data.ui_atlas_bind_group, &[]);
         render_pass.set_vertex_buffer(0, render_commands.uiboxes.instance_buffer.slice(..));
         render_pass.draw(0..4, 0..render_commands.uiboxes.instance_count);
 
@@ -304,11 +355,32 @@ impl Pipeline2d {
             render_pass.set_pipeline(&self.render_text_pipeline);
             render_pass.set_bind_group(0, &self.data.viewport_bind_group, &[]);
             render_pass.set_bind_group(1, &self.data.font_texture_bind_group, &[]);
+            render_pass.set_bind_group(2, render_command.style_bind_group, &[]);
             render_pass.set_vertex_buffer(0, render_command.instance_buffer.slice(..));
             render_pass.draw(0..4, 0..render_command.instance_count);
         }
     }
 
+    /// The bind group of one text's outline, the buffer must be kept along with it.
+    pub fn create_text_style_bind_group(
+        &self,
+        style: TextStyleUniform,
+        backend: &mut Backend,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = backend.create_uniform_buffer(style);
+        let bind_group = backend
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("text style bind group"),
+                layout: &self.data.bind_group_layouts.text_style,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            });
+        (buffer, bind_group)
+    }
+
     fn rebuild_pipelines(&mut self, backend: &mut Backend) {
         self.render_text_pipeline = build_render_text_pipeline(&self.data, backend);
         self.render_uibox_pipeline = build_uibox_pipeline(&self.data, backend);
@@ -341,48 +413,42 @@ impl Pipeline2d {
             })
     }
 
-    pub fn build_fullscreen_texture_bind_group(
-        &self,
-        texture: &wgpu::Texture,
+    fn build_ui_atlas_bind_group(
+        bind_group_layouts: &BindGroupLayouts,
+        ui_atlas_texture: &wgpu::Texture,
         sampler: &wgpu::Sampler,
         backend: &mut Backend,
     ) -> wgpu::BindGroup {
-        let texture_view = texture.create_view(&Default::default());
+        let ui_atlas_texture_view = ui_atlas_texture.create_view(&Default::default());
         backend
             .device
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("fullscreen texture bind group"),
-                layout: &self.data.bind_group_layouts.fullscreen_texture,
+                label: Some("ui atlas bind group"),
+                layout: &bind_group_layouts.ui_atlas,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&texture_view),
+                        resource: wgpu::BindingResource::TextureView(&ui_atlas_texture_view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
+                        resource: wgpu::BindingResource::Sampler(sampler),
                     },
                 ],
             })
     }
 
-    pub fn build_fullscreen_texture_array_bind_group(
+    pub fn build_fullscreen_texture_bind_group(
         &self,
         texture: &wgpu::Texture,
         sampler: &wgpu::Sampler,
         backend: &mut Backend,
-        layer: u32,
     ) -> wgpu::BindGroup {
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
-            dimension: Some(wgpu::TextureViewDimension::D2),
-            base_array_layer: layer,
-            array_layer_count: Some(1),
-            ..Default::default()
-        });
+        let texture_view = texture.create_view(&Default::default());
         backend
             .device
             .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("fullscreen texture array bind group"),
+                label: Some("fullscreen texture bind group"),
                 layout: &self.data.bind_group_layouts.fullscreen_texture,
                 entries: &[
                     wgpu::BindGroupEntry {
@@ -407,6 +473,8 @@ pub struct PipelineLayouts {
 pub struct BindGroupLayouts {
     pub viewport: wgpu::BindGroupLayout,
     pub text_font: wgpu::BindGroupLayout,
+    pub text_style: wgpu::BindGroupLayout,
+    pub ui_atlas: wgpu::BindGroupLayout,
     pub fullscreen_texture: wgpu::BindGroupLayout,
 }
 
@@ -425,9 +493,18 @@ pub struct RenderCommands<'a> {
     pub texture: Option<&'a RenderFullscreenTextureCommand<'a>>,
 }
 
+impl RenderCommands<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.texts.iter().all(|text| text.instance_count == 0)
+            && self.uiboxes.instance_count == 0
+            && self.texture.is_none()
+    }
+}
+
 pub struct RenderCommandText<'a> {
     pub instance_buffer: &'a wgpu::Buffer,
     pub instance_count: u32,
+    pub style_bind_group: &'a wgpu::BindGroup,
 }
 
 pub struct RenderCommandUiBoxes<'a> {
@@ -1,19 +1,22 @@
 use glam::Vec2;
 
+use crate::AtlasRect;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GlyphInstance {
     pub position: [f32; 2],
     pub scale: [f32; 2],
-    pub glyph: u32,
+    /// Min and max uv of the glyph in the font image.
+    pub uv_rect: [f32; 4],
 }
 
 impl GlyphInstance {
-    pub const fn new(position: Vec2, scale: Vec2, glyph: u8) -> Self {
+    pub fn new(position: Vec2, scale: Vec2, uv: AtlasRect) -> Self {
         Self {
             position: position.to_array(),
             scale: scale.to_array(),
-            glyph: glyph as u32,
+            uv_rect: [uv.uv_min.x, uv.uv_min.y, uv.uv_max.x, uv.uv_max.y],
         }
     }
 
@@ -35,7 +38,7 @@ impl GlyphInstance {
                 wgpu::VertexAttribute {
                     offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
                     shader_location: 12,
-                    format: wgpu::VertexFormat::Uint32,
+                    format: wgpu::VertexFormat::Float32x4,
                 },
             ],
         }
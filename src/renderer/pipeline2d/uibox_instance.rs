@@ -8,6 +8,8 @@ pub struct UiBoxInstance {
     pub position: [f32; 2],
     pub size: [f32; 2],
     pub color: [f32; 4],
+    /// Min and max uv of the box's image in the ui atlas, all zero when the box has no image.
+    pub uv_rect: [f32; 4],
 }
 
 impl UiBoxInstance {
@@ -16,6 +18,7 @@ impl UiBoxInstance {
             position: position.to_array(),
             size: size.to_array(),
             color: color.to_array(),
+            uv_rect: [0.0; 4],
         }
     }
 
@@ -39,6 +42,11 @@ impl UiBoxInstance {
                     shader_location: 12,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
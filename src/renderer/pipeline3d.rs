@@ -1,10 +1,17 @@
+use std::collections::HashMap;
+
 use wgpu::CommandEncoder;
 
 use crate::{arena::Handle, asset_server::AssetChanges, shader_source::ShaderSource, AssetServer};
 
+pub mod model_instance;
+use self::model_instance::ModelInstance;
+
 use super::{
     backend::Backend,
-    visual_server::{RenderTarget, RenderTargetInfo},
+    bloom::Bloom,
+    taa::Taa,
+    visual_server::{ColorLoadOp, RenderTarget, RenderTargetInfo, VisualServer},
     Vertex,
 };
 
@@ -20,6 +27,8 @@ pub struct Pipeline3dData {
     #[allow(unused)]
     pub bind_group_layouts: BindGroupLayouts,
     shaders: Shaders,
+    /// Replacing the mesh vertex stage for the materials that have them.
+    custom_vertex_shaders: HashMap<Handle<ShaderSource>, wgpu::ShaderModule>,
 }
 
 impl Pipeline3d {
@@ -43,6 +52,13 @@ impl Pipeline3d {
             .source()
             .to_string();
 
+        let skybox_shader_source_handle =
+            asset_server.load::<ShaderSource>("src/renderer/shaders/skybox.wgsl");
+        let skybox_shader_source = asset_server
+            .get(skybox_shader_source_handle)
+            .source()
+            .to_string();
+
         let render_shadow_map_shader_source_handle =
             asset_server.load::<ShaderSource>("src/renderer/shaders/render_shadow_map.wgsl");
         let render_shadow_map_shader_source =
@@ -60,6 +76,8 @@ impl Pipeline3d {
                 "render shadow map shader",
                 render_shadow_map_shader_source.source(),
             ),
+            skybox_source: skybox_shader_source_handle,
+            skybox: backend.create_shader_module("skybox shader", &skybox_shader_source),
         };
 
         let bind_group_layouts = BindGroupLayouts {
@@ -109,6 +127,36 @@ impl Pipeline3d {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
                     ],
                 }),
             model: backend
@@ -117,10 +165,10 @@ impl Pipeline3d {
                     label: Some("model bind group layout"),
                     entries: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
+                            has_dynamic_offset: true,
                             min_binding_size: None,
                         },
                         count: None,
@@ -130,31 +178,27 @@ impl Pipeline3d {
                 .device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     label: Some("light bind group layout"),
+                    entries: &light_bind_group_layout_entries(),
+                }),
+            skybox: backend
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("skybox bind group layout"),
                     entries: &[
                         wgpu::BindGroupLayoutEntry {
                             binding: 0,
                             visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
                             ty: wgpu::BindingType::Texture {
-                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                                view_dimension: wgpu::TextureViewDimension::D2Array,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::Cube,
                                 multisampled: false,
                             },
                             count: None,
                         },
                         wgpu::BindGroupLayoutEntry {
-                            binding: 2,
+                            binding: 1,
                             visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
                     ],
@@ -192,6 +236,13 @@ impl Pipeline3d {
                     push_constant_ranges: &[],
                 },
             ),
+            skybox: backend
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("skybox pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layouts.scene, &bind_group_layouts.skybox],
+                    push_constant_ranges: &[],
+                }),
         };
 
         let scene_bind_group = backend
@@ -211,6 +262,7 @@ impl Pipeline3d {
             pipeline_layouts,
             bind_group_layouts,
             shaders,
+            custom_vertex_shaders: HashMap::new(),
         };
 
         let pipelines = Self::build_pipelines(&data, backend);
@@ -256,6 +308,50 @@ impl Pipeline3d {
 
             self.rebuild_pipelines(backend);
         }
+
+        if changes.contains(self.data.shaders.skybox_source) {
+            let source = asset_server.get(self.data.shaders.skybox_source);
+            self.data.shaders.skybox =
+                backend.create_shader_module("skybox shader", source.source());
+
+            self.rebuild_pipelines(backend);
+        }
+
+        let changed_vertex_shaders: Vec<_> = self
+            .data
+            .custom_vertex_shaders
+            .keys()
+            .copied()
+            .filter(|&handle| changes.contains(handle))
+            .collect();
+        for handle in changed_vertex_shaders {
+            self.data.custom_vertex_shaders.remove(&handle);
+            self.register_custom_vertex_shader(handle, backend, asset_server);
+        }
+    }
+
+    /// Builds the pipelines of the meshes drawn with a material's custom vertex shader, if they
+    /// aren't already.
+    pub fn register_custom_vertex_shader(
+        &mut self,
+        handle: Handle<ShaderSource>,
+        backend: &mut Backend,
+        asset_server: &AssetServer,
+    ) {
+        if self.data.custom_vertex_shaders.contains_key(&handle) {
+            return;
+        }
+        let source = asset_server.get(handle);
+        let module = backend.create_shader_module("custom vertex shader", source.source());
+        self.data.custom_vertex_shaders.insert(handle, module);
+        let pipelines = build_mesh_pipelines(&self.data, Some(handle), backend);
+        self.pipelines.custom.insert(handle, pipelines);
+    }
+
+    fn mesh_pipelines(&self, vertex_shader: Option<Handle<ShaderSource>>) -> &MeshPipelines {
+        vertex_shader
+            .and_then(|handle| self.pipelines.custom.get(&handle))
+            .unwrap_or(&self.pipelines.mesh)
     }
 
     pub fn render(
@@ -266,13 +362,8 @@ impl Pipeline3d {
     ) {
         // Shadow maps
         for light in render_commands.lights {
-            for i in 0..light.cascades_bind_groups.len() {
-                let depth_view = light.shadow_maps.create_view(&wgpu::TextureViewDescriptor {
-                    dimension: Some(wgpu::TextureViewDimension::D2),
-                    base_array_layer: i as _,
-                    array_layer_count: Some(1),
-                    ..Default::default()
-                });
+            for (i, shadow_map) in light.shadow_maps.iter().enumerate() {
+                let depth_view = shadow_map.create_view(&Default::default());
                 let depth_stencil_attachment = wgpu::RenderPassDepthStencilAttachment {
                     view: &depth_view,
                     depth_ops: Some(wgpu::Operations {
@@ -290,10 +381,18 @@ impl Pipeline3d {
 
                 render_pass.set_pipeline(&self.pipelines.directional_shadow_map);
                 render_pass.set_bind_group(0, &light.cascades_bind_groups[i], &[]);
+                let mut instanced = false;
 
-                for mesh in render_commands.meshes {
+                let meshes = render_commands
+                    .meshes
+                    .iter()
+                    .chain(render_commands.blended_meshes);
+                for mesh in meshes {
                     let RenderCommandMesh {
                         model_bind_group,
+                        model_offset,
+                        instance_buffer,
+                        instance_count,
                         vertex_buffer,
                         index_buffer,
                         index_count,
@@ -304,67 +403,197 @@ impl Pipeline3d {
                         continue;
                     }
 
-                    render_pass.set_bind_group(1, model_bind_group, &[]);
+                    if instance_buffer.is_some() != instanced {
+                        instanced = instance_buffer.is_some();
+                        render_pass.set_pipeline(if instanced {
+                            &self.pipelines.directional_shadow_map_instanced
+                        } else {
+                            &self.pipelines.directional_shadow_map
+                        });
+                    }
+                    render_pass.set_bind_group(1, model_bind_group, &[*model_offset]);
                     render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                    if let Some(instance_buffer) = instance_buffer {
+                        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                    }
                     render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                    render_pass.draw_indexed(0..*index_count, 0, 0..1);
+                    render_pass.draw_indexed(0..*index_count, 0, 0..*instance_count);
                 }
             }
         }
 
         //## ACTUAL RENDERING DOWN HERE
         let (color_attachment, depth_stencil_attachment) = render_target.render_pass_attachments();
+        let color_attachment = match render_commands.color_load_op {
+            ColorLoadOp::Clear => color_attachment,
+            ColorLoadOp::Load => wgpu::RenderPassColorAttachment {
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                ..color_attachment
+            },
+        };
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("render pass"),
-            color_attachments: &[Some(color_attachment)],
+            color_attachments: &[
+                Some(color_attachment),
+                Some(render_commands.emissive_attachment.clone()),
+                Some(render_commands.motion_attachment.clone()),
+            ],
             depth_stencil_attachment: Some(depth_stencil_attachment),
             ..Default::default()
         });
 
         // Ambient and depth
-        render_pass.set_pipeline(&self.pipelines.ambient_light_depth_prepass);
         render_pass.set_bind_group(0, &self.data.scene_bind_group, &[]);
+        let mut current_pipeline = None;
 
-        for mesh in render_commands.meshes {
+        for mesh in render_commands.meshes.iter().filter(|mesh| mesh.visible) {
             let RenderCommandMesh {
                 material_bind_group,
                 model_bind_group,
+                model_offset,
+                instance_buffer,
+                instance_count,
                 vertex_buffer,
+                custom_attribute_buffer,
+                vertex_shader,
                 index_buffer,
                 index_count,
                 ..
             } = mesh;
 
+            let instanced = instance_buffer.is_some();
+            if current_pipeline != Some((*vertex_shader, instanced)) {
+                current_pipeline = Some((*vertex_shader, instanced));
+                let pipelines = self.mesh_pipelines(*vertex_shader);
+                render_pass.set_pipeline(if instanced {
+                    &pipelines.ambient_light_depth_prepass_instanced
+                } else {
+                    &pipelines.ambient_light_depth_prepass
+                });
+            }
             render_pass.set_bind_group(1, material_bind_group, &[]);
-            render_pass.set_bind_group(2, model_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_bind_group(2, model_bind_group, &[*model_offset]);
+            set_vertex_buffers(
+                &mut render_pass,
+                vertex_buffer,
+                *instance_buffer,
+                *custom_attribute_buffer,
+            );
             render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..*index_count, 0, 0..1);
+            render_pass.draw_indexed(0..*index_count, 0, 0..*instance_count);
+        }
+
+        // Skybox, wherever the depth prepass left the far plane
+        if let Some(skybox_bind_group) = render_commands.skybox {
+            render_pass.set_pipeline(&self.pipelines.skybox);
+            render_pass.set_bind_group(1, skybox_bind_group, &[]);
+            render_pass.draw(0..4, 0..1);
         }
 
         // Lights
-        render_pass.set_pipeline(&self.pipelines.light);
+        let mut current_pipeline = None;
 
-        for mesh in render_commands.meshes {
+        for mesh in render_commands.meshes.iter().filter(|mesh| mesh.visible) {
             let RenderCommandMesh {
                 material_bind_group,
                 model_bind_group,
+                model_offset,
+                instance_buffer,
+                instance_count,
                 vertex_buffer,
+                custom_attribute_buffer,
+                vertex_shader,
                 index_buffer,
                 index_count,
                 ..
             } = mesh;
 
+            let instanced = instance_buffer.is_some();
+            if current_pipeline != Some((*vertex_shader, instanced)) {
+                current_pipeline = Some((*vertex_shader, instanced));
+                let pipelines = self.mesh_pipelines(*vertex_shader);
+                render_pass.set_pipeline(if instanced {
+                    &pipelines.light_instanced
+                } else {
+                    &pipelines.light
+                });
+            }
             render_pass.set_bind_group(1, material_bind_group, &[]);
-            render_pass.set_bind_group(2, model_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_bind_group(2, model_bind_group, &[*model_offset]);
+            set_vertex_buffers(
+                &mut render_pass,
+                vertex_buffer,
+                *instance_buffer,
+                *custom_attribute_buffer,
+            );
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            for light in render_commands.lights {
+                render_pass.set_bind_group(3, light.bind_group, &[]);
+                render_pass.draw_indexed(0..*index_count, 0, 0..*instance_count);
+            }
+        }
+
+        // Blended, each mesh lit completely before the next one is blended over it
+        let blended_meshes = render_commands
+            .blended_meshes
+            .iter()
+            .filter(|mesh| mesh.visible);
+        for mesh in blended_meshes {
+            let RenderCommandMesh {
+                material_bind_group,
+                model_bind_group,
+                model_offset,
+                vertex_buffer,
+                custom_attribute_buffer,
+                vertex_shader,
+                index_buffer,
+                index_count,
+                ..
+            } = mesh;
+
+            let pipelines = self.mesh_pipelines(*vertex_shader);
+            render_pass.set_pipeline(&pipelines.ambient_light_blend);
+            render_pass.set_bind_group(1, material_bind_group, &[]);
+            render_pass.set_bind_group(2, model_bind_group, &[*model_offset]);
+            set_vertex_buffers(
+                &mut render_pass,
+                vertex_buffer,
+                None,
+                *custom_attribute_buffer,
+            );
             render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..*index_count, 0, 0..1);
 
+            render_pass.set_pipeline(&pipelines.light_blend);
             for light in render_commands.lights {
                 render_pass.set_bind_group(3, light.bind_group, &[]);
                 render_pass.draw_indexed(0..*index_count, 0, 0..1);
             }
         }
+
+        // Overlays, unlit and ignoring depth
+        render_pass.set_pipeline(&self.pipelines.overlay);
+        for mesh in render_commands.overlays {
+            let RenderCommandMesh {
+                material_bind_group,
+                model_bind_group,
+                model_offset,
+                vertex_buffer,
+                index_buffer,
+                index_count,
+                ..
+            } = mesh;
+
+            render_pass.set_bind_group(1, material_bind_group, &[]);
+            render_pass.set_bind_group(2, model_bind_group, &[*model_offset]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..*index_count, 0, 0..1);
+        }
     }
 
     fn rebuild_pipelines(&mut self, backend: &mut Backend) {
@@ -373,9 +602,18 @@ impl Pipeline3d {
 
     fn build_pipelines(data: &Pipeline3dData, backend: &mut Backend) -> Pipelines {
         Pipelines {
-            ambient_light_depth_prepass: build_pipeline_ambient_light_depth_prepass(data, backend),
-            light: build_pipeline_light(data, backend),
-            directional_shadow_map: build_pipeline_directional_shadow_map(data, backend),
+            mesh: build_mesh_pipelines(data, None, backend),
+            custom: data
+                .custom_vertex_shaders
+                .keys()
+                .map(|&handle| (handle, build_mesh_pipelines(data, Some(handle), backend)))
+                .collect(),
+            directional_shadow_map: build_pipeline_directional_shadow_map(data, false, backend),
+            directional_shadow_map_instanced: build_pipeline_directional_shadow_map(
+                data, true, backend,
+            ),
+            skybox: build_pipeline_skybox(data, backend),
+            overlay: build_pipeline_overlay(data, backend),
         }
     }
 }
@@ -384,12 +622,27 @@ struct PipelineLayouts {
     pub ambient_light_depth_prepass: wgpu::PipelineLayout,
     pub light: wgpu::PipelineLayout,
     pub directional_shadow_map: wgpu::PipelineLayout,
+    pub skybox: wgpu::PipelineLayout,
 }
 
 struct Pipelines {
+    pub mesh: MeshPipelines,
+    /// Per custom vertex shader.
+    pub custom: HashMap<Handle<ShaderSource>, MeshPipelines>,
+    pub directional_shadow_map: wgpu::RenderPipeline,
+    pub directional_shadow_map_instanced: wgpu::RenderPipeline,
+    pub skybox: wgpu::RenderPipeline,
+    pub overlay: wgpu::RenderPipeline,
+}
+
+/// Those that draw meshes with the material's shaders.
+struct MeshPipelines {
     pub ambient_light_depth_prepass: wgpu::RenderPipeline,
+    pub ambient_light_depth_prepass_instanced: wgpu::RenderPipeline,
     pub light: wgpu::RenderPipeline,
-    pub directional_shadow_map: wgpu::RenderPipeline,
+    pub light_instanced: wgpu::RenderPipeline,
+    pub ambient_light_blend: wgpu::RenderPipeline,
+    pub light_blend: wgpu::RenderPipeline,
 }
 
 pub struct BindGroupLayouts {
@@ -397,6 +650,7 @@ pub struct BindGroupLayouts {
     pub material: wgpu::BindGroupLayout,
     pub model: wgpu::BindGroupLayout,
     pub light: wgpu::BindGroupLayout,
+    pub skybox: wgpu::BindGroupLayout,
 }
 
 struct Shaders {
@@ -406,50 +660,184 @@ struct Shaders {
     pub render_light: wgpu::ShaderModule,
     pub render_shadow_map_source: Handle<ShaderSource>,
     pub render_shadow_map: wgpu::ShaderModule,
+    pub skybox_source: Handle<ShaderSource>,
+    pub skybox: wgpu::ShaderModule,
 }
 
 pub struct RenderCommands<'a> {
+    /// Opaque, the only ones that can be instanced.
     pub meshes: &'a [RenderCommandMesh<'a>],
+    /// Drawn after the opaque meshes, in order. Expected to be sorted back to front.
+    pub blended_meshes: &'a [RenderCommandMesh<'a>],
     pub lights: &'a [RenderCommandLight<'a>],
+    pub skybox: Option<&'a wgpu::BindGroup>,
+    /// Drawn last, over everything else, in order. They never go in shadow maps.
+    pub overlays: &'a [RenderCommandMesh<'a>],
+    pub emissive_attachment: wgpu::RenderPassColorAttachment<'a>,
+    pub motion_attachment: wgpu::RenderPassColorAttachment<'a>,
+    pub color_load_op: ColorLoadOp,
 }
 
 pub struct RenderCommandMesh<'a> {
     pub material_bind_group: &'a wgpu::BindGroup,
+    /// Shared by every mesh, with each one's uniform at its own dynamic offset.
     pub model_bind_group: &'a wgpu::BindGroup,
+    pub model_offset: u32,
+    /// The models of all the meshes drawn at once, for instanced draws. The model bind group
+    /// is then unused.
+    pub instance_buffer: Option<&'a wgpu::Buffer>,
+    /// How many meshes the command draws, 1 unless it is instanced.
+    pub instance_count: u32,
     pub vertex_buffer: &'a wgpu::Buffer,
+    /// Only with a custom vertex shader, which then reads it.
+    pub custom_attribute_buffer: Option<&'a wgpu::Buffer>,
+    /// Replaces the one of the mesh shader. Shadow maps are still rendered with the default one.
+    pub vertex_shader: Option<Handle<ShaderSource>>,
     pub index_buffer: &'a wgpu::Buffer,
     pub index_count: u32,
     pub casts_shadows: bool,
+    /// Distance along the camera's view direction, used to sort draws.
+    pub view_depth: f32,
+    /// Meshes outside the camera's view only get drawn in shadow maps.
+    pub visible: bool,
 }
 
 pub struct RenderCommandLight<'a> {
     pub bind_group: &'a wgpu::BindGroup,
-    pub shadow_maps: &'a wgpu::Texture,
+    /// One per cascade.
+    pub shadow_maps: &'a [wgpu::Texture],
     pub cascades_bind_groups: Vec<&'a wgpu::BindGroup>,
 }
 
-fn build_pipeline_ambient_light_depth_prepass(
+fn light_bind_group_layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+    let mut entries = vec![
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+            count: None,
+        },
+    ];
+    // Each cascade has its own shadow map, so that they can be of different resolutions.
+    for binding in 2..2 + VisualServer::MAX_SHADOW_CASCADES {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        });
+    }
+    entries
+}
+
+fn build_mesh_pipelines(
+    data: &Pipeline3dData,
+    vertex_shader: Option<Handle<ShaderSource>>,
+    backend: &mut Backend,
+) -> MeshPipelines {
+    MeshPipelines {
+        ambient_light_depth_prepass: build_pipeline_ambient_light(
+            data,
+            vertex_shader,
+            false,
+            false,
+            backend,
+        ),
+        ambient_light_depth_prepass_instanced: build_pipeline_ambient_light(
+            data,
+            vertex_shader,
+            false,
+            true,
+            backend,
+        ),
+        light: build_pipeline_light(data, vertex_shader, false, false, backend),
+        light_instanced: build_pipeline_light(data, vertex_shader, false, true, backend),
+        ambient_light_blend: build_pipeline_ambient_light(
+            data,
+            vertex_shader,
+            true,
+            false,
+            backend,
+        ),
+        light_blend: build_pipeline_light(data, vertex_shader, true, false, backend),
+    }
+}
+
+/// Blended meshes are drawn over the opaque ones without writing depth or motion.
+fn build_pipeline_ambient_light(
     pipeline_data: &Pipeline3dData,
+    vertex_shader: Option<Handle<ShaderSource>>,
+    blend: bool,
+    instanced: bool,
     backend: &mut Backend,
 ) -> wgpu::RenderPipeline {
+    let (label, color_blend, depth_write_enabled, motion_writes) = if blend {
+        (
+            "ambient_light_blend render pipeline",
+            wgpu::BlendState::ALPHA_BLENDING,
+            false,
+            wgpu::ColorWrites::empty(),
+        )
+    } else if instanced {
+        (
+            "ambient_light_depth_prepass instanced render pipeline",
+            wgpu::BlendState::REPLACE,
+            true,
+            wgpu::ColorWrites::ALL,
+        )
+    } else {
+        (
+            "ambient_light_depth_prepass render pipeline",
+            wgpu::BlendState::REPLACE,
+            true,
+            wgpu::ColorWrites::ALL,
+        )
+    };
+    let (entry_point, buffers) = mesh_vertex_entry_point_and_buffers(vertex_shader, instanced);
     backend
         .device
         .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("ambient_light_depth_prepass render pipeline"),
+            label: Some(label),
             layout: Some(&pipeline_data.pipeline_layouts.ambient_light_depth_prepass),
             vertex: wgpu::VertexState {
-                module: &pipeline_data.shaders.render_mesh,
-                entry_point: "vs_main",
-                buffers: &[Vertex::buffer_layout()],
+                module: mesh_vertex_module(pipeline_data, vertex_shader),
+                entry_point,
+                buffers: &buffers,
             },
             fragment: Some(wgpu::FragmentState {
                 module: &pipeline_data.shaders.render_mesh,
                 entry_point: "fs_main_ambient_light_depth_prepass",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: pipeline_data.render_target_info.color_format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: pipeline_data.render_target_info.color_format,
+                        blend: Some(color_blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Bloom::EMISSIVE_FORMAT,
+                        blend: Some(color_blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Taa::MOTION_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: motion_writes,
+                    }),
+                ],
             }),
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
@@ -460,7 +848,7 @@ fn build_pipeline_ambient_light_depth_prepass(
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: pipeline_data.render_target_info.depth_format,
-                depth_write_enabled: true,
+                depth_write_enabled,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
@@ -474,28 +862,64 @@ fn build_pipeline_ambient_light_depth_prepass(
         })
 }
 
+/// Blended meshes have no depth of their own in the depth buffer to compare with.
 fn build_pipeline_light(
     pipeline_data: &Pipeline3dData,
+    vertex_shader: Option<Handle<ShaderSource>>,
+    blend: bool,
+    instanced: bool,
     backend: &mut Backend,
 ) -> wgpu::RenderPipeline {
+    let (label, color_blend, depth_compare) = if blend {
+        (
+            "light_blend render pipeline",
+            ALPHA_ADDITIVE_BLENDING,
+            wgpu::CompareFunction::Less,
+        )
+    } else if instanced {
+        (
+            "light instanced render pipeline",
+            ADDITIVE_BLENDING,
+            wgpu::CompareFunction::Equal,
+        )
+    } else {
+        (
+            "light render pipeline",
+            ADDITIVE_BLENDING,
+            wgpu::CompareFunction::Equal,
+        )
+    };
+    let (entry_point, buffers) = mesh_vertex_entry_point_and_buffers(vertex_shader, instanced);
     backend
         .device
         .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("light render pipeline"),
+            label: Some(label),
             layout: Some(&pipeline_data.pipeline_layouts.light),
             vertex: wgpu::VertexState {
-                module: &pipeline_data.shaders.render_mesh,
-                entry_point: "vs_main",
-                buffers: &[Vertex::buffer_layout()],
+                module: mesh_vertex_module(pipeline_data, vertex_shader),
+                entry_point,
+                buffers: &buffers,
             },
             fragment: Some(wgpu::FragmentState {
                 module: &pipeline_data.shaders.render_light,
-                entry_point: "fs_main_blinn_phong",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: pipeline_data.render_target_info.color_format,
-                    blend: Some(ADDITIVE_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                entry_point: "fs_main_lights",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: pipeline_data.render_target_info.color_format,
+                        blend: Some(color_blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Bloom::EMISSIVE_FORMAT,
+                        blend: Some(color_blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Taa::MOTION_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }),
+                ],
             }),
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
@@ -507,7 +931,7 @@ fn build_pipeline_light(
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: pipeline_data.render_target_info.depth_format,
                 depth_write_enabled: false,
-                depth_compare: wgpu::CompareFunction::Equal,
+                depth_compare,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
@@ -522,17 +946,24 @@ fn build_pipeline_light(
 
 fn build_pipeline_directional_shadow_map(
     pipeline_data: &Pipeline3dData,
+    instanced: bool,
     backend: &mut Backend,
 ) -> wgpu::RenderPipeline {
+    let label = if instanced {
+        "directional shadow map instanced render pipeline"
+    } else {
+        "directional shadow map render pipeline"
+    };
+    let (entry_point, buffers) = vertex_entry_point_and_buffers(instanced);
     backend
         .device
         .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("directional shadow map render pipeline"),
+            label: Some(label),
             layout: Some(&pipeline_data.pipeline_layouts.directional_shadow_map),
             vertex: wgpu::VertexState {
                 module: &pipeline_data.shaders.render_shadow_map,
-                entry_point: "vs_main",
-                buffers: &[Vertex::buffer_layout()],
+                entry_point,
+                buffers: &buffers,
             },
             fragment: None,
             primitive: wgpu::PrimitiveState {
@@ -550,7 +981,125 @@ fn build_pipeline_directional_shadow_map(
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: Backend::SHADOW_MAP_SAMPLE_COUNT,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+}
+
+fn build_pipeline_skybox(
+    pipeline_data: &Pipeline3dData,
+    backend: &mut Backend,
+) -> wgpu::RenderPipeline {
+    backend
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("skybox render pipeline"),
+            layout: Some(&pipeline_data.pipeline_layouts.skybox),
+            vertex: wgpu::VertexState {
+                module: &pipeline_data.shaders.skybox,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &pipeline_data.shaders.skybox,
+                entry_point: "fs_main",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: pipeline_data.render_target_info.color_format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Bloom::EMISSIVE_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Taa::MOTION_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: pipeline_data.render_target_info.depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: pipeline_data.render_target_info.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+}
+
+/// Shares the ambient light pass's layout and shaders, but draws over the depth buffer without
+/// writing to it, and without emissive.
+fn build_pipeline_overlay(
+    pipeline_data: &Pipeline3dData,
+    backend: &mut Backend,
+) -> wgpu::RenderPipeline {
+    backend
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("overlay render pipeline"),
+            layout: Some(&pipeline_data.pipeline_layouts.ambient_light_depth_prepass),
+            vertex: wgpu::VertexState {
+                module: &pipeline_data.shaders.render_mesh,
+                entry_point: "vs_main",
+                buffers: &[Vertex::buffer_layout()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &pipeline_data.shaders.render_mesh,
+                entry_point: "fs_main_ambient_light_depth_prepass",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: pipeline_data.render_target_info.color_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Bloom::EMISSIVE_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: Taa::MOTION_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: pipeline_data.render_target_info.depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: pipeline_data.render_target_info.sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
@@ -558,7 +1107,73 @@ fn build_pipeline_directional_shadow_map(
         })
 }
 
-const ADDITIVE_BLENDING: wgpu::BlendState = {
+/// Instanced draws take their models from a second vertex buffer, one per instance, rather
+/// than from the model bind group.
+fn vertex_entry_point_and_buffers(
+    instanced: bool,
+) -> (&'static str, Vec<wgpu::VertexBufferLayout<'static>>) {
+    if instanced {
+        (
+            "vs_main_instanced",
+            vec![Vertex::buffer_layout(), ModelInstance::buffer_layout()],
+        )
+    } else {
+        ("vs_main", vec![Vertex::buffer_layout()])
+    }
+}
+
+/// Custom vertex shaders read their attributes from the vertex buffer after the others, at
+/// location 15.
+fn mesh_vertex_entry_point_and_buffers(
+    vertex_shader: Option<Handle<ShaderSource>>,
+    instanced: bool,
+) -> (&'static str, Vec<wgpu::VertexBufferLayout<'static>>) {
+    let (entry_point, mut buffers) = vertex_entry_point_and_buffers(instanced);
+    if vertex_shader.is_some() {
+        buffers.push(CUSTOM_ATTRIBUTE_BUFFER_LAYOUT);
+    }
+    (entry_point, buffers)
+}
+
+fn mesh_vertex_module(
+    pipeline_data: &Pipeline3dData,
+    vertex_shader: Option<Handle<ShaderSource>>,
+) -> &wgpu::ShaderModule {
+    match vertex_shader {
+        Some(handle) => &pipeline_data.custom_vertex_shaders[&handle],
+        None => &pipeline_data.shaders.render_mesh,
+    }
+}
+
+fn set_vertex_buffers<'a>(
+    render_pass: &mut wgpu::RenderPass<'a>,
+    vertex_buffer: &'a wgpu::Buffer,
+    instance_buffer: Option<&'a wgpu::Buffer>,
+    custom_attribute_buffer: Option<&'a wgpu::Buffer>,
+) {
+    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+    let mut slot = 1;
+    if let Some(instance_buffer) = instance_buffer {
+        render_pass.set_vertex_buffer(slot, instance_buffer.slice(..));
+        slot += 1;
+    }
+    if let Some(custom_attribute_buffer) = custom_attribute_buffer {
+        render_pass.set_vertex_buffer(slot, custom_attribute_buffer.slice(..));
+    }
+}
+
+const CUSTOM_ATTRIBUTE_BUFFER_LAYOUT: wgpu::VertexBufferLayout<'static> =
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 15,
+            format: wgpu::VertexFormat::Float32x4,
+        }],
+    };
+
+pub const ADDITIVE_BLENDING: wgpu::BlendState = {
     use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
     BlendState {
         alpha: BlendComponent {
@@ -573,3 +1188,20 @@ const ADDITIVE_BLENDING: wgpu::BlendState = {
         },
     }
 };
+
+/// Adds the source weighted by its alpha, for lighting blended surfaces.
+const ALPHA_ADDITIVE_BLENDING: wgpu::BlendState = {
+    use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+    BlendState {
+        alpha: BlendComponent {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        },
+        color: BlendComponent {
+            src_factor: BlendFactor::SrcAlpha,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        },
+    }
+};
@@ -0,0 +1,181 @@
+use std::{any::Any, marker::PhantomData};
+
+use wgpu::CommandEncoder;
+
+use crate::{asset_server::AssetChanges, AssetServer};
+
+use super::{backend::Backend, visual_server::RenderTarget};
+
+/// Resources of the 3d render target that post passes read from and write to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Color,
+    Depth,
+    Emissive,
+    Motion,
+    Exposure,
+}
+
+pub struct PassContext<'a> {
+    pub encoder: &'a mut CommandEncoder,
+    pub render_target: &'a RenderTarget,
+    pub backend: &'a mut Backend,
+    pub camera_moved: bool,
+}
+
+/// A pass that runs on the 3d render target after the 3d pipeline rendered to it.
+pub trait PostPass: Any {
+    fn reads(&self) -> &[Slot];
+    fn writes(&self) -> &[Slot];
+    fn update_render_target(&mut self, render_target: &RenderTarget, backend: &mut Backend);
+    fn notify_asset_changes(
+        &mut self,
+        changes: &AssetChanges,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    );
+    fn render(&mut self, context: &mut PassContext);
+    /// Approximate amount of memory taken by the textures and buffers of the pass, in bytes.
+    fn vram_usage(&self) -> u64;
+}
+
+pub struct PassId<P> {
+    index: usize,
+    _marker: PhantomData<fn() -> P>,
+}
+
+impl<P> Clone for PassId<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P> Copy for PassId<P> {}
+
+struct PassEntry {
+    pass: Box<dyn PostPass>,
+    enabled: bool,
+}
+
+/// Schedules post passes from the slots they read and write.
+/// A pass that only reads a slot runs after every pass that writes it, and passes that write
+/// the same slot run in the order they were added.
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<PassEntry>,
+    schedule: Vec<usize>,
+}
+
+impl RenderGraph {
+    /// Adds a pass, disabled.
+    pub fn add<P: PostPass>(&mut self, pass: P) -> Result<PassId<P>, String> {
+        self.passes.push(PassEntry {
+            pass: Box::new(pass),
+            enabled: false,
+        });
+        match schedule(&self.passes) {
+            Ok(schedule) => self.schedule = schedule,
+            Err(e) => {
+                self.passes.pop();
+                return Err(e);
+            }
+        }
+
+        Ok(PassId {
+            index: self.passes.len() - 1,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn get<P: PostPass>(&self, id: PassId<P>) -> &P {
+        let pass: &dyn Any = self.passes[id.index].pass.as_ref();
+        pass.downcast_ref().unwrap()
+    }
+
+    pub fn get_mut<P: PostPass>(&mut self, id: PassId<P>) -> &mut P {
+        let pass: &mut dyn Any = self.passes[id.index].pass.as_mut();
+        pass.downcast_mut().unwrap()
+    }
+
+    pub fn enabled<P>(&self, id: PassId<P>) -> bool {
+        self.passes[id.index].enabled
+    }
+
+    pub fn set_enabled<P>(&mut self, id: PassId<P>, enabled: bool) {
+        self.passes[id.index].enabled = enabled;
+    }
+
+    pub fn update_render_target(&mut self, render_target: &RenderTarget, backend: &mut Backend) {
+        for entry in &mut self.passes {
+            entry.pass.update_render_target(render_target, backend);
+        }
+    }
+
+    pub fn notify_asset_changes(
+        &mut self,
+        changes: &AssetChanges,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) {
+        for entry in &mut self.passes {
+            entry
+                .pass
+                .notify_asset_changes(changes, backend, asset_server);
+        }
+    }
+
+    pub fn vram_usage(&self) -> u64 {
+        self.passes
+            .iter()
+            .map(|entry| entry.pass.vram_usage())
+            .sum()
+    }
+
+    pub fn render(&mut self, context: &mut PassContext) {
+        for &index in &self.schedule {
+            let entry = &mut self.passes[index];
+            if entry.enabled {
+                entry.pass.render(context);
+            }
+        }
+    }
+}
+
+fn schedule(passes: &[PassEntry]) -> Result<Vec<usize>, String> {
+    let runs_before = |a: &dyn PostPass, b: &dyn PostPass, a_index: usize, b_index: usize| {
+        a.writes().iter().any(|slot| {
+            let b_writes = b.writes().contains(slot);
+            (b.reads().contains(slot) && !b_writes) || (b_writes && a_index < b_index)
+        })
+    };
+
+    let mut dependencies = vec![0; passes.len()];
+    for (b_index, b) in passes.iter().enumerate() {
+        for (a_index, a) in passes.iter().enumerate() {
+            if a_index != b_index && runs_before(a.pass.as_ref(), b.pass.as_ref(), a_index, b_index)
+            {
+                dependencies[b_index] += 1;
+            }
+        }
+    }
+
+    let mut schedule = Vec::with_capacity(passes.len());
+    while schedule.len() < passes.len() {
+        let Some(next) =
+            (0..passes.len()).find(|&index| dependencies[index] == 0 && !schedule.contains(&index))
+        else {
+            return Err("post passes depend on each other in a cycle".to_string());
+        };
+        schedule.push(next);
+
+        for (b_index, b) in passes.iter().enumerate() {
+            if b_index != next
+                && runs_before(passes[next].pass.as_ref(), b.pass.as_ref(), next, b_index)
+            {
+                dependencies[b_index] -= 1;
+            }
+        }
+    }
+
+    Ok(schedule)
+}
@@ -0,0 +1,71 @@
+struct AutoExposureUniform {
+    delta_time: f32,
+    adaptation_speed: f32,
+    key_value: f32,
+    min_exposure: f32,
+    max_exposure: f32,
+};
+@group(0) @binding(0)
+var<uniform> params: AutoExposureUniform;
+
+@group(0) @binding(1)
+var hdr_texture: texture_2d<f32>;
+
+struct Exposure {
+    value: f32,
+};
+@group(0) @binding(2)
+var<storage, read_write> exposure: Exposure;
+
+// Keep coherent with workgroup size.
+const INVOCATION_COUNT: u32 = 256u;
+const SAMPLES_PER_TILE_AXIS: u32 = 4u;
+
+var<workgroup> log_luminances: array<f32, INVOCATION_COUNT>;
+
+// Expects to be dispatched as a single workgroup. Each invocation averages the log luminance of
+// a tile of the texture, then the tiles are reduced into the scene's average luminance.
+@compute @workgroup_size(16, 16)
+fn cs_main(
+    @builtin(local_invocation_id) local_id: vec3u,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let texture_size = textureDimensions(hdr_texture);
+    let tile_size = max(texture_size / 16u, vec2u(1u));
+    let tile_origin = local_id.xy * tile_size;
+
+    var log_luminance_sum = 0.0;
+    for (var y = 0u; y < SAMPLES_PER_TILE_AXIS; y++) {
+        for (var x = 0u; x < SAMPLES_PER_TILE_AXIS; x++) {
+            let sample_offset = (vec2u(x, y) * tile_size) / SAMPLES_PER_TILE_AXIS;
+            let coords = min(tile_origin + sample_offset, texture_size - 1u);
+            let color = textureLoad(hdr_texture, coords, 0).rgb;
+            log_luminance_sum += log(max(luminance(color), 0.0001));
+        }
+    }
+    log_luminances[local_index] = log_luminance_sum / f32(SAMPLES_PER_TILE_AXIS * SAMPLES_PER_TILE_AXIS);
+    workgroupBarrier();
+
+    for (var stride = INVOCATION_COUNT / 2u; stride > 0u; stride = stride / 2u) {
+        if local_index < stride {
+            log_luminances[local_index] += log_luminances[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if local_index == 0u {
+        let average_luminance = exp(log_luminances[0] / f32(INVOCATION_COUNT));
+        let target_exposure = clamp(
+            params.key_value / average_luminance,
+            params.min_exposure,
+            params.max_exposure,
+        );
+        // Exponential smoothing so the eye adapts gradually instead of snapping.
+        let blend = 1.0 - exp(-params.delta_time * params.adaptation_speed);
+        exposure.value = mix(exposure.value, target_exposure, blend);
+    }
+}
+
+fn luminance(v: vec3f) -> f32 {
+    return 0.2126 * v.r + 0.7152 * v.g + 0.0722 * v.b;
+}
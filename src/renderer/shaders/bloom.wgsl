@@ -0,0 +1,22 @@
+#import fullscreen.wgsl
+#import blur.wgsl
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(blur(source_texture, source_sampler, in.uv, vec2f(1.0, 0.0)), 1.0);
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(blur(source_texture, source_sampler, in.uv, vec2f(0.0, 1.0)), 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(textureSample(source_texture, source_sampler, in.uv).rgb, 1.0);
+}
@@ -0,0 +1,24 @@
+// 9 tap gaussian blur, using bilinear filtering to sample two texels at once.
+const WEIGHT_0 = 0.2270270270;
+const WEIGHT_1 = 0.3162162162;
+const WEIGHT_2 = 0.0702702703;
+const OFFSET_1 = 1.3846153846;
+const OFFSET_2 = 3.2307692308;
+
+fn blur(
+    source_texture: texture_2d<f32>,
+    source_sampler: sampler,
+    uv: vec2f,
+    direction: vec2f,
+) -> vec3f {
+    let texel_size = vec2f(1.0) / vec2f(textureDimensions(source_texture));
+    let offset_1 = direction * OFFSET_1 * texel_size;
+    let offset_2 = direction * OFFSET_2 * texel_size;
+
+    var color = textureSample(source_texture, source_sampler, uv).rgb * WEIGHT_0;
+    color += textureSample(source_texture, source_sampler, uv + offset_1).rgb * WEIGHT_1;
+    color += textureSample(source_texture, source_sampler, uv - offset_1).rgb * WEIGHT_1;
+    color += textureSample(source_texture, source_sampler, uv + offset_2).rgb * WEIGHT_2;
+    color += textureSample(source_texture, source_sampler, uv - offset_2).rgb * WEIGHT_2;
+    return color;
+}
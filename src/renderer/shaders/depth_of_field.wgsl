@@ -0,0 +1,55 @@
+#import fullscreen.wgsl
+#import blur.wgsl
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+struct DepthOfFieldUniform {
+    focus_distance: f32,
+    aperture: f32,
+    near: f32,
+    far: f32,
+    orthographic: u32,
+};
+
+#ifdef MULTISAMPLED
+@group(0) @binding(2)
+var depth_texture: texture_multisampled_2d<f32>;
+#endif
+#ifndef MULTISAMPLED
+@group(0) @binding(2)
+var depth_texture: texture_2d<f32>;
+#endif
+@group(0) @binding(3)
+var<uniform> dof: DepthOfFieldUniform;
+
+// The horizontal pass reads the full resolution render target, the vertical one reads the half
+// resolution blur texture. Both spread their taps two half resolution texels apart.
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(blur(source_texture, source_sampler, in.uv, vec2f(4.0, 0.0)), 1.0);
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4f {
+    return vec4f(blur(source_texture, source_sampler, in.uv, vec2f(0.0, 2.0)), 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4f {
+    // Mip level when single sampled, sample index when multisampled.
+    let depth = textureLoad(depth_texture, vec2i(in.clip_position.xy), 0).r;
+    var view_depth: f32;
+    if dof.orthographic != 0u {
+        view_depth = dof.near + depth * (dof.far - dof.near);
+    } else {
+        view_depth = dof.near * dof.far / (dof.far - depth * (dof.far - dof.near));
+    }
+
+    // Circle of confusion, used as the opacity of the blurred image.
+    let coc = saturate(dof.aperture * abs(view_depth - dof.focus_distance) / view_depth);
+
+    return vec4f(textureSample(source_texture, source_sampler, in.uv).rgb, coc);
+}
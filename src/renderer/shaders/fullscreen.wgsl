@@ -0,0 +1,23 @@
+// Covers the screen, for passes working on whole images.
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) uv: vec2f,
+};
+
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Expects Topology::TriangleStrips, Ccw winding and 4 vertices
+    let x = f32(in_vertex_index / 2u) * 2.0 - 1.0;
+    let y = f32(1u - (in_vertex_index & 1u)) * 2.0 - 1.0;
+    out.clip_position = vec4f(x, y, 0.0, 1.0);
+
+    out.uv.x = f32(in_vertex_index / 2u);
+    out.uv.y = f32(in_vertex_index & 1u);
+
+    return out;
+}
@@ -3,14 +3,25 @@ struct SceneUniform {
     view: mat4x4f,
     camera_transform: mat4x4f,
     ambient_light: vec4f,
+    previous_projection_view: mat4x4f,
+    inv_projection_view: mat4x4f,
+    jitter: vec2f, // Temporal antialiasing subpixel offset, in normalized device coordinates.
 };
 @group(0) @binding(0)
 var<uniform> scene: SceneUniform;
 
 struct MaterialUniform {
     base_color: vec4f,
+    emissive: vec4f,
     billboard_mode: u32, // Off: 0, On: 1, Fixed-size: 2
     unlit: u32,
+    uv_scale: vec2f,
+    uv_offset: vec2f,
+    texture_mapping: u32, // Uv: 0, Triplanar: 1
+    metallic: f32,
+    roughness: f32,
+    alpha_mode: u32, // Opaque: 0, Mask: 1, Blend: 2
+    alpha_cutoff: f32,
 };
 @group(1) @binding(0)
 var<uniform> material: MaterialUniform;
@@ -18,9 +29,17 @@ var<uniform> material: MaterialUniform;
 var base_color_texture: texture_2d<f32>;
 @group(1) @binding(2)
 var material_sampler: sampler;
+@group(1) @binding(3)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(5)
+var emissive_texture: texture_2d<f32>;
 
 struct ModelUniform {
     transform: mat4x4f,
+    tint: vec4f,
+    previous_transform: mat4x4f,
 };
 @group(2) @binding(0)
 var<uniform> model: ModelUniform;
@@ -29,6 +48,22 @@ struct VertexInput {
     @location(0) pos: vec3f,
     @location(1) normal: vec3f,
     @location(2) uv: vec2f,
+    @location(3) tangent: vec4f,
+    @location(4) uv2: vec2f,
+    @location(5) color: vec4f,
+};
+
+// The model, per instance in instanced draws. Matrices are given by columns.
+struct InstanceInput {
+    @location(6) transform_x: vec4f,
+    @location(7) transform_y: vec4f,
+    @location(8) transform_z: vec4f,
+    @location(9) transform_w: vec4f,
+    @location(10) tint: vec4f,
+    @location(11) previous_transform_x: vec4f,
+    @location(12) previous_transform_y: vec4f,
+    @location(13) previous_transform_z: vec4f,
+    @location(14) previous_transform_w: vec4f,
 };
 
 struct VertexOutput {
@@ -36,6 +71,12 @@ struct VertexOutput {
     @location(0) frag_pos: vec3f,
     @location(1) normal: vec3f,
     @location(2) uv: vec2f,
+    // Unjittered positions of this frame and the previous one, for motion vectors.
+    @location(3) current_clip_position: vec4f,
+    @location(4) previous_clip_position: vec4f,
+    @location(5) tangent: vec4f,
+    @location(6) color: vec4f,
+    @location(7) @interpolate(flat) tint: vec4f,
 };
 
 
@@ -43,6 +84,28 @@ struct VertexOutput {
 fn vs_main(
     vertex: VertexInput,
 ) -> VertexOutput {
+    return transform_vertex(vertex, model);
+}
+
+@vertex
+fn vs_main_instanced(
+    vertex: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let instance_model = ModelUniform(
+        mat4x4f(instance.transform_x, instance.transform_y, instance.transform_z, instance.transform_w),
+        instance.tint,
+        mat4x4f(
+            instance.previous_transform_x,
+            instance.previous_transform_y,
+            instance.previous_transform_z,
+            instance.previous_transform_w,
+        ),
+    );
+    return transform_vertex(vertex, instance_model);
+}
+
+fn transform_vertex(vertex: VertexInput, model: ModelUniform) -> VertexOutput {
     var out: VertexOutput;
 
     let projection_view = scene.projection * scene.view;
@@ -50,49 +113,76 @@ fn vs_main(
     let vertex_pos_in_world_space = model.transform * vec4f(vertex.pos, 1.0);
     out.clip_position = projection_view * vertex_pos_in_world_space;
     out.frag_pos = vertex_pos_in_world_space.xyz;
+    out.previous_clip_position = scene.previous_projection_view * model.previous_transform * vec4f(vertex.pos, 1.0);
 
     // FIXME: This is incorrect, normals will be wrong with a non-uniform scaling factor (look up 'normal matrix')
     out.normal = (model.transform * vec4f(vertex.normal, 0.0)).xyz;
-    out.uv = vertex.uv;
+    out.tangent = vec4f((model.transform * vec4f(vertex.tangent.xyz, 0.0)).xyz, vertex.tangent.w);
+    out.uv = vertex.uv * material.uv_scale + material.uv_offset;
+    out.color = vertex.color;
+    out.tint = model.tint;
 
     if material.billboard_mode == 1u {
+        // Along the camera's axes, so that the xy plane faces the screen squarely, but sized
+        // by the model's scale.
+        let scale = vec3f(
+            length(model.transform.x.xyz),
+            length(model.transform.y.xyz),
+            length(model.transform.z.xyz),
+        );
         let transform = mat4x4f(
-            scene.camera_transform.x * 0.4,
-            scene.camera_transform.y * 0.4,
-            scene.camera_transform.z * 0.4,
+            vec4f(normalize(scene.camera_transform.x.xyz) * scale.x, 0.0),
+            vec4f(normalize(scene.camera_transform.y.xyz) * scale.y, 0.0),
+            vec4f(normalize(scene.camera_transform.z.xyz) * scale.z, 0.0),
             model.transform.w,
         );
         let vertex_pos_in_world_space = transform * vec4f(vertex.pos, 1.0);
         out.clip_position = projection_view * vertex_pos_in_world_space;
         out.frag_pos = vertex_pos_in_world_space.xyz;
+        out.previous_clip_position = out.clip_position;
     } else if material.billboard_mode == 2u {
+        // The model's scale is the height of the billboard, as a fraction of the screen height.
+        let size = length(model.transform.x.xyz) * 2.0;
+        let inv_aspect_ratio = scene.projection[0][0] / scene.projection[1][1];
         let vp_model_pos = projection_view * vec4f(model.transform.w.xyz, 1.0);
         out.clip_position = vp_model_pos;
         out.clip_position /= out.clip_position.w;
         out.clip_position = vec4f(
-            out.clip_position.xy + vertex.pos.xy * vec2f(0.4, 0.4),
+            out.clip_position.xy + vertex.pos.xy * vec2f(size * inv_aspect_ratio, size),
             out.clip_position.z,
             out.clip_position.w,
         );
-    
+        if vp_model_pos.w <= 0.0 {
+            // Behind the camera, clip it.
+            out.clip_position = vec4f(0.0, 0.0, -1.0, 1.0);
+        }
+
         out.normal = vertex.normal.xyz;
+        out.previous_clip_position = out.clip_position;
     }
 
+    out.current_clip_position = out.clip_position;
+    out.clip_position = vec4f(out.clip_position.xy + scene.jitter * out.clip_position.w, out.clip_position.zw);
+
     return out;
 }
 
 
 
+struct FragmentOutput {
+    @location(0) color: vec4f,
+    // Light that should bloom: emissive and overbright light.
+    @location(1) emissive: vec4f,
+    // Screen space motion since the previous frame, in texture coordinates.
+    @location(2) motion: vec4f,
+};
+
 @fragment
-fn fs_main_ambient_light_depth_prepass(in: VertexOutput) -> @location(0) vec4f {
+fn fs_main_ambient_light_depth_prepass(in: VertexOutput) -> FragmentOutput {
     let normal = normalize(in.normal);
-    var base_color = material.base_color.rgba * textureSample(base_color_texture, material_sampler, in.uv).rgba;
+    var base_color = in.tint * material.base_color.rgba * in.color * sample_base_color_texture(in, normal);
     
-    if base_color.a < 0.5 {
-        discard;
-    } else {
-        base_color.a = 1.0;
-    }
+    base_color.a = apply_alpha_mode(base_color.a);
 
     var ambient_light = base_color.rgb;
     if material.unlit == 0u {
@@ -103,7 +193,60 @@ fn fs_main_ambient_light_depth_prepass(in: VertexOutput) -> @location(0) vec4f {
         );
     }
 
-    return vec4f(ambient_light, base_color.a);
+    let emissive = in.tint.rgb * material.emissive.rgb * textureSample(emissive_texture, material_sampler, in.uv).rgb;
+
+    var out: FragmentOutput;
+    out.color = vec4f(ambient_light + emissive, base_color.a);
+    out.emissive = vec4f(emissive, base_color.a);
+    out.motion = vec4f(compute_motion(in), 0.0, 0.0);
+    return out;
+}
+
+// Discards masked out fragments and gives the alpha to blend with, 1 unless blended.
+fn apply_alpha_mode(alpha: f32) -> f32 {
+    if material.alpha_mode == 1u && alpha < material.alpha_cutoff {
+        discard;
+    }
+    if material.alpha_mode == 2u {
+        return alpha;
+    }
+    return 1.0;
+}
+
+fn compute_motion(in: VertexOutput) -> vec2f {
+    // Nothing was rendered before the first frame.
+    if in.previous_clip_position.w <= 0.0 {
+        return vec2f(0.0);
+    }
+    let current = in.current_clip_position.xy / in.current_clip_position.w;
+    let previous = in.previous_clip_position.xy / in.previous_clip_position.w;
+    return (current - previous) * vec2f(0.5, -0.5);
+}
+
+fn sample_base_color_texture(in: VertexOutput, normal: vec3f) -> vec4f {
+    if material.texture_mapping == 0u {
+        return textureSample(base_color_texture, material_sampler, in.uv);
+    }
+
+    // Triplanar: one projection per world axis, weighted by how much the surface faces it.
+    var weights = pow(abs(normal), vec3f(4.0));
+    weights /= weights.x + weights.y + weights.z;
+    let x_projection = textureSample(base_color_texture, material_sampler, in.frag_pos.zy * material.uv_scale + material.uv_offset);
+    let y_projection = textureSample(base_color_texture, material_sampler, in.frag_pos.xz * material.uv_scale + material.uv_offset);
+    let z_projection = textureSample(base_color_texture, material_sampler, in.frag_pos.xy * material.uv_scale + material.uv_offset);
+    return x_projection * weights.x + y_projection * weights.y + z_projection * weights.z;
+}
+
+// Perturbs the surface normal with the material's normal image, in tangent space.
+fn compute_mapped_normal(in: VertexOutput, normal: vec3f) -> vec3f {
+    let tangent_space_normal = textureSample(normal_texture, material_sampler, in.uv).xyz * 2.0 - 1.0;
+    // Vertices without tangents can't orient the normal image.
+    if dot(in.tangent.xyz, in.tangent.xyz) == 0.0 {
+        return normal;
+    }
+    let tangent = normalize(in.tangent.xyz - normal * dot(normal, in.tangent.xyz));
+    let bitangent = cross(normal, tangent) * in.tangent.w;
+    return normalize(mat3x3f(tangent, bitangent, normal) * tangent_space_normal);
 }
 
 fn compute_ambient_light(base_color: vec3f, light_color: vec3f, light_intensity: f32) -> vec3f {
@@ -115,93 +258,147 @@ fn compute_ambient_light(base_color: vec3f, light_color: vec3f, light_intensity:
 
 struct LightUniform {
     transform: mat4x4f,
-    cascades_world_to_light: array<mat4x4f, 3>, // NOTE hardcoded max cascade limit to 3
+    // Must match VisualServer::MAX_SHADOW_CASCADES.
+    cascades_world_to_light: array<mat4x4f, 4>,
+    cascade_ends: vec4f, // View depths
     color: vec4f,
     radius: f32,
-    kind: u32, // Directional=0, Point=1
+    kind: u32, // Directional=0, Point=1, Spot=2
+    shadow_filter_radius: u32,
+    shadow_cascade_blend_width: f32,
+    spot_cos_angles: vec2f, // Inner, outer
+    casts_shadows: u32,
+    shadow_cascade_count: u32,
 };
 @group(3) @binding(0)
 var<uniform> light: LightUniform;
 
 const LIGHT_KIND_DIRECTIONAL = 0u;
 const LIGHT_KIND_POINT = 1u;
+const LIGHT_KIND_SPOT = 2u;
 
 @group(3) @binding(1)
-var shadow_maps: texture_2d_array<f32>;
+var shadow_map_sampler: sampler_comparison;
+// One per cascade, each with its own resolution. Must match VisualServer::MAX_SHADOW_CASCADES.
 @group(3) @binding(2)
-var shadow_map_sampler: sampler;
+var shadow_map_0: texture_depth_2d;
+@group(3) @binding(3)
+var shadow_map_1: texture_depth_2d;
+@group(3) @binding(4)
+var shadow_map_2: texture_depth_2d;
+@group(3) @binding(5)
+var shadow_map_3: texture_depth_2d;
 
 @fragment
-fn fs_main_blinn_phong(in: VertexOutput) -> @location(0) vec4f {
+fn fs_main_lights(in: VertexOutput) -> FragmentOutput {
     if material.unlit == 1u {
         // TODO This probably should just not be a draw call...
         discard;
     }
 
     let normal = normalize(in.normal);
-    let base_color = material.base_color.rgba * textureSample(base_color_texture, material_sampler, in.uv).rgba;
+    let base_color = in.tint * material.base_color.rgba * in.color * sample_base_color_texture(in, normal);
+    let mapped_normal = compute_mapped_normal(in, normal);
+    let alpha = apply_alpha_mode(base_color.a);
 
-    if base_color.a < 0.5 {
-        discard;
-    }
+    // glTF layout: roughness in green, metallic in blue.
+    let metallic_roughness = textureSample(metallic_roughness_texture, material_sampler, in.uv);
+    let metallic = material.metallic * metallic_roughness.b;
+    let roughness = material.roughness * metallic_roughness.g;
 
     let from_frag_to_view_dir = normalize(scene.camera_transform.w.xyz - in.frag_pos);
     var light_contribution = vec3f(0.0);
     if light.kind == LIGHT_KIND_DIRECTIONAL {
         let light_direction = light.transform.z.xyz;
         let occlusion = compute_light_occlusion(in.frag_pos, normal, light_direction);
-        light_contribution = compute_light_blinn_phong(
+        light_contribution = compute_light_pbr(
             base_color.rgb,
-            normal,
+            metallic,
+            roughness,
+            mapped_normal,
             from_frag_to_view_dir,
             light_direction,
             light.color.rgb,
             light.color.a * (1.0 - occlusion),
-            8.0,
         );
-    } else if light.kind == LIGHT_KIND_POINT {
+    } else if light.kind == LIGHT_KIND_POINT || light.kind == LIGHT_KIND_SPOT {
         let distance = distance(in.frag_pos, light.transform.w.xyz);
         if distance > light.radius {
             discard;
         }
         let light_direction = normalize(in.frag_pos - light.transform.w.xyz);
-        let attenuation = compute_light_attenuation(distance, light.radius);
-        light_contribution = compute_light_blinn_phong(
+        var attenuation = compute_light_attenuation(distance, light.radius);
+        if light.kind == LIGHT_KIND_SPOT {
+            let cos_angle = dot(light_direction, normalize(light.transform.z.xyz));
+            attenuation *= smoothstep(light.spot_cos_angles.y, light.spot_cos_angles.x, cos_angle);
+        }
+        light_contribution = compute_light_pbr(
             base_color.rgb,
-            normal,
+            metallic,
+            roughness,
+            mapped_normal,
             from_frag_to_view_dir,
             light_direction,
             light.color.rgb,
             light.color.a * attenuation,
-            8.0,
         );
     }
 
-    return vec4f(light_contribution, 1.0);
+    var out: FragmentOutput;
+    out.color = vec4f(light_contribution, alpha);
+    // Only what goes over what a regular surface can reflect blooms.
+    out.emissive = vec4f(max(light_contribution - vec3f(1.0), vec3f(0.0)), alpha);
+    // Not written, the depth prepass already did.
+    out.motion = vec4f(0.0);
+    return out;
 }
 
 // https://learnopengl.com/Advanced-Lighting/Shadows/Shadow-Mapping
 fn compute_light_occlusion(frag_pos: vec3f, normal: vec3f, light_dir: vec3f) -> f32 {
-    if dot(normal, light_dir) > 0.0 {
+    if light.casts_shadows == 0u || dot(normal, light_dir) > 0.0 {
         return 0.0;
     }
 
-    var cascade_layer = 0;
-    var cascade_bias_mod = 0.0;
+    let last_cascade_layer = i32(light.shadow_cascade_count) - 1;
+    var cascade_layer = last_cascade_layer;
     let frag_view_depth = (scene.view * vec4f(frag_pos, 1.0)).z;
-    // FIXME hardcoded cascade depths is bad, use something like #define's
-    if frag_view_depth <= (0.05 * 100.0) {
-        cascade_layer = 0;
+    for (var i = 0; i < last_cascade_layer; i++) {
+        if frag_view_depth <= light.cascade_ends[i] {
+            cascade_layer = i;
+            break;
+        }
+    }
+    var cascade_start = 0.0;
+    if cascade_layer > 0 {
+        cascade_start = light.cascade_ends[cascade_layer - 1];
+    }
+    let cascade_end = light.cascade_ends[cascade_layer];
+
+    let occlusion = compute_cascade_occlusion(cascade_layer, frag_pos, normal, light_dir);
+
+    // Near the end of a cascade, blend with the next one to hide the seam between them.
+    let blend_band = (cascade_end - cascade_start) * light.shadow_cascade_blend_width;
+    let blend_start = cascade_end - blend_band;
+    if cascade_layer < last_cascade_layer && frag_view_depth > blend_start {
+        let next_occlusion = compute_cascade_occlusion(cascade_layer + 1, frag_pos, normal, light_dir);
+        let blend = (frag_view_depth - blend_start) / blend_band;
+        return mix(occlusion, next_occlusion, blend);
+    }
+
+    return occlusion;
+}
+
+// Must match VisualServer::MAX_SHADOW_FILTER_RADIUS.
+const MAX_SHADOW_FILTER_RADIUS: i32 = 3;
+
+fn compute_cascade_occlusion(cascade_layer: i32, frag_pos: vec3f, normal: vec3f, light_dir: vec3f) -> f32 {
+    var cascade_bias_mod = 0.2;
+    if cascade_layer == 0 {
         cascade_bias_mod = 0.03;
-    } else if frag_view_depth <= (0.1 * 100.0) {
-        cascade_layer = 1;
+    } else if cascade_layer == 1 {
         cascade_bias_mod = 0.05;
-    } else if frag_view_depth <= (0.3 * 100.0) {
-        cascade_layer = 2;
+    } else if cascade_layer == 2 {
         cascade_bias_mod = 0.1;
-    } else {
-        cascade_layer = 3;
-        cascade_bias_mod = 0.2;
     }
 
     // These bias values are pretty arbitrary... TODO learn how to properly fix shadow acne.
@@ -223,27 +420,26 @@ fn compute_light_occlusion(frag_pos: vec3f, normal: vec3f, light_dir: vec3f) ->
     shadow_map_coords = vec2f(shadow_map_coords.x, 1.0 - shadow_map_coords.y);
     let frag_depth = clamp(ndc_coords.z, 0.0, 1.0);
 
-    let shadow_map_size = vec2f(textureDimensions(shadow_maps));
-    let texel_size = vec2f(1.0) / shadow_map_size;
+    let texel_size = vec2f(1.0) / cascade_shadow_map_size(cascade_layer);
  
     var occlusion = 0.0;
     var sample_count = 0.0;
-    for (var x = -1; x <= 1; x++) {
-        for (var y = -1; y <= 1; y++) {
+    let filter_radius = min(i32(light.shadow_filter_radius), MAX_SHADOW_FILTER_RADIUS);
+    // Constant bounds, so the loops can be unrolled.
+    for (var x = -MAX_SHADOW_FILTER_RADIUS; x <= MAX_SHADOW_FILTER_RADIUS; x++) {
+        for (var y = -MAX_SHADOW_FILTER_RADIUS; y <= MAX_SHADOW_FILTER_RADIUS; y++) {
+            if abs(x) > filter_radius || abs(y) > filter_radius {
+                continue;
+            }
             sample_count += 1.0;
 
             let sample_offset = vec2f(vec2(x, y)) * texel_size;
-            // https://www.w3.org/TR/WGSL/#texturesample
-            let occluder_depth = textureSample(
-                shadow_maps,
-                shadow_map_sampler,
-                shadow_map_coords.xy + sample_offset,
+            let lit = sample_cascade_shadow_map(
                 cascade_layer,
-            ).r;
-
-            if frag_depth > occluder_depth {
-                occlusion += 1.0;
-            }
+                shadow_map_coords.xy + sample_offset,
+                frag_depth,
+            );
+            occlusion += 1.0 - lit;
         }
     }
     occlusion = occlusion / sample_count;
@@ -251,28 +447,67 @@ fn compute_light_occlusion(frag_pos: vec3f, normal: vec3f, light_dir: vec3f) ->
     return occlusion;
 }
 
+fn cascade_shadow_map_size(cascade_layer: i32) -> vec2f {
+    switch cascade_layer {
+        case 0: { return vec2f(textureDimensions(shadow_map_0)); }
+        case 1: { return vec2f(textureDimensions(shadow_map_1)); }
+        case 2: { return vec2f(textureDimensions(shadow_map_2)); }
+        default: { return vec2f(textureDimensions(shadow_map_3)); }
+    }
+}
+
+// Always samples the first level, so this can be called from non-uniform control flow.
+// https://www.w3.org/TR/WGSL/#texturesamplecomparelevel
+fn sample_cascade_shadow_map(cascade_layer: i32, coords: vec2f, depth: f32) -> f32 {
+    switch cascade_layer {
+        case 0: { return textureSampleCompareLevel(shadow_map_0, shadow_map_sampler, coords, depth); }
+        case 1: { return textureSampleCompareLevel(shadow_map_1, shadow_map_sampler, coords, depth); }
+        case 2: { return textureSampleCompareLevel(shadow_map_2, shadow_map_sampler, coords, depth); }
+        default: { return textureSampleCompareLevel(shadow_map_3, shadow_map_sampler, coords, depth); }
+    }
+}
+
 #endif
 
-fn compute_light_blinn_phong(
+const PI = 3.14159265;
+
+// Cook-Torrance with a GGX distribution, Smith-Schlick geometry and Schlick fresnel.
+// https://learnopengl.com/PBR/Theory
+fn compute_light_pbr(
     base_color: vec3f,
+    metallic: f32,
+    roughness: f32,
     normal: vec3f,
     from_frag_to_view_dir: vec3f,
     light_dir: vec3f,
     light_color: vec3f,
     light_intensity: f32,
-    shininess: f32,
 ) -> vec3f {
-    // Diffuse
     let from_frag_to_light_dir = -light_dir;
-    let diffuse_intensity = max(dot(normal, from_frag_to_light_dir), 0.0);
-    let diffuse = light_color * light_intensity * diffuse_intensity;
-
-    // Specular
     let halfway_dir = normalize(from_frag_to_light_dir + from_frag_to_view_dir);
-    let spec_intensity = pow(max(dot(normal, halfway_dir), 0.0), shininess);
-    let spec = light_color * light_intensity * spec_intensity;
+    let n_dot_l = max(dot(normal, from_frag_to_light_dir), 0.0);
+    let n_dot_v = max(dot(normal, from_frag_to_view_dir), 0.0001);
+    let n_dot_h = max(dot(normal, halfway_dir), 0.0);
+    let v_dot_h = max(dot(from_frag_to_view_dir, halfway_dir), 0.0);
+
+    // Fully smooth surfaces would have infinitely small highlights.
+    let alpha = max(roughness * roughness, 0.002);
+    let alpha2 = alpha * alpha;
+    let distribution_denom = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * distribution_denom * distribution_denom);
+
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = (n_dot_v / (n_dot_v * (1.0 - k) + k)) * (n_dot_l / (n_dot_l * (1.0 - k) + k));
+
+    let f0 = mix(vec3f(0.04), base_color, metallic);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color / PI;
 
-    return base_color * (diffuse + spec);
+    // Light intensities are scaled by pi, so that a white diffuse surface facing a light of
+    // intensity 1 reflects 1.
+    return (diffuse + specular) * PI * light_color * light_intensity * n_dot_l;
 }
 
 fn compute_light_attenuation(distance: f32, max_distance: f32) -> f32 {
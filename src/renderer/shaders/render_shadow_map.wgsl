@@ -4,6 +4,14 @@ struct VertexInput {
     @location(2) uv: vec2f,
 };
 
+// The model's transform, per instance in instanced draws. Given by columns.
+struct InstanceInput {
+    @location(6) transform_x: vec4f,
+    @location(7) transform_y: vec4f,
+    @location(8) transform_z: vec4f,
+    @location(9) transform_w: vec4f,
+};
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
 };
@@ -17,6 +25,7 @@ var<uniform> cascade: CascadeUniform;
 
 struct ModelUniform {
     transform: mat4x4f,
+    tint: vec4f,
 };
 @group(1) @binding(0)
 var<uniform> model: ModelUniform;
@@ -24,9 +33,19 @@ var<uniform> model: ModelUniform;
 
 @vertex
 fn vs_main(vertex: VertexInput) -> VertexOutput {
+    return transform_vertex(vertex, model.transform);
+}
+
+@vertex
+fn vs_main_instanced(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let transform = mat4x4f(instance.transform_x, instance.transform_y, instance.transform_z, instance.transform_w);
+    return transform_vertex(vertex, transform);
+}
+
+fn transform_vertex(vertex: VertexInput, transform: mat4x4f) -> VertexOutput {
     var out: VertexOutput;
 
-    let vertex_pos_in_world_space = model.transform * vec4f(vertex.pos, 1.0);
+    let vertex_pos_in_world_space = transform * vec4f(vertex.pos, 1.0);
     out.clip_position = cascade.projection_view * vertex_pos_in_world_space;
 
     return out;
@@ -24,6 +24,9 @@ fn vs_main(
 
 struct ShowTextureUniform {
     tone_mapping: u32,
+    exposure: f32,
+    upscale_filter: u32,
+    manual_exposure: f32,
 };
 @group(0) @binding(0)
 var<uniform> render: ShowTextureUniform;
@@ -35,11 +38,46 @@ var tex_sampler: sampler;
 
 const TONE_MAPPING_NONE: u32 = 0u;
 const TONE_MAPPING_REINHARD: u32 = 1u;
+const TONE_MAPPING_ACES_FILMIC: u32 = 2u;
+const TONE_MAPPING_UNCHARTED2: u32 = 3u;
+
+const UPSCALE_FILTER_NEAREST: u32 = 0u;
+const UPSCALE_FILTER_BILINEAR: u32 = 1u;
+const UPSCALE_FILTER_SHARPEN: u32 = 2u;
+
+const SHARPNESS: f32 = 0.8;
+
+const UNCHARTED2_EXPOSURE_BIAS: f32 = 2.0;
+const UNCHARTED2_WHITE_POINT: f32 = 11.2;
 
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    var color = textureSample(tex_texture, tex_sampler, in.uv);
+    let color = sample(in.uv);
+    if render.upscale_filter != UPSCALE_FILTER_SHARPEN {
+        return color;
+    }
+
+    // Contrast adaptive sharpening, on the cross of texels around the pixel. Sharpens less
+    // where the contrast is already high, to avoid ringing.
+    let texel_size = vec2f(1.0) / vec2f(textureDimensions(tex_texture));
+    let north = sample(in.uv + vec2f(0.0, -texel_size.y)).rgb;
+    let south = sample(in.uv + vec2f(0.0, texel_size.y)).rgb;
+    let west = sample(in.uv + vec2f(-texel_size.x, 0.0)).rgb;
+    let east = sample(in.uv + vec2f(texel_size.x, 0.0)).rgb;
+
+    let min_rgb = min(color.rgb, min(min(north, south), min(west, east)));
+    let max_rgb = max(color.rgb, max(max(north, south), max(west, east)));
+    let amplitude = sqrt(saturate(min(min_rgb, 1.0 - max_rgb) / max(max_rgb, vec3f(0.0001))));
+    let weight = amplitude * (-1.0 / mix(8.0, 5.0, SHARPNESS));
+
+    let sharpened = (color.rgb + (north + south + west + east) * weight) / (1.0 + 4.0 * weight);
+    return vec4f(saturate(sharpened), color.a);
+}
+
+fn sample(uv: vec2f) -> vec4f {
+    var color = textureSample(tex_texture, tex_sampler, uv);
+    color = vec4f(color.rgb * render.exposure * render.manual_exposure, color.a);
     
     switch render.tone_mapping {
         case TONE_MAPPING_REINHARD: {
@@ -48,6 +86,15 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4f {
             color.g = tone_mapped.g;
             color.b = tone_mapped.b;
         }
+        case TONE_MAPPING_ACES_FILMIC: {
+            color = vec4f(aces_filmic(color.rgb), color.a);
+        }
+        case TONE_MAPPING_UNCHARTED2: {
+            // The curve is scaled so that the white point maps to 1.
+            let tone_mapped = uncharted2(color.rgb * UNCHARTED2_EXPOSURE_BIAS)
+                / uncharted2(vec3f(UNCHARTED2_WHITE_POINT));
+            color = vec4f(tone_mapped, color.a);
+        }
         default: {
             // Don't.
         }
@@ -56,6 +103,27 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4f {
     return color;
 }
 
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces_filmic(x: vec3f) -> vec3f {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return saturate((x * (a * x + b)) / (x * (c * x + d) + e));
+}
+
+// http://filmicworlds.com/blog/filmic-tonemapping-operators/
+fn uncharted2(x: vec3f) -> vec3f {
+    let a = 0.15; // Shoulder strength
+    let b = 0.50; // Linear strength
+    let c = 0.10; // Linear angle
+    let d = 0.20; // Toe strength
+    let e = 0.02; // Toe numerator
+    let f = 0.30; // Toe denominator
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
 fn luminance(v: vec3f) -> f32 {
     return 0.2126 * v.r + 0.7152 * v.g + 0.0722 * v.b;
-}
\ No newline at end of file
+}
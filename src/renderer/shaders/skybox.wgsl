@@ -0,0 +1,67 @@
+struct SceneUniform {
+    projection: mat4x4f,
+    view: mat4x4f,
+    camera_transform: mat4x4f,
+    ambient_light: vec4f,
+    previous_projection_view: mat4x4f,
+    inv_projection_view: mat4x4f,
+    jitter: vec2f, // Temporal antialiasing subpixel offset, in normalized device coordinates.
+};
+@group(0) @binding(0)
+var<uniform> scene: SceneUniform;
+
+@group(1) @binding(0)
+var skybox_texture: texture_cube<f32>;
+@group(1) @binding(1)
+var skybox_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) ndc: vec2f,
+};
+
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Expects Topology::TriangleStrips, Ccw winding and 4 vertices
+    let x = f32(in_vertex_index / 2u) * 2.0 - 1.0;
+    let y = f32(1u - (in_vertex_index & 1u)) * 2.0 - 1.0;
+    // On the far plane, so that only what nothing was drawn over passes the depth test.
+    out.clip_position = vec4f(x, y, 1.0, 1.0);
+    out.ndc = vec2f(x, y);
+
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4f,
+    @location(1) emissive: vec4f,
+    @location(2) motion: vec4f,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // What's seen through the pixel is what was there before the jitter moved it.
+    let ndc = in.ndc - scene.jitter;
+    let near = scene.inv_projection_view * vec4f(ndc, 0.0, 1.0);
+    let far = scene.inv_projection_view * vec4f(ndc, 1.0, 1.0);
+    let direction = far.xyz / far.w - near.xyz / near.w;
+
+    var out: FragmentOutput;
+    out.color = vec4f(textureSample(skybox_texture, skybox_sampler, direction).rgb, 1.0);
+    out.emissive = vec4f(0.0, 0.0, 0.0, 1.0);
+
+    let previous_clip_position = scene.previous_projection_view * vec4f(far.xyz / far.w, 1.0);
+    // Nothing was rendered before the first frame.
+    if previous_clip_position.w > 0.0 {
+        let previous = previous_clip_position.xy / previous_clip_position.w;
+        out.motion = vec4f((ndc - previous) * vec2f(0.5, -0.5), 0.0, 0.0);
+    } else {
+        out.motion = vec4f(0.0);
+    }
+    return out;
+}
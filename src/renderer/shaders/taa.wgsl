@@ -0,0 +1,55 @@
+#import fullscreen.wgsl
+
+struct TaaUniform {
+    history_weight: f32,
+    clamp_history: u32,
+};
+
+@group(0) @binding(0)
+var current_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var history_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var motion_texture: texture_2d<f32>;
+@group(0) @binding(3)
+var history_sampler: sampler;
+@group(0) @binding(4)
+var<uniform> taa: TaaUniform;
+
+@fragment
+fn fs_resolve(in: VertexOutput) -> @location(0) vec4f {
+    let coords = vec2i(in.clip_position.xy);
+    let max_coords = vec2i(textureDimensions(current_texture)) - vec2i(1);
+    let current = textureLoad(current_texture, coords, 0).rgb;
+
+    // The history gets clamped to the colors around the pixel, so that what was disoccluded or
+    // changed doesn't leave ghosts behind.
+    var neighborhood_min = current;
+    var neighborhood_max = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbor_coords = clamp(coords + vec2i(x, y), vec2i(0), max_coords);
+            let neighbor = textureLoad(current_texture, neighbor_coords, 0).rgb;
+            neighborhood_min = min(neighborhood_min, neighbor);
+            neighborhood_max = max(neighborhood_max, neighbor);
+        }
+    }
+
+    let motion = textureLoad(motion_texture, coords, 0).xy;
+    let history_uv = in.uv - motion;
+    var history = textureSample(history_texture, history_sampler, history_uv).rgb;
+    if taa.clamp_history == 1u {
+        history = clamp(history, neighborhood_min, neighborhood_max);
+    }
+
+    // What was off screen last frame has no history.
+    let on_screen = all(history_uv >= vec2f(0.0)) && all(history_uv <= vec2f(1.0));
+    let history_weight = select(0.0, taa.history_weight, on_screen);
+
+    return vec4f(mix(current, history, history_weight), 1.0);
+}
+
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4f {
+    return textureLoad(current_texture, vec2i(in.clip_position.xy), 0);
+}
@@ -4,13 +4,13 @@
 struct InstanceInput {
     @location(10) pos: vec2f,
     @location(11) scale: vec2f,
-    @location(12) glyph: u32,
+    // Min and max uv of the glyph in the font image.
+    @location(12) uv_rect: vec4f,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) uv: vec2f,
-    @location(1) font_size: f32,
 };
 
 struct ViewportUniform {
@@ -25,6 +25,19 @@ var font_atlas: texture_2d<f32>;
 @group(1) @binding(1)
 var tex_sampler: sampler;
 
+struct TextStyleUniform {
+    outline_color: vec4f,
+    // In pixels.
+    outline_width: f32,
+};
+@group(2) @binding(0)
+var<uniform> text_style: TextStyleUniform;
+
+// Value of the glyphs' edges in the font image. It grows away from the glyphs.
+// TODO: potential improvement: have this calculated from data about the sdffont instead of hand
+// picking it.
+const EDGE_DISTANCE: f32 = 0.37;
+
 
 
 @vertex
@@ -34,7 +47,6 @@ fn vs_main(
     instance: InstanceInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.font_size = instance.scale.y;
 
     let viewport_size = vec2f(viewport.size);
 
@@ -50,19 +62,11 @@ fn vs_main(
     let clip_pos = (translated_pos / viewport_size) * 2.0 - 1.0;
     out.clip_position = vec4f(clip_pos, 0.0, 1.0);
 
-    // Expects a wide image of 128 monospaced characters
     let quad_uv = vec2f(
         f32(in_vertex_index / 2u),
         f32(in_vertex_index & 1u),
     );
-    let glyph_tile_count = 128.0;
-    let glyph_tile_width = 1.0 / glyph_tile_count;
-    let glyph_uv = vec2f(
-        (quad_uv.x / glyph_tile_count) + f32(instance.glyph) * glyph_tile_width,
-        quad_uv.y,
-    );
-
-    out.uv = glyph_uv;
+    out.uv = mix(instance.uv_rect.xy, instance.uv_rect.zw, quad_uv);
 
     return out;
 }
@@ -74,41 +78,28 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4f {
 }
 
 fn fs_signed_distance_field(in: VertexOutput) -> vec4f {
-    let fill_color = vec3f(1.0);
-    let outline_color = vec3f(0.0);
-    
-    // TODO: potential improvement: have these values automatically calculated from data about the sdffont
-    // instead of hand picking them.
-    let fill_smoothcenter = 0.37;
-    let outline_smoothcenter = 0.8;
-    var smoothing = clamp(
-        map_range(in.font_size, 12.0, 24.0, 0.25, 0.15),
-        0.1,
-        0.9,
+    let fill_color = vec4f(1.0);
+
+    let distance = textureSample(font_atlas, tex_sampler, in.uv).b;
+    // How much the distance changes from one pixel to the next, whatever the font size. Edges
+    // are smoothed over one pixel so they stay crisp when scaled up.
+    let pixel_distance = max(length(vec2f(dpdx(distance), dpdy(distance))), 0.0001);
+    let smoothing = pixel_distance * 0.5;
+
+    let fill_alpha = 1.0 - smoothstep(EDGE_DISTANCE - smoothing, EDGE_DISTANCE + smoothing, distance);
+    // Outlines can't reach further than the distances stored in the font image.
+    let outline_edge = EDGE_DISTANCE + text_style.outline_width * pixel_distance;
+    let outline_alpha = select(
+        0.0,
+        1.0 - smoothstep(outline_edge - smoothing, outline_edge + smoothing, distance),
+        text_style.outline_width > 0.0,
     );
-    // 12px - 0.3
-    // 16px - 0.18
-    // 24px - 0.15
 
-    let sd = textureSample(font_atlas, tex_sampler, in.uv);
-    
-    let fill_dist = smoothstep(fill_smoothcenter - smoothing, fill_smoothcenter + smoothing, sd.b);
-    let outline_dist = smoothstep(outline_smoothcenter - smoothing, outline_smoothcenter + smoothing, sd.b);
-
-    let fill_alpha = 1.0 - fill_dist;
-    let outline_alpha = 1.0 - outline_dist;
-
-    let alpha = outline_alpha;
-    let color = mix(outline_color, fill_color, fill_alpha);
+    // The fill over the outline.
+    let fill = fill_color.a * fill_alpha;
+    let outline = text_style.outline_color.a * outline_alpha * (1.0 - fill);
+    let alpha = fill + outline;
+    let color = (fill_color.rgb * fill + text_style.outline_color.rgb * outline) / max(alpha, 0.0001);
 
     return vec4f(color, alpha);
 }
-
-fn map_range(value: f32, from_min: f32, from_max: f32, to_min: f32, to_max: f32) -> f32 {
-    let from_range_size = from_max - from_min;
-    let value_from_ratio = (value - from_min) / from_range_size;
-
-    let to_range_size = to_max - to_min;
-    let mapped_value = to_min + value_from_ratio * to_range_size;
-    return mapped_value;
-}
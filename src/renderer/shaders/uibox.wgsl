@@ -2,11 +2,14 @@ struct InstanceInput {
     @location(10) pos: vec2f,
     @location(11) size: vec2f,
     @location(12) color: vec4f,
+    @location(13) uv_rect: vec4f,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) color: vec4f,
+    @location(1) uv: vec2f,
+    @location(2) @interpolate(flat) textured: u32,
 };
 
 struct ViewportUniform {
@@ -15,6 +18,10 @@ struct ViewportUniform {
 @group(0) @binding(0)
 var<uniform> viewport: ViewportUniform;
 
+@group(1) @binding(0)
+var atlas_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
 
 
 @vertex
@@ -41,11 +48,17 @@ fn vs_main(
     let clip_pos = (translated_pos / viewport_size) * 2.0 - 1.0;
     out.clip_position = vec4f(clip_pos, 0.0, 1.0);
 
+    // Boxes without an image have an all zero uv rect.
+    out.uv = mix(instance.uv_rect.xy, instance.uv_rect.zw, vec2f(x, 1.0 - y));
+    out.textured = u32(any(instance.uv_rect != vec4f(0.0)));
+
     return out;
 }
 
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return in.color;
+    // Sampled in uniform control flow, and then ignored for boxes without an image.
+    let texel = textureSample(atlas_texture, atlas_sampler, in.uv);
+    return in.color * select(vec4f(1.0), texel, in.textured == 1u);
 }
@@ -0,0 +1,500 @@
+use std::sync::Arc;
+
+use glam::{UVec2, Vec2};
+
+use crate::{arena::Handle, asset_server::AssetChanges, shader_source::ShaderSource, AssetServer};
+
+use super::{
+    backend::{texture_byte_size, Backend},
+    fullscreen::{
+        build_pipeline, create_texture, draw_fullscreen, sampler_layout_entry, texture_layout_entry,
+    },
+    render_graph::{PassContext, PostPass, Slot},
+    visual_server::RenderTarget,
+};
+
+pub struct Taa {
+    motion_buffer: MotionBuffer,
+    history_textures: [(wgpu::Texture, wgpu::TextureView); 2],
+    // Index of the history texture holding the last resolved frame.
+    history_index: usize,
+    history_valid: bool,
+    accumulation: bool,
+    // Frames averaged in the history while accumulating.
+    accumulated_frames: u32,
+    frame_index: u32,
+    uniform_buffer: wgpu::Buffer,
+    bind_groups: BindGroups,
+    bind_group_layouts: BindGroupLayouts,
+    resolve_pipeline_layout: wgpu::PipelineLayout,
+    copy_pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Pipelines,
+    sampler: Arc<wgpu::Sampler>,
+    target_format: wgpu::TextureFormat,
+    shader_source: Handle<ShaderSource>,
+    shader: wgpu::ShaderModule,
+}
+
+impl Taa {
+    pub const MOTION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+    // Long enough to keep finding new subpixel positions while accumulating.
+    const JITTER_SEQUENCE_LENGTH: u32 = 64;
+    // How much of the history is kept every frame.
+    const HISTORY_WEIGHT: f32 = 0.9;
+    const MAX_ACCUMULATED_FRAMES: u32 = 64;
+
+    pub fn new(
+        render_target: &RenderTarget,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) -> Self {
+        let shader_source = asset_server.load::<ShaderSource>("src/renderer/shaders/taa.wgsl");
+        let shader =
+            backend.create_shader_module("taa shader", asset_server.get(shader_source).source());
+
+        let resolve = backend
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("taa resolve bind group layout"),
+                entries: &[
+                    texture_layout_entry(0),
+                    texture_layout_entry(1),
+                    texture_layout_entry(2),
+                    sampler_layout_entry(3),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let copy = backend
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("taa copy bind group layout"),
+                entries: &[texture_layout_entry(0)],
+            });
+        let bind_group_layouts = BindGroupLayouts { resolve, copy };
+
+        let resolve_pipeline_layout =
+            backend
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("taa resolve pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layouts.resolve],
+                    push_constant_ranges: &[],
+                });
+        let copy_pipeline_layout =
+            backend
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("taa copy pipeline layout"),
+                    bind_group_layouts: &[&bind_group_layouts.copy],
+                    push_constant_ranges: &[],
+                });
+
+        let sampler = backend.sampler();
+        let uniform_buffer = backend.create_uniform_buffer(TaaUniform::default());
+
+        let motion_buffer = MotionBuffer::new(render_target, backend);
+        let history_textures = create_history_textures(render_target, backend);
+        let bind_groups = BindGroups::new(
+            render_target,
+            &motion_buffer,
+            &history_textures,
+            &uniform_buffer,
+            &bind_group_layouts,
+            &sampler,
+            backend,
+        );
+
+        let target_format = render_target.color_format;
+        let pipelines = Pipelines::new(
+            &resolve_pipeline_layout,
+            &copy_pipeline_layout,
+            &shader,
+            target_format,
+            backend,
+        );
+
+        Self {
+            motion_buffer,
+            history_textures,
+            history_index: 0,
+            history_valid: false,
+            accumulation: false,
+            accumulated_frames: 0,
+            frame_index: 0,
+            uniform_buffer,
+            bind_groups,
+            bind_group_layouts,
+            resolve_pipeline_layout,
+            copy_pipeline_layout,
+            pipelines,
+            sampler,
+            target_format,
+            shader_source,
+            shader,
+        }
+    }
+
+    /// The next frame won't be blended with previous ones.
+    pub fn reset_history(&mut self) {
+        self.history_valid = false;
+    }
+
+    pub fn accumulation(&self) -> bool {
+        self.accumulation
+    }
+
+    /// While the camera doesn't move, frames get averaged together instead of blended with the
+    /// reprojected history.
+    pub fn set_accumulation(&mut self, enabled: bool) {
+        self.accumulation = enabled;
+        self.reset_history();
+    }
+
+    /// Subpixel offset to apply to the projection of the next frame, in normalized device
+    /// coordinates. Follows a Halton (2, 3) sequence.
+    pub fn next_jitter(&mut self, render_size: UVec2) -> Vec2 {
+        self.frame_index = self.frame_index.wrapping_add(1);
+        let index = self.frame_index % Self::JITTER_SEQUENCE_LENGTH + 1;
+        let offset = Vec2::new(halton(index, 2), halton(index, 3)) - Vec2::splat(0.5);
+        offset * 2.0 / render_size.as_vec2().max(Vec2::ONE)
+    }
+
+    /// Attachment the 3d pipeline writes per pixel screen space motion to, next to its color.
+    pub fn motion_attachment(&self) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: &self.motion_buffer.color_view,
+            resolve_target: self.motion_buffer.resolve.as_ref().map(|(_, view)| view),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    fn rebuild_pipelines(&mut self, backend: &mut Backend) {
+        self.pipelines = Pipelines::new(
+            &self.resolve_pipeline_layout,
+            &self.copy_pipeline_layout,
+            &self.shader,
+            self.target_format,
+            backend,
+        );
+    }
+}
+
+impl PostPass for Taa {
+    fn reads(&self) -> &[Slot] {
+        &[Slot::Color, Slot::Motion]
+    }
+
+    fn writes(&self) -> &[Slot] {
+        &[Slot::Color]
+    }
+
+    fn update_render_target(&mut self, render_target: &RenderTarget, backend: &mut Backend) {
+        if self.target_format != render_target.color_format {
+            self.target_format = render_target.color_format;
+            self.rebuild_pipelines(backend);
+        }
+
+        self.motion_buffer = MotionBuffer::new(render_target, backend);
+        self.history_textures = create_history_textures(render_target, backend);
+        self.bind_groups = BindGroups::new(
+            render_target,
+            &self.motion_buffer,
+            &self.history_textures,
+            &self.uniform_buffer,
+            &self.bind_group_layouts,
+            &self.sampler,
+            backend,
+        );
+        self.reset_history();
+    }
+
+    fn notify_asset_changes(
+        &mut self,
+        changes: &AssetChanges,
+        backend: &mut Backend,
+        asset_server: &mut AssetServer,
+    ) {
+        if changes.contains(self.shader_source) {
+            let source = asset_server.get(self.shader_source);
+            self.shader = backend.create_shader_module("taa shader", source.source());
+            self.rebuild_pipelines(backend);
+        }
+    }
+
+    /// Blends the render target with the reprojected history, then writes the result back.
+    /// When accumulating, the history is instead averaged with every frame as is, which only
+    /// makes sense while nothing moves.
+    fn render(&mut self, context: &mut PassContext) {
+        let PassContext {
+            encoder,
+            render_target,
+            backend,
+            camera_moved,
+        } = context;
+        let accumulate = self.accumulation && !*camera_moved;
+        if !self.history_valid || !accumulate {
+            self.accumulated_frames = 0;
+        }
+        let uniform = if !self.history_valid {
+            TaaUniform::default()
+        } else if accumulate {
+            let frames = self.accumulated_frames as f32;
+            TaaUniform {
+                history_weight: frames / (frames + 1.0),
+                clamp_history: 0,
+                ..Default::default()
+            }
+        } else {
+            TaaUniform {
+                history_weight: Self::HISTORY_WEIGHT,
+                clamp_history: 1,
+                ..Default::default()
+            }
+        };
+        self.accumulated_frames = (self.accumulated_frames + 1).min(Self::MAX_ACCUMULATED_FRAMES);
+        backend.update_uniform_buffer(&self.uniform_buffer, uniform);
+
+        let next_index = 1 - self.history_index;
+        draw_fullscreen(
+            encoder,
+            "taa resolve render pass",
+            &self.pipelines.resolve,
+            &self.bind_groups.resolve[self.history_index],
+            &self.history_textures[next_index].1,
+            wgpu::LoadOp::Load,
+        );
+        draw_fullscreen(
+            encoder,
+            "taa copy render pass",
+            &self.pipelines.copy,
+            &self.bind_groups.copy[next_index],
+            render_target.texture.view(),
+            wgpu::LoadOp::Load,
+        );
+
+        self.history_index = next_index;
+        self.history_valid = true;
+    }
+
+    fn vram_usage(&self) -> u64 {
+        let motion = &self.motion_buffer;
+        self.uniform_buffer.size()
+            + texture_byte_size(&motion.color)
+            + motion
+                .resolve
+                .as_ref()
+                .map_or(0, |(texture, _)| texture_byte_size(texture))
+            + self
+                .history_textures
+                .iter()
+                .map(|(texture, _)| texture_byte_size(texture))
+                .sum::<u64>()
+    }
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+    history_weight: f32,
+    clamp_history: u32,
+    _padding: [f32; 2],
+}
+
+struct MotionBuffer {
+    color: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    resolve: Option<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl MotionBuffer {
+    fn new(render_target: &RenderTarget, backend: &mut Backend) -> Self {
+        let (color, color_view) = create_texture(
+            "taa motion texture",
+            render_target.size,
+            render_target.sample_count,
+            Taa::MOTION_FORMAT,
+            backend,
+        );
+        let resolve = (render_target.sample_count > 1).then(|| {
+            create_texture(
+                "taa motion resolve texture",
+                render_target.size,
+                1,
+                Taa::MOTION_FORMAT,
+                backend,
+            )
+        });
+        Self {
+            color,
+            color_view,
+            resolve,
+        }
+    }
+
+    fn view(&self) -> &wgpu::TextureView {
+        match &self.resolve {
+            Some((_, resolve_view)) => resolve_view,
+            None => &self.color_view,
+        }
+    }
+}
+
+struct BindGroupLayouts {
+    resolve: wgpu::BindGroupLayout,
+    copy: wgpu::BindGroupLayout,
+}
+
+struct BindGroups {
+    // Indexed by the history texture that gets read.
+    resolve: [wgpu::BindGroup; 2],
+    // Indexed by the history texture that gets copied.
+    copy: [wgpu::BindGroup; 2],
+}
+
+impl BindGroups {
+    fn new(
+        render_target: &RenderTarget,
+        motion_buffer: &MotionBuffer,
+        history_textures: &[(wgpu::Texture, wgpu::TextureView); 2],
+        uniform_buffer: &wgpu::Buffer,
+        layouts: &BindGroupLayouts,
+        sampler: &wgpu::Sampler,
+        backend: &mut Backend,
+    ) -> Self {
+        let build_resolve = |history_view: &wgpu::TextureView| {
+            backend
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("taa resolve bind group"),
+                    layout: &layouts.resolve,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                render_target.texture.view(),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(history_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(motion_buffer.view()),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: uniform_buffer.as_entire_binding(),
+                        },
+                    ],
+                })
+        };
+        let build_copy = |history_view: &wgpu::TextureView| {
+            backend
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("taa copy bind group"),
+                    layout: &layouts.copy,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(history_view),
+                    }],
+                })
+        };
+        Self {
+            resolve: [
+                build_resolve(&history_textures[0].1),
+                build_resolve(&history_textures[1].1),
+            ],
+            copy: [
+                build_copy(&history_textures[0].1),
+                build_copy(&history_textures[1].1),
+            ],
+        }
+    }
+}
+
+struct Pipelines {
+    resolve: wgpu::RenderPipeline,
+    copy: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn new(
+        resolve_layout: &wgpu::PipelineLayout,
+        copy_layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        target_format: wgpu::TextureFormat,
+        backend: &mut Backend,
+    ) -> Self {
+        Self {
+            resolve: build_pipeline(
+                "taa resolve pipeline",
+                "fs_resolve",
+                target_format,
+                wgpu::BlendState::REPLACE,
+                resolve_layout,
+                shader,
+                backend,
+            ),
+            copy: build_pipeline(
+                "taa copy pipeline",
+                "fs_copy",
+                target_format,
+                wgpu::BlendState::REPLACE,
+                copy_layout,
+                shader,
+                backend,
+            ),
+        }
+    }
+}
+
+fn create_history_textures(
+    render_target: &RenderTarget,
+    backend: &mut Backend,
+) -> [(wgpu::Texture, wgpu::TextureView); 2] {
+    [
+        create_texture(
+            "taa history texture",
+            render_target.size,
+            1,
+            render_target.color_format,
+            backend,
+        ),
+        create_texture(
+            "taa history texture",
+            render_target.size,
+            1,
+            render_target.color_format,
+            backend,
+        ),
+    ]
+}
@@ -1,4 +1,6 @@
-use glam::{Vec2, Vec3};
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::Color;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -6,6 +8,13 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub uv: [f32; 2],
+    /// Handedness in w, the bitangent is `cross(normal, tangent.xyz) * tangent.w`. All zero when
+    /// the vertex has no tangent, in which case normal maps are ignored.
+    pub tangent: [f32; 4],
+    /// Second set of texture coordinates.
+    pub uv2: [f32; 2],
+    /// Multiplied with the base color, white unless the mesh has vertex colors.
+    pub color: [f32; 4],
 }
 
 impl Vertex {
@@ -14,9 +23,27 @@ impl Vertex {
             position: position.to_array(),
             normal: normal.to_array(),
             uv: uv.to_array(),
+            tangent: [0.0; 4],
+            uv2: [0.0; 2],
+            color: Color::WHITE.to_array(),
         }
     }
 
+    pub const fn with_tangent(mut self, tangent: Vec4) -> Self {
+        self.tangent = tangent.to_array();
+        self
+    }
+
+    pub const fn with_uv2(mut self, uv2: Vec2) -> Self {
+        self.uv2 = uv2.to_array();
+        self
+    }
+
+    pub const fn with_color(mut self, color: Color) -> Self {
+        self.color = color.to_array();
+        self
+    }
+
     pub fn buffer_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -38,6 +65,21 @@ impl Vertex {
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 14]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -1,9 +1,12 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+    path::Path,
     sync::Arc,
 };
 
-use glam::{Affine3A, Mat4, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+use asset_error::AssetError;
+use glam::{Affine3A, Mat4, Quat, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
 use log::warn;
 
 // TODO Find ways to reduce coupling between the renderer and the rest of the engine, to
@@ -13,18 +16,28 @@ use crate::{
     arena::Handle,
     asset_server::AssetChanges,
     image::Image,
-    material::BillboardMode,
+    material::{AlphaMode, BillboardMode, TextureMapping},
+    mesh::Submesh,
     scene::{NodeId, UniqueNodeId},
-    AssetServer, Camera, Color, Light, Material, Mesh,
+    shader_source::ShaderSource,
+    Aabb, AssetServer, Camera, Color, Cubemap, FontMetrics, Frustum, Light, Material, Mesh,
 };
 
 use super::{
-    backend::{Backend, ShowTextureUniform},
+    auto_exposure::AutoExposure,
+    backend::{texture_byte_size, Backend, ShowTextureUniform},
+    bloom::Bloom,
+    depth_of_field::DepthOfField,
     pipeline2d::{
         self, glyph_instance::GlyphInstance, uibox_instance::UiBoxInstance, Pipeline2d,
         RenderCommandText, RenderCommandUiBoxes, RenderFullscreenTextureCommand,
     },
-    pipeline3d::{Pipeline3d, RenderCommandLight, RenderCommandMesh, RenderCommands},
+    pipeline3d::{
+        model_instance::ModelInstance, Pipeline3d, RenderCommandLight, RenderCommandMesh,
+        RenderCommands,
+    },
+    render_graph::{PassContext, PassId, RenderGraph},
+    taa::Taa,
 };
 
 pub struct VisualServer {
@@ -33,10 +46,19 @@ pub struct VisualServer {
     //
     viewport_uniform_buffer: wgpu::Buffer,
     render_scene: RenderScene,
+    model_uniforms: ModelUniforms,
     render_scene_data: RenderSceneData,
     white_texture: wgpu::Texture,
+    flat_normal_texture: wgpu::Texture,
+    // Bound in place of the shadow map of lights that don't cast shadows.
+    no_shadow_map: wgpu::Texture,
     font_texture: wgpu::Texture,
     font_handle: Option<Handle<Image>>,
+    font_atlas_layout: FontAtlasLayout,
+    font_metrics: FontMetrics,
+    ui_atlas_texture: wgpu::Texture,
+    ui_atlas_handle: Option<Handle<Image>>,
+    skybox: Option<Skybox>,
     default_material: Option<Handle<Material>>,
     quad_mesh: Option<Handle<Mesh>>,
     samplers: Samplers,
@@ -49,12 +71,46 @@ pub struct VisualServer {
     render_target_2d: RenderTarget,
     pipeline3d: Pipeline3d,
     pipeline2d: Pipeline2d,
+    render_graph: RenderGraph,
+    passes: Passes,
+    // Drawn and culled submeshes of the last frame.
+    mesh_draw_counts: (u32, u32),
+    drawn_triangles: u64,
+    // Whether the last frame drew to the 2D render target, shown over the 3D one.
+    rendered_2d: bool,
 }
 
 impl VisualServer {
+    /// Largest width or height of the first mip level uploaded when streaming textures.
+    pub const STREAMED_TEXTURE_INITIAL_SIZE: u32 = 64;
+    /// Beyond it, the shadow filtering kernel gets too costly for its loops to be unrolled.
+    pub const MAX_SHADOW_FILTER_RADIUS: u32 = 3;
+    /// The light uniform holds this many cascades, fewer of them can be used.
+    pub const MAX_SHADOW_CASCADES: u32 = 4;
+    pub const MIN_SHADOW_MAP_RESOLUTION: u32 = 256;
+    pub const MAX_SHADOW_MAP_RESOLUTION: u32 = 8192;
+
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn new(window: &Arc<winit::window::Window>, asset_server: &mut AssetServer) -> Self {
-        let mut backend = Backend::new(window);
+        pollster::block_on(Self::new_async(window, asset_server))
+    }
+
+    /// See [`Engine::new_async`](crate::Engine::new_async).
+    pub async fn new_async(
+        window: &Arc<winit::window::Window>,
+        asset_server: &mut AssetServer,
+    ) -> Self {
+        Self::with_backend(Backend::new(window).await, asset_server)
+    }
 
+    /// Renders without a window, frames are read back with [`VisualServer::render_to_image`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_headless(render_size: UVec2, asset_server: &mut AssetServer) -> Self {
+        let backend = pollster::block_on(Backend::new_headless(render_size));
+        Self::with_backend(backend, asset_server)
+    }
+
+    fn with_backend(mut backend: Backend, asset_server: &mut AssetServer) -> Self {
         let viewport_uniform = ViewportUniform {
             size: backend.render_size().to_array(),
         };
@@ -65,6 +121,13 @@ impl VisualServer {
             view: Mat4::IDENTITY.to_cols_array(),
             camera_transform: Mat4::IDENTITY.to_cols_array(),
             ambient_light: Color::new(0.3, 0.5, 0.9, 0.04).to_array(),
+            previous_projection_view: Mat4::ZERO.to_cols_array(),
+            inv_projection_view: Camera::default()
+                .projection_matrix()
+                .inverse()
+                .to_cols_array(),
+            jitter: [0.0; 2],
+            _padding: [0.0; 2],
         };
         let render_scene_data = RenderSceneData {
             uniform: scene_uniform,
@@ -72,30 +135,57 @@ impl VisualServer {
         };
 
         let white_texture = backend.create_color_texture(1, 1, &[255, 255, 255, 255], 1);
+        let flat_normal_texture =
+            backend.create_color_texture_linear(1, 1, &[128, 128, 255, 255], 1);
         let font_texture = backend.create_color_texture(1, 1, &[255, 255, 0, 255], 1);
-
-        let samplers = Samplers {
-            unfiltered: backend.create_sampler_non_filtering(),
-            filtered: backend.create_sampler(),
-            shadow_map: backend.create_sampler_shadow_map(),
-        };
+        let ui_atlas_texture = backend.create_color_texture(1, 1, &[255, 255, 255, 255], 1);
 
         let uibox_instance_buffer = backend.create_vertex_buffer::<UiBoxInstance>(&[]);
 
         let settings = Settings {
             render_size_factor: 1.0,
-            // FIXME The shader doesn't know about these, they're hardcoded right now. fix it
-            shadow_cascades: vec![(0.0, 0.05), (0.05, 0.1), (0.1, 0.3), (0.3, 1.0)],
+            render_format: wgpu::TextureFormat::Rgba16Float,
+            shadow_cascades: shadow_cascade_splits(Self::MAX_SHADOW_CASCADES),
+            shadow_map_resolutions: [2048; Self::MAX_SHADOW_CASCADES as usize],
+            shadow_filter_radius: 1,
+            shadow_map_filter: wgpu::FilterMode::Nearest,
+            shadow_map_compare: wgpu::CompareFunction::LessEqual,
+            shadow_cascade_blend_width: 0.1,
+            shadow_caster_extrusion: 50.0,
             tone_mapping: ToneMapping::Reinhard,
+            exposure: 1.0,
+            upscale_filter: UpscaleFilter::Nearest,
+            taa: false,
+            camera_jitter: false,
+            opaque_draw_order: DrawOrder::FrontToBack,
+            color_load_op: ColorLoadOp::Clear,
+            render_2d: true,
+            clean_render: false,
+            texture_streaming: false,
         };
 
+        debug_assert_eq!(validate_shadow_cascades(&settings.shadow_cascades), Ok(()));
+
+        let samplers = Samplers {
+            unfiltered: backend.sampler_non_filtering(),
+            filtered: backend.sampler(),
+            shadow_map: backend
+                .sampler_shadow_map(settings.shadow_map_filter, settings.shadow_map_compare),
+        };
+
+        let no_shadow_map = create_shadow_map(1, &mut backend);
+
         let render_target_3d = create_render_target(
             backend.render_size(),
             1,
-            wgpu::TextureFormat::Rgba16Float,
+            settings.render_format,
             Backend::DEPTH_TEXTURE_FORMAT,
             &samplers.unfiltered,
-            settings.tone_mapping,
+            ShowTextureUniform::new(
+                settings.tone_mapping,
+                settings.exposure,
+                settings.upscale_filter,
+            ),
             &mut backend,
         );
 
@@ -105,7 +195,7 @@ impl VisualServer {
             wgpu::TextureFormat::Rgba8UnormSrgb,
             Backend::DEPTH_TEXTURE_FORMAT,
             &samplers.filtered,
-            ToneMapping::None,
+            ShowTextureUniform::new(ToneMapping::None, 1.0, UpscaleFilter::Bilinear),
             &mut backend,
         );
 
@@ -119,21 +209,57 @@ impl VisualServer {
         let pipeline2d = Pipeline2d::new(
             &viewport_uniform_buffer,
             &font_texture,
+            &ui_atlas_texture,
             render_target_2d.info(),
             &mut backend,
             asset_server,
         );
 
+        // Passes that modify the color in place run in the order they're added here.
+        let mut render_graph = RenderGraph::default();
+        let passes = Passes {
+            taa: render_graph
+                .add(Taa::new(&render_target_3d, &mut backend, asset_server))
+                .unwrap(),
+            depth_of_field: render_graph
+                .add(DepthOfField::new(
+                    &render_target_3d,
+                    &mut backend,
+                    asset_server,
+                ))
+                .unwrap(),
+            bloom: render_graph
+                .add(Bloom::new(&render_target_3d, &mut backend, asset_server))
+                .unwrap(),
+            auto_exposure: render_graph
+                .add(AutoExposure::new(
+                    &render_target_3d,
+                    &mut backend,
+                    asset_server,
+                ))
+                .unwrap(),
+        };
+
+        let model_uniforms = ModelUniforms::new(&mut backend);
+
         let mut this = Self {
             backend,
             settings,
             //
             viewport_uniform_buffer,
             render_scene: Default::default(),
+            model_uniforms,
             render_scene_data,
             white_texture,
+            flat_normal_texture,
+            no_shadow_map,
             font_texture,
             font_handle: None,
+            font_atlas_layout: Default::default(),
+            font_metrics: FontMetrics::monospace(Default::default()),
+            ui_atlas_texture,
+            ui_atlas_handle: None,
+            skybox: None,
             quad_mesh: None,
             default_material: None,
             samplers,
@@ -146,6 +272,11 @@ impl VisualServer {
             render_target_2d,
             pipeline3d,
             pipeline2d,
+            render_graph,
+            passes,
+            mesh_draw_counts: (0, 0),
+            drawn_triangles: 0,
+            rendered_2d: false,
         };
 
         this.initialize_default_resources(asset_server);
@@ -171,6 +302,107 @@ impl VisualServer {
         self.recreate_render_targets();
     }
 
+    pub fn render_format(&self) -> wgpu::TextureFormat {
+        self.settings.render_format
+    }
+
+    /// Sets the color format the 3d scene is rendered and post processed in. Use a float format
+    /// to keep light values above 1 until tone mapping.
+    pub fn set_render_format(&mut self, format: wgpu::TextureFormat) {
+        let features = self.backend.device.features();
+        let renderable = format
+            .guaranteed_format_features(features)
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+        let filterable = format.sample_type(None, Some(features))
+            == Some(wgpu::TextureSampleType::Float { filterable: true });
+        if !renderable || !filterable {
+            warn!(
+                "render format {format:?} isn't a renderable and filterable color format, ignoring"
+            );
+            return;
+        }
+
+        self.settings.render_format = format;
+        self.recreate_render_targets();
+    }
+
+    pub fn max_frame_latency(&self) -> u32 {
+        self.backend.max_frame_latency()
+    }
+
+    /// Frames queued for the GPU at most, at least 1. Defaults to 2.
+    pub fn set_max_frame_latency(&mut self, max_frame_latency: u32) {
+        self.backend.set_max_frame_latency(max_frame_latency);
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.backend.present_mode()
+    }
+
+    /// Falls back to `PresentMode::Fifo`, which is always supported, if the display can't do
+    /// the requested mode. Defaults to `Fifo`.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.backend.set_present_mode(present_mode);
+    }
+
+    pub fn anisotropy_clamp(&self) -> u16 {
+        self.backend.anisotropy_clamp()
+    }
+
+    /// Overrides the maximum anisotropy chosen for the device, from 1 (off) to 16. Backends
+    /// without anisotropic filtering stay at 1.
+    pub fn set_anisotropy_clamp(&mut self, anisotropy_clamp: u16, asset_server: &AssetServer) {
+        self.backend.set_anisotropy_clamp(anisotropy_clamp);
+        self.samplers.filtered = self.backend.sampler();
+
+        let materials: Vec<_> = self.render_scene.materials.keys().copied().collect();
+        for material in materials {
+            self.update_render_material_data(material, asset_server);
+        }
+        self.recreate_render_targets();
+    }
+
+    pub fn taa(&self) -> bool {
+        self.settings.taa
+    }
+
+    /// Temporal antialiasing, which jitters the camera every frame and blends the result with
+    /// the previous frames.
+    pub fn set_taa(&mut self, enabled: bool) {
+        self.settings.taa = enabled;
+        self.update_taa_enabled();
+        self.render_graph.get_mut(self.passes.taa).reset_history();
+    }
+
+    pub fn camera_jitter(&self) -> bool {
+        self.settings.camera_jitter
+    }
+
+    /// Offsets the camera projection by a different subpixel amount every frame. Always on with
+    /// temporal antialiasing.
+    pub fn set_camera_jitter(&mut self, enabled: bool) {
+        self.settings.camera_jitter = enabled;
+    }
+
+    pub fn accumulation(&self) -> bool {
+        self.render_graph.get(self.passes.taa).accumulation()
+    }
+
+    /// While the camera doesn't move, frames get averaged together. With camera jitter, this
+    /// supersamples still images.
+    pub fn set_accumulation(&mut self, enabled: bool) {
+        self.render_graph
+            .get_mut(self.passes.taa)
+            .set_accumulation(enabled);
+        self.update_taa_enabled();
+    }
+
+    fn update_taa_enabled(&mut self) {
+        let enabled = self.settings.taa || self.accumulation();
+        self.render_graph.set_enabled(self.passes.taa, enabled);
+    }
+
     pub fn msaa_sample_count(&self) -> u32 {
         self.render_target_3d.sample_count
     }
@@ -180,17 +412,301 @@ impl VisualServer {
         self.recreate_render_targets();
     }
 
+    pub fn ambient_light(&self) -> Color {
+        self.render_scene_data.uniform.ambient_light.into()
+    }
+
+    /// The alpha is the intensity.
+    pub fn set_ambient_light(&mut self, color: Color) {
+        self.render_scene_data.uniform.ambient_light = color.to_array();
+        self.backend.update_uniform_buffer(
+            &self.render_scene_data.uniform_buffer,
+            self.render_scene_data.uniform,
+        );
+    }
+
     pub fn tone_mapping(&self) -> ToneMapping {
         self.settings.tone_mapping
     }
 
     pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
         self.settings.tone_mapping = tone_mapping;
+        self.update_tone_mapping_uniform();
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.settings.exposure
+    }
+
+    /// Scales the HDR color before tone mapping, on top of auto exposure if it is enabled.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.settings.exposure = exposure;
+        self.update_tone_mapping_uniform();
+    }
+
+    // Leaves the rest of the uniform alone, auto exposure writes its part on the gpu.
+    fn update_tone_mapping_uniform(&mut self) {
+        let buffer = &self.render_target_3d.backend_uniform_buffer;
+        self.backend.queue.write_buffer(
+            buffer,
+            ShowTextureUniform::TONE_MAPPING_OFFSET,
+            bytemuck::bytes_of(&(self.settings.tone_mapping as u32)),
+        );
+        self.backend.queue.write_buffer(
+            buffer,
+            ShowTextureUniform::MANUAL_EXPOSURE_OFFSET,
+            bytemuck::bytes_of(&self.settings.exposure),
+        );
+    }
+
+    pub fn upscale_filter(&self) -> UpscaleFilter {
+        self.settings.upscale_filter
+    }
+
+    /// Sets how the 3d render target gets scaled up to the window when the render size factor
+    /// is below 1. Scaling down always filters bilinearly.
+    pub fn set_upscale_filter(&mut self, upscale_filter: UpscaleFilter) {
+        self.settings.upscale_filter = upscale_filter;
         self.recreate_render_targets();
     }
 
-    pub fn set_font_image(&mut self, handle: Handle<Image>, asset_server: &AssetServer) {
+    pub fn color_load_op(&self) -> ColorLoadOp {
+        self.settings.color_load_op
+    }
+
+    /// Sets what the 3d render target starts each frame with. Loading it keeps the previous
+    /// frame to draw over, where nothing covers it, like the skybox would.
+    pub fn set_color_load_op(&mut self, color_load_op: ColorLoadOp) {
+        self.settings.color_load_op = color_load_op;
+    }
+
+    pub fn opaque_draw_order(&self) -> DrawOrder {
+        self.settings.opaque_draw_order
+    }
+
+    /// Sets the order opaque meshes are drawn in. Front to back lets the depth test skip
+    /// shading what ends up hidden.
+    pub fn set_opaque_draw_order(&mut self, draw_order: DrawOrder) {
+        self.settings.opaque_draw_order = draw_order;
+    }
+
+    pub fn render_2d(&self) -> bool {
+        self.settings.render_2d
+    }
+
+    /// Skips the 2d pass entirely, even when there are texts, ui boxes or a fullscreen texture.
+    /// Without any of those, it is skipped regardless.
+    pub fn set_render_2d(&mut self, enabled: bool) {
+        self.settings.render_2d = enabled;
+    }
+
+    pub fn clean_render(&self) -> bool {
+        self.settings.clean_render
+    }
+
+    /// Presents only the lit 3d scene, without gizmos nor the 2d pass (ui, texts and fullscreen
+    /// textures), for captures to compare. The other settings are kept as they are, and apply
+    /// again once it is disabled.
+    pub fn set_clean_render(&mut self, enabled: bool) {
+        self.settings.clean_render = enabled;
+    }
+
+    pub fn texture_streaming(&self) -> bool {
+        self.settings.texture_streaming
+    }
+
+    /// Textures uploaded from then on start with only their mip levels of at most
+    /// `STREAMED_TEXTURE_INITIAL_SIZE` pixels, and get their next larger level every frame.
+    pub fn set_texture_streaming(&mut self, enabled: bool) {
+        self.settings.texture_streaming = enabled;
+    }
+
+    pub fn auto_exposure(&self) -> bool {
+        self.render_graph.enabled(self.passes.auto_exposure)
+    }
+
+    pub fn set_auto_exposure(&mut self, enabled: bool) {
+        self.render_graph
+            .set_enabled(self.passes.auto_exposure, enabled);
+        // Recreating the render targets resets the exposure that was applied to them.
+        self.recreate_render_targets();
+    }
+
+    /// The resolution of the nearest cascade's shadow map.
+    pub fn shadow_map_resolution(&self) -> u32 {
+        self.settings.shadow_map_resolutions[0]
+    }
+
+    /// Sets the width and height of the shadow maps of all cascades, from
+    /// `MIN_SHADOW_MAP_RESOLUTION` to `MAX_SHADOW_MAP_RESOLUTION`, or less if the device can't
+    /// make textures that large.
+    pub fn set_shadow_map_resolution(&mut self, resolution: u32) {
+        let resolution = self.clamp_shadow_map_resolution(resolution);
+        self.settings.shadow_map_resolutions.fill(resolution);
+        // Lights are set every frame, their shadow maps will be recreated with the new size.
+        self.render_scene.lights.clear();
+    }
+
+    pub fn shadow_cascade_resolution(&self, cascade: usize) -> Option<u32> {
+        self.settings.shadow_map_resolutions.get(cascade).copied()
+    }
+
+    /// Like [`VisualServer::set_shadow_map_resolution`], for the shadow map of a single cascade,
+    /// 0 being the nearest. Far cascades cover more ground, and can usually do with less.
+    pub fn set_shadow_cascade_resolution(&mut self, cascade: usize, resolution: u32) {
+        let resolution = self.clamp_shadow_map_resolution(resolution);
+        let Some(dst) = self.settings.shadow_map_resolutions.get_mut(cascade) else {
+            warn!("there is no shadow cascade {}", cascade);
+            return;
+        };
+        *dst = resolution;
+        self.render_scene.lights.clear();
+    }
+
+    fn clamp_shadow_map_resolution(&self, resolution: u32) -> u32 {
+        let max = Self::MAX_SHADOW_MAP_RESOLUTION
+            .min(self.backend.device.limits().max_texture_dimension_2d);
+        resolution.clamp(Self::MIN_SHADOW_MAP_RESOLUTION, max)
+    }
+
+    pub fn shadow_cascade_count(&self) -> u32 {
+        self.settings.shadow_cascades.len() as u32
+    }
+
+    /// Sets how many cascades the view is split into for directional shadows, from 1 to
+    /// `MAX_SHADOW_CASCADES`. More cascades give sharper shadows far away, at the cost of
+    /// rendering the scene once more per cascade.
+    pub fn set_shadow_cascade_count(&mut self, count: u32) {
+        self.settings.shadow_cascades =
+            shadow_cascade_splits(count.clamp(1, Self::MAX_SHADOW_CASCADES));
+        // Lights are set every frame, their shadow maps will be recreated with the new layers.
+        self.render_scene.lights.clear();
+    }
+
+    /// Near and far ratios of the view frustum covered by each shadow cascade.
+    pub fn shadow_cascades(&self) -> &[(f32, f32)] {
+        &self.settings.shadow_cascades
+    }
+
+    /// Sets the ranges of the view frustum covered by each shadow cascade, as near and far ratios.
+    /// There can be up to `MAX_SHADOW_CASCADES` of them, sorted from the nearest, with each
+    /// starting where the previous one ends, the first at 0 and the last ending at 1.
+    pub fn set_shadow_cascades(&mut self, cascades: &[(f32, f32)]) -> Result<(), String> {
+        validate_shadow_cascades(cascades)?;
+        self.settings.shadow_cascades = cascades.to_vec();
+        self.render_scene.lights.clear();
+        Ok(())
+    }
+
+    pub fn shadow_filter_radius(&self) -> u32 {
+        self.settings.shadow_filter_radius
+    }
+
+    /// Sets the radius, in shadow map texels, of the percentage-closer filtering kernel.
+    /// A radius of 0 gives hard shadows, 1 a 3x3 kernel, up to `MAX_SHADOW_FILTER_RADIUS`.
+    pub fn set_shadow_filter_radius(&mut self, radius: u32) {
+        self.settings.shadow_filter_radius = radius.min(Self::MAX_SHADOW_FILTER_RADIUS);
+    }
+
+    pub fn shadow_map_filter(&self) -> wgpu::FilterMode {
+        self.settings.shadow_map_filter
+    }
+
+    /// Nearest gives hard comparisons at each filtering kernel texel, linear blends the
+    /// comparisons of neighboring texels for smoother shadow edges.
+    pub fn set_shadow_map_filter(&mut self, filter: wgpu::FilterMode) {
+        self.settings.shadow_map_filter = filter;
+        self.recreate_shadow_map_sampler();
+    }
+
+    pub fn shadow_map_compare(&self) -> wgpu::CompareFunction {
+        self.settings.shadow_map_compare
+    }
+
+    /// The comparison a fragment's depth in light space must pass against the shadow map
+    /// to be lit.
+    pub fn set_shadow_map_compare(&mut self, compare: wgpu::CompareFunction) {
+        self.settings.shadow_map_compare = compare;
+        self.recreate_shadow_map_sampler();
+    }
+
+    fn recreate_shadow_map_sampler(&mut self) {
+        self.samplers.shadow_map = self.backend.sampler_shadow_map(
+            self.settings.shadow_map_filter,
+            self.settings.shadow_map_compare,
+        );
+        for light in self.render_scene.lights.values_mut() {
+            light.bind_group = self.backend.create_light_bind_group(
+                &light.uniform_buffer,
+                &cascade_shadow_maps(&light.shadow_maps, &self.no_shadow_map),
+                &self.samplers.shadow_map,
+                &self.pipeline3d.data.bind_group_layouts.light,
+            );
+        }
+    }
+
+    pub fn bloom(&self) -> bool {
+        self.render_graph.enabled(self.passes.bloom)
+    }
+
+    pub fn set_bloom(&mut self, enabled: bool) {
+        self.render_graph.set_enabled(self.passes.bloom, enabled);
+    }
+
+    pub fn shadow_cascade_blend_width(&self) -> f32 {
+        self.settings.shadow_cascade_blend_width
+    }
+
+    /// Sets the fraction of each cascade's depth range, at its far end, over which it is blended
+    /// with the next cascade. A width of 0 switches between cascades abruptly.
+    pub fn set_shadow_cascade_blend_width(&mut self, width: f32) {
+        self.settings.shadow_cascade_blend_width = width.clamp(0.0, 1.0);
+    }
+
+    pub fn shadow_caster_extrusion(&self) -> f32 {
+        self.settings.shadow_caster_extrusion
+    }
+
+    /// Sets how far towards the light, past the part of the view it covers, each cascade still
+    /// renders shadow casters, so that casters outside of the view don't pop in and out.
+    pub fn set_shadow_caster_extrusion(&mut self, distance: f32) {
+        self.settings.shadow_caster_extrusion = distance.max(0.0);
+    }
+
+    pub fn shadow_cascades_frozen(&self) -> bool {
+        self.render_scene.frozen_inv_projection_view.is_some()
+    }
+
+    /// Keeps the shadow cascades fitted to the current camera view, even as the camera moves.
+    pub fn freeze_shadow_cascades(&mut self, freeze: bool) {
+        if !freeze {
+            self.render_scene.frozen_inv_projection_view = None;
+        } else if !self.shadow_cascades_frozen() {
+            self.render_scene.frozen_inv_projection_view =
+                Some(self.render_scene.inv_projection_view);
+        }
+    }
+
+    /// The glyphs are placed according to the BMFont `.fnt` file next to the image, if there is
+    /// one. Otherwise they're taken as monospaced, laid out as `atlas_layout` says.
+    pub fn set_font_image(
+        &mut self,
+        handle: Handle<Image>,
+        atlas_layout: FontAtlasLayout,
+        asset_server: &AssetServer,
+    ) {
         self.font_handle = Some(handle);
+        self.font_atlas_layout = atlas_layout;
+        self.font_metrics = asset_server
+            .asset_path(handle)
+            .map(|path| Path::new(path).with_extension("fnt"))
+            .filter(|fnt_path| fnt_path.exists())
+            .and_then(|fnt_path| {
+                FontMetrics::load(&fnt_path.to_string_lossy())
+                    .inspect_err(|e| eprintln!("font metrics {:?}: {}", fnt_path, e))
+                    .ok()
+            })
+            .unwrap_or_else(|| FontMetrics::monospace(atlas_layout));
         let image = asset_server.get(handle);
         self.font_texture = self.backend.create_color_texture_linear(
             image.width(),
@@ -203,11 +719,60 @@ impl VisualServer {
             .update_font_texture(&self.font_texture, &mut self.backend);
     }
 
+    pub fn font_metrics(&self) -> &FontMetrics {
+        &self.font_metrics
+    }
+
+    /// Sets the image ui boxes take their images from, usually packed with `TextureAtlas`.
+    pub fn set_ui_atlas_image(&mut self, handle: Handle<Image>, asset_server: &AssetServer) {
+        self.ui_atlas_handle = Some(handle);
+        let image = asset_server.get(handle);
+        self.ui_atlas_texture =
+            self.backend
+                .create_color_texture(image.width(), image.height(), image.data(), 1);
+
+        self.pipeline2d
+            .update_ui_atlas_texture(&self.ui_atlas_texture, &mut self.backend);
+    }
+
+    /// Draws the cubemap behind everything, in place of the clear color.
+    pub fn set_skybox(&mut self, handle: Handle<Cubemap>, asset_server: &AssetServer) {
+        let cubemap = asset_server.get(handle);
+        let texture = self
+            .backend
+            .create_cubemap_texture(cubemap.size(), bytemuck::cast_slice(cubemap.data()));
+        let bind_group = self.backend.create_skybox_bind_group(
+            &texture,
+            &self.samplers.filtered,
+            &self.pipeline3d.data.bind_group_layouts.skybox,
+        );
+        self.skybox = Some(Skybox {
+            handle,
+            texture,
+            bind_group,
+        });
+    }
+
+    pub fn unset_skybox(&mut self) {
+        self.skybox = None;
+    }
+
     pub fn set_camera(&mut self, transform: &Affine3A, camera: &Camera) {
         let proj = camera.projection_matrix();
         let view = Mat4::from(transform.inverse());
 
+        // The camera gets set once per frame, so what it was set to is the previous frame's.
+        let uniform = &mut self.render_scene_data.uniform;
+        uniform.previous_projection_view = (Mat4::from_cols_array(&uniform.projection)
+            * Mat4::from_cols_array(&uniform.view))
+        .to_cols_array();
+
+        self.render_scene.camera_moved = !(proj * view).abs_diff_eq(
+            Mat4::from_cols_array(&uniform.previous_projection_view),
+            1e-6,
+        );
         self.render_scene.inv_projection_view = (proj * view).inverse();
+        uniform.inv_projection_view = self.render_scene.inv_projection_view.to_cols_array();
 
         self.render_scene_data.uniform.projection = proj.to_cols_array();
         self.render_scene_data.uniform.view = view.to_cols_array();
@@ -218,31 +783,218 @@ impl VisualServer {
             self.render_scene_data.uniform,
         );
 
+        let depth_of_field = self.render_graph.get_mut(self.passes.depth_of_field);
+        depth_of_field.set_camera(camera, &mut self.backend);
+        let enabled = depth_of_field.enabled();
+        self.render_graph
+            .set_enabled(self.passes.depth_of_field, enabled);
+
         // FIXME TODO recompute directional lights shadow cascades
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let mut render_commands_meshes = Vec::new();
+        self.render_targets();
+        self.backend.render(
+            &self.render_target_3d,
+            self.rendered_2d.then_some(&self.render_target_2d),
+        )
+    }
+
+    /// Renders a frame like [`VisualServer::render`], but into an image rather than the window.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_image(&mut self) -> Result<Image, String> {
+        self.render_targets();
+        self.last_frame_image()
+    }
+
+    /// Saves the last rendered frame as it was shown, in the sRGB color space. The image format
+    /// comes from the extension of `path`, like `.png`. Failing to read the frame back or to
+    /// write the file is reported, not panicked on.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_screenshot(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        self.last_frame_image()
+            .and_then(|image| image.save_to_path(path).map_err(|e| e.to_string()))
+            .map_err(|e| format!("failed to save screenshot to {}: {}", path.display(), e))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn last_frame_image(&mut self) -> Result<Image, String> {
+        self.backend.render_to_image(
+            &self.render_target_3d,
+            self.rendered_2d.then_some(&self.render_target_2d),
+        )
+    }
+
+    fn render_targets(&mut self) {
+        let jitter = if self.settings.taa || self.settings.camera_jitter {
+            self.render_graph
+                .get_mut(self.passes.taa)
+                .next_jitter(self.render_target_3d.size)
+        } else {
+            Vec2::ZERO
+        };
+        self.render_scene_data.uniform.jitter = jitter.to_array();
+        self.backend.update_uniform_buffer(
+            &self.render_scene_data.uniform_buffer,
+            self.render_scene_data.uniform,
+        );
+        self.model_uniforms.upload(&mut self.backend);
 
+        let bloom = self.render_graph.get(self.passes.bloom);
+        let taa = self.render_graph.get(self.passes.taa);
+        let mut render_commands_meshes = Vec::new();
+        let mut render_commands_blended_meshes = Vec::new();
+
+        let view = Mat4::from_cols_array(&self.render_scene_data.uniform.view);
+        let projection = Mat4::from_cols_array(&self.render_scene_data.uniform.projection);
+        let frustum =
+            Frustum::from_projection_view(self.render_scene.inv_projection_view.inverse());
+        // Opaque submeshes are grouped to draw those of the same mesh all at once. Blended ones
+        // have to be drawn in order.
+        let mut opaque_groups: HashMap<_, Vec<&RenderMeshInstance>> = HashMap::new();
         for mesh_instance in self.render_scene.mesh_instances.values() {
             let mesh = self.render_scene.meshes.get(&mesh_instance.mesh).unwrap();
+            let view_depth = (view * mesh_instance.transform.w_axis).z;
+            let visible = mesh_instance
+                .aabb
+                .is_none_or(|aabb| frustum.intersects_aabb(&aabb));
+            let lod_level = mesh_instance.aabb.map_or(0, |aabb| {
+                mesh.lod_level(screen_size(&aabb, &projection, &view))
+            });
 
-            for submesh in &mesh.submeshes {
-                let material_handle = mesh_instance
-                    .material_override
-                    .as_ref()
-                    .unwrap_or(&submesh.material);
-                let material = self.render_scene.materials.get(material_handle).unwrap();
-                render_commands_meshes.push(RenderCommandMesh {
+            for (i, submesh) in mesh.lod_submeshes(lod_level).iter().enumerate() {
+                if mesh_instance.hidden_submeshes.contains(&i) {
+                    continue;
+                }
+                let material = self.render_scene.materials.get(&submesh.material).unwrap();
+                if !material.blend {
+                    let key = (
+                        mesh_instance.mesh,
+                        lod_level,
+                        i,
+                        visible,
+                        mesh_instance.casts_shadows,
+                    );
+                    opaque_groups.entry(key).or_default().push(mesh_instance);
+                    continue;
+                }
+                let (custom_attribute_buffer, vertex_shader) =
+                    submesh.custom_vertex_stage(material);
+                render_commands_blended_meshes.push(RenderCommandMesh {
                     material_bind_group: &material.bind_group,
-                    model_bind_group: &mesh_instance.model_bind_group,
+                    model_bind_group: &self.model_uniforms.bind_group,
+                    model_offset: mesh_instance.model_offset,
+                    instance_buffer: None,
+                    instance_count: 1,
                     vertex_buffer: &submesh.vertex_buffer,
+                    custom_attribute_buffer,
+                    vertex_shader,
                     index_buffer: &submesh.index_buffer,
                     index_count: submesh.index_count,
                     casts_shadows: mesh_instance.casts_shadows,
+                    view_depth,
+                    visible,
                 });
             }
         }
+        // Groups of one are drawn with their model uniform instead.
+        let opaque_groups: Vec<_> = opaque_groups
+            .into_iter()
+            .map(|(key, mesh_instances)| {
+                let instance_buffer = (mesh_instances.len() > 1).then(|| {
+                    let instances: Vec<ModelInstance> = mesh_instances
+                        .iter()
+                        .map(|mesh_instance| {
+                            self.model_uniforms.instance(mesh_instance.model_offset)
+                        })
+                        .collect();
+                    self.backend.create_vertex_buffer(&instances)
+                });
+                (key, mesh_instances, instance_buffer)
+            })
+            .collect();
+        for (
+            (mesh, lod_level, submesh_index, visible, casts_shadows),
+            mesh_instances,
+            instance_buffer,
+        ) in &opaque_groups
+        {
+            let submesh = &self.render_scene.meshes[mesh].lod_submeshes(*lod_level)[*submesh_index];
+            let material = &self.render_scene.materials[&submesh.material];
+            let (custom_attribute_buffer, vertex_shader) = submesh.custom_vertex_stage(material);
+            let view_depth = mesh_instances
+                .iter()
+                .map(|mesh_instance| (view * mesh_instance.transform.w_axis).z)
+                .fold(f32::INFINITY, f32::min);
+            render_commands_meshes.push(RenderCommandMesh {
+                material_bind_group: &material.bind_group,
+                model_bind_group: &self.model_uniforms.bind_group,
+                model_offset: mesh_instances[0].model_offset,
+                instance_buffer: instance_buffer.as_ref(),
+                instance_count: mesh_instances.len() as u32,
+                vertex_buffer: &submesh.vertex_buffer,
+                custom_attribute_buffer,
+                vertex_shader,
+                index_buffer: &submesh.index_buffer,
+                index_count: submesh.index_count,
+                casts_shadows: *casts_shadows,
+                view_depth,
+                visible: *visible,
+            });
+        }
+        match self.settings.opaque_draw_order {
+            DrawOrder::Unsorted => (),
+            DrawOrder::FrontToBack => render_commands_meshes
+                .sort_unstable_by(|a, b| a.view_depth.total_cmp(&b.view_depth)),
+            DrawOrder::BackToFront => render_commands_meshes
+                .sort_unstable_by(|a, b| b.view_depth.total_cmp(&a.view_depth)),
+        }
+        // Blended meshes only look right drawn over what's behind them.
+        render_commands_blended_meshes
+            .sort_unstable_by(|a, b| b.view_depth.total_cmp(&a.view_depth));
+
+        let mut render_commands_overlays = Vec::new();
+        if let Some(quad_mesh) = self.quad_mesh.filter(|_| !self.settings.clean_render) {
+            let quad = &self.render_scene.meshes[&quad_mesh].submeshes[0];
+            for sprite in self.render_scene.sprites.values() {
+                let material = &self.render_scene.materials[&sprite.material];
+                render_commands_overlays.push(RenderCommandMesh {
+                    material_bind_group: &material.bind_group,
+                    model_bind_group: &self.model_uniforms.bind_group,
+                    model_offset: sprite.model_offset,
+                    instance_buffer: None,
+                    instance_count: 1,
+                    vertex_buffer: &quad.vertex_buffer,
+                    custom_attribute_buffer: None,
+                    vertex_shader: None,
+                    index_buffer: &quad.index_buffer,
+                    index_count: quad.index_count,
+                    casts_shadows: false,
+                    view_depth: (view * sprite.position.extend(1.0)).z,
+                    visible: true,
+                });
+            }
+        }
+        // Nearer sprites overlap farther ones.
+        render_commands_overlays.sort_unstable_by(|a, b| b.view_depth.total_cmp(&a.view_depth));
+        let all_meshes = || {
+            render_commands_meshes
+                .iter()
+                .chain(&render_commands_blended_meshes)
+        };
+        let mesh_draws = all_meshes()
+            .filter(|m| m.visible)
+            .map(|m| m.instance_count)
+            .sum::<u32>();
+        let mesh_draw_counts = (
+            mesh_draws,
+            all_meshes().map(|m| m.instance_count).sum::<u32>() - mesh_draws,
+        );
+        let drawn_triangles = all_meshes()
+            .filter(|m| m.visible)
+            .map(|m| (m.index_count / 3) as u64 * m.instance_count as u64)
+            .sum();
 
         let mut render_commands_lights = Vec::new();
         for light in self.render_scene.lights.values() {
@@ -253,13 +1005,19 @@ impl VisualServer {
                     .iter()
                     .map(|sc| &sc.bind_group)
                     .collect(),
-                shadow_maps: &light.shadow_map,
+                shadow_maps: &light.shadow_maps,
             });
         }
 
         let commands = RenderCommands {
             meshes: &render_commands_meshes,
+            blended_meshes: &render_commands_blended_meshes,
             lights: &render_commands_lights,
+            skybox: self.skybox.as_ref().map(|skybox| &skybox.bind_group),
+            overlays: &render_commands_overlays,
+            emissive_attachment: bloom.emissive_attachment(),
+            motion_attachment: taa.motion_attachment(),
+            color_load_op: self.settings.color_load_op,
         };
 
         let mut encoder =
@@ -272,11 +1030,19 @@ impl VisualServer {
         self.pipeline3d
             .render(&mut encoder, &commands, &self.render_target_3d);
 
+        self.render_graph.render(&mut PassContext {
+            encoder: &mut encoder,
+            render_target: &self.render_target_3d,
+            backend: &mut self.backend,
+            camera_moved: self.render_scene.camera_moved,
+        });
+
         let mut render_text_commands = Vec::new();
         for text in &self.text_instance_buffers {
             render_text_commands.push(RenderCommandText {
                 instance_buffer: &text.instance_buffer,
                 instance_count: text.instance_count,
+                style_bind_group: &text.style_bind_group,
             });
         }
 
@@ -296,23 +1062,96 @@ impl VisualServer {
             },
             texture: maybe_texture_command.as_ref(),
         };
-        self.pipeline2d
-            .render(&mut encoder, &commands_2d, &self.render_target_2d);
+        let render_2d =
+            self.settings.render_2d && !self.settings.clean_render && !commands_2d.is_empty();
+        if render_2d {
+            self.pipeline2d
+                .render(&mut encoder, &commands_2d, &self.render_target_2d);
+        }
 
         // FIXME: Strive to minimise the amount of submits across the board / submit as much work as possible
         // to reduce overhead / wasted GPU cycles. Right now there is two submits, one here and one in backend,
         // where there could be only one.
         self.backend.queue.submit(Some(encoder.finish()));
 
-        self.backend
-            .render(&self.render_target_3d, &self.render_target_2d)?;
+        self.mesh_draw_counts = mesh_draw_counts;
+        self.drawn_triangles = drawn_triangles;
+        self.rendered_2d = render_2d;
+    }
 
-        Ok(())
+    pub fn stats(&self) -> RenderStats {
+        let scene = &self.render_scene;
+        let textures: u64 = scene
+            .textures
+            .values()
+            .chain(scene.linear_textures.values())
+            .map(texture_byte_size)
+            .sum();
+        let meshes: u64 = scene
+            .meshes
+            .values()
+            .flat_map(|mesh| {
+                (0..=mesh.lods.len()).flat_map(|lod_level| mesh.lod_submeshes(lod_level))
+            })
+            .map(|submesh| submesh.vertex_buffer.size() + submesh.index_buffer.size())
+            .sum();
+        let materials: u64 = scene
+            .materials
+            .values()
+            .map(|material| material.uniform_buffer.size())
+            .sum();
+        let mesh_instances = self.model_uniforms.buffer.size();
+        let lights: u64 = scene
+            .lights
+            .values()
+            .map(|light| {
+                light.uniform_buffer.size()
+                    + light.shadow_maps.iter().map(texture_byte_size).sum::<u64>()
+                    + light
+                        .shadow_cascades
+                        .iter()
+                        .map(|cascade| cascade.uniform_buffer.size())
+                        .sum::<u64>()
+            })
+            .sum();
+        let ui: u64 = self.uibox_instance_buffer.size()
+            + self
+                .text_instance_buffers
+                .iter()
+                .map(|text| text.instance_buffer.size() + text.style_buffer.size())
+                .sum::<u64>()
+            + texture_byte_size(&self.font_texture)
+            + texture_byte_size(&self.ui_atlas_texture)
+            + texture_byte_size(&self.white_texture)
+            + texture_byte_size(&self.flat_normal_texture)
+            + texture_byte_size(&self.no_shadow_map)
+            + self
+                .skybox
+                .as_ref()
+                .map_or(0, |skybox| texture_byte_size(&skybox.texture));
+        let render_targets =
+            self.render_target_3d.vram_usage() + self.render_target_2d.vram_usage();
+
+        RenderStats {
+            vram_bytes: textures
+                + meshes
+                + materials
+                + mesh_instances
+                + lights
+                + ui
+                + render_targets
+                + self.viewport_uniform_buffer.size()
+                + self.render_scene_data.uniform_buffer.size()
+                + self.render_graph.vram_usage(),
+            mesh_draws: self.mesh_draw_counts.0,
+            culled_mesh_draws: self.mesh_draw_counts.1,
+            triangles: self.drawn_triangles,
+        }
     }
 
     pub fn set_depth_fullscreen_texture(&mut self) {
         let texture = &self.render_target_3d.texture.depth();
-        let sampler = self.backend.create_sampler_non_filtering();
+        let sampler = self.backend.sampler_non_filtering();
         let bind_group = self.pipeline2d.build_fullscreen_texture_bind_group(
             texture,
             &sampler,
@@ -321,6 +1160,7 @@ impl VisualServer {
         self.render_scene.fullscreen_texture = Some(RenderFullscreenTexture {
             bind_group,
             sampler,
+            light_id: None,
         });
     }
 
@@ -329,17 +1169,24 @@ impl VisualServer {
             eprintln!("warning: {}:{}: no such light registered", file!(), line!());
             return;
         };
-        let texture = &light.shadow_map;
-        let sampler = self.backend.create_sampler_non_filtering();
-        let bind_group = self.pipeline2d.build_fullscreen_texture_array_bind_group(
+        let Some(texture) = light.shadow_maps.first() else {
+            eprintln!(
+                "warning: {}:{}: light doesn't cast shadows",
+                file!(),
+                line!()
+            );
+            return;
+        };
+        let sampler = self.backend.sampler_non_filtering();
+        let bind_group = self.pipeline2d.build_fullscreen_texture_bind_group(
             texture,
             &sampler,
             &mut self.backend,
-            0,
         );
         self.render_scene.fullscreen_texture = Some(RenderFullscreenTexture {
             bind_group,
             sampler,
+            light_id: Some(light_id),
         });
     }
 
@@ -347,38 +1194,56 @@ impl VisualServer {
         self.render_scene.fullscreen_texture = None;
     }
 
+    /// The light whose shadow map is shown over the screen, if any.
+    pub fn shadow_map_fullscreen_texture_light(&self) -> Option<UniqueNodeId> {
+        self.render_scene
+            .fullscreen_texture
+            .as_ref()
+            .and_then(|texture| texture.light_id)
+    }
+
     pub fn set_light(&mut self, id: UniqueNodeId, transform: Affine3A, light: &Light) {
-        if self.render_scene.lights.contains_key(&id) {
+        let render_light = self.render_scene.lights.get(&id);
+        if render_light.is_some_and(|l| l.shadow_maps.is_empty() != light.casts_shadows) {
             self.update_light(id, transform, light);
         } else {
             self.create_light(id, transform, light);
         }
     }
 
+    pub fn remove_light(&mut self, id: UniqueNodeId) {
+        self.render_scene.lights.remove(&id);
+    }
+
+    /// Lights set this frame or before, and not removed since.
+    pub fn light_ids(&self) -> impl Iterator<Item = UniqueNodeId> + '_ {
+        self.render_scene.lights.keys().copied()
+    }
+
+    /// Whether the light has a shadow map, false if there is no such light.
+    pub fn light_casts_shadows(&self, id: UniqueNodeId) -> bool {
+        self.render_scene
+            .lights
+            .get(&id)
+            .is_some_and(|light| !light.shadow_maps.is_empty())
+    }
+
     fn create_light(&mut self, id: UniqueNodeId, transform: Affine3A, light: &Light) {
-        // TODO look into variance shadow maps (VSMs)
-        let shadow_map = self
-            .backend
-            .device
-            .create_texture(&wgpu::TextureDescriptor {
-                label: Some("shadow map texture"),
-                size: wgpu::Extent3d {
-                    width: 2048,
-                    height: 2048,
-                    depth_or_array_layers: self.settings.shadow_cascades.len() as _,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: Backend::DEPTH_TEXTURE_FORMAT,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                    | wgpu::TextureUsages::TEXTURE_BINDING,
-                view_formats: &[],
-            });
+        let mut shadow_maps = Vec::new();
+        if light.casts_shadows {
+            for &resolution in
+                &self.settings.shadow_map_resolutions[..self.settings.shadow_cascades.len()]
+            {
+                shadow_maps.push(create_shadow_map(resolution, &mut self.backend));
+            }
+        }
 
         // FIXME cascades are recomputed twice, when updating the light and the camera. Make it one.
-        let light_dir = transform.z_axis.into();
-        let cascade_projviews = self.compute_shadow_cascade_projviews(light_dir);
+        let cascade_projviews = if light.casts_shadows {
+            self.compute_shadow_cascade_projviews(transform.z_axis.into())
+        } else {
+            Vec::new()
+        };
         let mut shadow_cascades = Vec::new();
         for projview in cascade_projviews {
             let projview = projview.to_cols_array();
@@ -404,22 +1269,29 @@ impl VisualServer {
             })
         }
 
+        let mut cascades_world_to_light =
+            [Mat4::ZERO.to_cols_array(); Self::MAX_SHADOW_CASCADES as usize];
+        for (dst, shadow_cascade) in cascades_world_to_light.iter_mut().zip(&shadow_cascades) {
+            *dst = shadow_cascade.projview;
+        }
+        let spot_cos_angles = light.spot_cos_angles().unwrap_or_default();
         let uniform_buffer = self.backend.create_uniform_buffer(LightUniform {
             transform: Mat4::from(transform).to_cols_array(),
-            cascades_world_to_light: [
-                shadow_cascades[0].projview,
-                shadow_cascades[1].projview,
-                shadow_cascades[2].projview,
-            ],
+            cascades_world_to_light,
+            cascade_ends: self.shadow_cascade_ends(),
             color: light.color.to_array(),
             radius: light.radius().unwrap_or_default(),
             kind: light.kind.id(),
-            _padding: Default::default(),
+            shadow_filter_radius: self.settings.shadow_filter_radius,
+            shadow_cascade_blend_width: self.settings.shadow_cascade_blend_width,
+            spot_cos_angles: spot_cos_angles.into(),
+            casts_shadows: light.casts_shadows as u32,
+            shadow_cascade_count: self.shadow_cascade_count(),
         });
 
         let bind_group = self.backend.create_light_bind_group(
             &uniform_buffer,
-            &shadow_map,
+            &cascade_shadow_maps(&shadow_maps, &self.no_shadow_map),
             &self.samplers.shadow_map,
             &self.pipeline3d.data.bind_group_layouts.light,
         );
@@ -429,7 +1301,7 @@ impl VisualServer {
             RenderLight {
                 bind_group,
                 uniform_buffer,
-                shadow_map,
+                shadow_maps,
                 shadow_cascades,
             },
         );
@@ -442,33 +1314,40 @@ impl VisualServer {
         };
 
         // FIXME cascades are recomputed twice, when updating the light and the camera. Make it one.
-        let light_dir = transform.z_axis.into();
-        let cascade_projviews = self.compute_shadow_cascade_projviews(light_dir);
-        for (shadow_cascade, projview) in render_light
-            .shadow_cascades
-            .iter()
-            .zip(cascade_projviews.iter())
-        {
-            let projview = projview.to_cols_array();
-            self.backend.update_uniform_buffer(
-                &shadow_cascade.uniform_buffer,
-                ShadowCascadeUniform { projview },
-            );
+        let mut cascades_world_to_light =
+            [Mat4::ZERO.to_cols_array(); Self::MAX_SHADOW_CASCADES as usize];
+        if light.casts_shadows {
+            let cascade_projviews = self.compute_shadow_cascade_projviews(transform.z_axis.into());
+            for ((shadow_cascade, projview), dst) in render_light
+                .shadow_cascades
+                .iter()
+                .zip(cascade_projviews.iter())
+                .zip(cascades_world_to_light.iter_mut())
+            {
+                let projview = projview.to_cols_array();
+                self.backend.update_uniform_buffer(
+                    &shadow_cascade.uniform_buffer,
+                    ShadowCascadeUniform { projview },
+                );
+                *dst = projview;
+            }
         }
 
+        let spot_cos_angles = light.spot_cos_angles().unwrap_or_default();
         self.backend.update_uniform_buffer(
             &render_light.uniform_buffer,
             LightUniform {
                 transform: Mat4::from(transform).to_cols_array(),
-                cascades_world_to_light: [
-                    cascade_projviews[0].to_cols_array(),
-                    cascade_projviews[1].to_cols_array(),
-                    cascade_projviews[2].to_cols_array(),
-                ],
+                cascades_world_to_light,
+                cascade_ends: self.shadow_cascade_ends(),
                 color: light.color.to_array(),
                 radius: light.radius().unwrap_or_default(),
                 kind: light.kind.id(),
-                _padding: Default::default(),
+                shadow_filter_radius: self.settings.shadow_filter_radius,
+                shadow_cascade_blend_width: self.settings.shadow_cascade_blend_width,
+                spot_cos_angles: spot_cos_angles.into(),
+                casts_shadows: light.casts_shadows as u32,
+                shadow_cascade_count: self.shadow_cascade_count(),
             },
         );
     }
@@ -483,67 +1362,143 @@ impl VisualServer {
         id: UniqueNodeId,
         transform: Affine3A,
         mesh_handle: Handle<Mesh>,
+        tint: Color,
         asset_server: &AssetServer,
     ) {
         self.register_mesh(mesh_handle, asset_server);
 
+        let transform = Mat4::from(transform);
+        let previous = self.render_scene.mesh_instances.get(&id);
+        let previous_transform =
+            previous.map_or(transform, |mesh_instance| mesh_instance.transform);
+        let hidden_submeshes = previous
+            .filter(|mesh_instance| mesh_instance.mesh == mesh_handle)
+            .map(|mesh_instance| mesh_instance.hidden_submeshes.clone())
+            .unwrap_or_default();
         let model_uniform = ModelUniform {
-            transform: Mat4::from(transform).to_cols_array(),
+            transform: transform.to_cols_array(),
+            tint: tint.to_array(),
+            previous_transform: previous_transform.to_cols_array(),
         };
-        let model_uniform_buffer = self.backend.create_uniform_buffer(model_uniform);
-        let model_bind_group = self.backend.create_model_bind_group(&model_uniform_buffer);
+        let model_offset = match previous {
+            Some(mesh_instance) => {
+                let model_offset = mesh_instance.model_offset;
+                self.model_uniforms.update(model_offset, model_uniform);
+                model_offset
+            }
+            None => self
+                .model_uniforms
+                .allocate(model_uniform, &mut self.backend),
+        };
+        let aabb = self.render_scene.meshes[&mesh_handle]
+            .aabb
+            .map(|aabb| aabb.transformed(&transform));
 
         self.render_scene.mesh_instances.insert(
             id,
             RenderMeshInstance {
-                model_uniform_buffer,
-                model_bind_group,
+                model_offset,
+                transform,
                 mesh: mesh_handle,
-                material_override: None,
                 casts_shadows: true,
+                hidden_submeshes,
+                aabb,
             },
         );
     }
 
+    /// Hides or shows a single submesh of a mesh instance, until its mesh changes.
+    pub fn set_submesh_visible(&mut self, id: UniqueNodeId, submesh_index: usize, visible: bool) {
+        let Some(mesh_instance) = self.render_scene.mesh_instances.get_mut(&id) else {
+            eprintln!(
+                "warning: {}:{}: no such mesh instance registered",
+                file!(),
+                line!()
+            );
+            return;
+        };
+        let hidden = &mut mesh_instance.hidden_submeshes;
+        if visible {
+            hidden.retain(|&i| i != submesh_index);
+        } else if !hidden.contains(&submesh_index) {
+            hidden.push(submesh_index);
+        }
+    }
+
+    pub fn remove_mesh_instance(&mut self, id: UniqueNodeId) {
+        if let Some(mesh_instance) = self.render_scene.mesh_instances.remove(&id) {
+            self.model_uniforms.free(mesh_instance.model_offset);
+        }
+    }
+
+    /// Also drops the sprite's material.
+    pub fn remove_sprite(&mut self, id: UniqueNodeId, asset_server: &mut AssetServer) {
+        let Some(sprite) = self.render_scene.sprites.remove(&id) else {
+            return;
+        };
+        self.model_uniforms.free(sprite.model_offset);
+        self.render_scene.materials.remove(&sprite.material);
+        asset_server.remove(sprite.material);
+    }
+
+    /// Sprites always face the camera and keep the same size on screen. `size` is their height
+    /// as a fraction of the screen height. They are drawn over the scene, unlit, and don't
+    /// cast shadows.
     pub fn set_sprite(
         &mut self,
         id: UniqueNodeId,
         transform: Affine3A,
+        size: f32,
         image_handle: Handle<Image>,
         base_color: Color,
         asset_server: &mut AssetServer,
     ) {
+        let transform = Mat4::from_scale_rotation_translation(
+            Vec3::splat(size),
+            Quat::IDENTITY,
+            transform.translation.into(),
+        );
         let model_uniform = ModelUniform {
-            transform: Mat4::from(transform).to_cols_array(),
+            transform: transform.to_cols_array(),
+            tint: Color::WHITE.to_array(),
+            previous_transform: transform.to_cols_array(),
         };
 
-        if let Some(mesh_instance) = self.render_scene.mesh_instances.get(&id) {
-            self.backend
-                .update_uniform_buffer(&mesh_instance.model_uniform_buffer, model_uniform);
+        if let Some(sprite) = self.render_scene.sprites.get_mut(&id) {
+            self.model_uniforms
+                .update(sprite.model_offset, model_uniform);
+            sprite.position = transform.w_axis.truncate();
 
-            let material = asset_server.get_mut(mesh_instance.material_override.unwrap());
-            material.base_color = base_color;
-            material.base_color_image = Some(image_handle);
+            let material_handle = sprite.material;
+            let material = asset_server.get_mut(material_handle);
+            if material.base_color != base_color || material.base_color_image != Some(image_handle)
+            {
+                material.base_color = base_color;
+                material.base_color_image = Some(image_handle);
+                self.register_texture(image_handle, asset_server);
+                self.update_render_material_data(material_handle, asset_server);
+            }
         } else {
-            let model_uniform_buffer = self.backend.create_uniform_buffer(model_uniform);
-            let model_bind_group = self.backend.create_model_bind_group(&model_uniform_buffer);
-
-            let material = asset_server.add(Material {
-                base_color,
-                base_color_image: Some(image_handle),
-                billboard_mode: BillboardMode::On,
-                unlit: true,
-            });
+            let model_offset = self
+                .model_uniforms
+                .allocate(model_uniform, &mut self.backend);
+
+            let material = asset_server.add(
+                Material::new()
+                    .with_base_color(base_color)
+                    .with_base_color_image(Some(image_handle))
+                    .with_billboard_mode(BillboardMode::FixedSize)
+                    .with_alpha_mode(AlphaMode::Mask(0.5))
+                    .unlit(),
+            );
             self.register_material(material, asset_server);
 
-            self.render_scene.mesh_instances.insert(
+            self.render_scene.sprites.insert(
                 id,
-                RenderMeshInstance {
-                    model_uniform_buffer,
-                    model_bind_group,
-                    mesh: self.quad_mesh.unwrap(),
-                    material_override: Some(material),
-                    casts_shadows: false,
+                RenderSprite {
+                    model_offset,
+                    position: transform.w_axis.truncate(),
+                    material,
                 },
             );
         }
@@ -554,34 +1509,148 @@ impl VisualServer {
     }
 
     pub fn add_text(&mut self, _id: NodeId, text: TextDescriptor) {
-        let offset = text.position;
-        let glyphs = text
-            .text
-            .iter()
-            .enumerate()
-            .map(|(i, &id)| {
-                let id = u8::min(id, 127);
-                GlyphInstance::new(
-                    offset + Vec2::new(i as f32 * text.font_size * 1.1667 * 0.5, 0.0),
-                    Vec2::new(text.font_size * 1.1667 * 0.5, text.font_size),
-                    id,
-                )
-            })
-            .collect::<Vec<_>>();
+        let metrics = &self.font_metrics;
+        let lines = text.lines(metrics);
+        // Without a width to align in, lines are aligned with the longest one.
+        let align_width = if text.max_width.is_finite() {
+            text.max_width
+        } else {
+            lines
+                .iter()
+                .map(|line| text.line_width(line, metrics))
+                .fold(0.0, f32::max)
+        };
+
+        let mut glyphs = Vec::new();
+        for (line_index, line) in lines.iter().enumerate() {
+            let free_width = align_width - text.line_width(line, metrics);
+            let x = match text.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => free_width * 0.5,
+                TextAlign::Right => free_width,
+            };
+            let mut pen = text.position + Vec2::new(x, line_index as f32 * text.font_size);
+            for &c in line.iter() {
+                let glyph = metrics.glyph(c);
+                glyphs.push(GlyphInstance::new(
+                    pen + glyph.offset * text.font_size,
+                    glyph.size * text.font_size,
+                    glyph.uv,
+                ));
+                pen.x += glyph.advance * text.font_size + text.letter_spacing;
+            }
+        }
         let instance_buffer = self.backend.create_vertex_buffer(&glyphs);
+        let (style_buffer, style_bind_group) = self.pipeline2d.create_text_style_bind_group(
+            TextStyleUniform {
+                outline_color: text.outline_color.to_array(),
+                outline_width: text.outline_width,
+                _padding: Default::default(),
+            },
+            &mut self.backend,
+        );
 
         self.text_instance_buffers.push(RenderText {
             instance_buffer,
             instance_count: glyphs.len() as u32,
+            style_buffer,
+            style_bind_group,
         });
     }
 
-    pub fn reset_scene(&mut self) {
-        self.render_scene = Default::default();
+    /// Drops the render state of the previous scene. The GPU resources of `kept_meshes`, of
+    /// their materials and of their textures are kept, so that they don't get uploaded again
+    /// for the next scene.
+    pub fn reset_scene(
+        &mut self,
+        kept_meshes: &HashSet<Handle<Mesh>>,
+        asset_server: &mut AssetServer,
+    ) {
+        let previous = std::mem::take(&mut self.render_scene);
+        self.model_uniforms.free_all();
+        // Sprite materials are owned by the visual server.
+        for sprite in previous.sprites.into_values() {
+            asset_server.remove(sprite.material);
+        }
+
+        // The default resources are expected to stay registered.
+        let meshes: HashSet<Handle<Mesh>> =
+            kept_meshes.iter().copied().chain(self.quad_mesh).collect();
+        let materials: HashSet<Handle<Material>> = meshes
+            .iter()
+            .flat_map(|&mesh| &asset_server.get(mesh).submeshes)
+            .filter_map(|submesh| submesh.material)
+            .chain(self.default_material)
+            .collect();
+        let images: HashSet<Handle<Image>> = materials
+            .iter()
+            .flat_map(|&material| {
+                let material = asset_server.get(material);
+                [
+                    material.base_color_image,
+                    material.metallic_roughness_image,
+                    material.normal_image,
+                    material.emissive_image,
+                ]
+            })
+            .flatten()
+            .collect();
+
+        let render_scene = &mut self.render_scene;
+        render_scene.meshes = previous.meshes;
+        render_scene.meshes.retain(|mesh, _| meshes.contains(mesh));
+        render_scene.materials = previous.materials;
+        render_scene
+            .materials
+            .retain(|material, _| materials.contains(material));
+        render_scene.textures = previous.textures;
+        render_scene
+            .textures
+            .retain(|image, _| images.contains(image));
+        render_scene.linear_textures = previous.linear_textures;
+        render_scene
+            .linear_textures
+            .retain(|image, _| images.contains(image));
+        render_scene.streaming_textures = previous.streaming_textures;
+        render_scene
+            .streaming_textures
+            .retain(|streaming| images.contains(&streaming.handle));
+    }
+
+    pub fn reset_instances(&mut self) {
+        for (_, mesh_instance) in self.render_scene.mesh_instances.drain() {
+            self.model_uniforms.free(mesh_instance.model_offset);
+        }
+        self.render_scene.lights.clear();
+    }
+
+    /// Reads every shader from its file again and rebuilds the pipelines using them now, rather
+    /// than waiting for their files to be seen modified.
+    pub fn reload_shaders(&mut self, asset_server: &mut AssetServer) {
+        asset_server.reload_all::<ShaderSource>();
+        let changes = asset_server.take_asset_changes();
+        self.notify_asset_changes(&changes, asset_server);
     }
 
     pub fn notify_asset_changes(&mut self, changes: &AssetChanges, asset_server: &mut AssetServer) {
+        for removed_image_handle in changes.iter_removed::<Image>() {
+            self.render_scene.textures.remove(&removed_image_handle);
+            self.render_scene
+                .linear_textures
+                .remove(&removed_image_handle);
+            self.render_scene
+                .streaming_textures
+                .retain(|texture| texture.handle != removed_image_handle);
+        }
+        for removed_mesh_handle in changes.iter_removed::<Mesh>() {
+            self.render_scene.meshes.remove(&removed_mesh_handle);
+        }
+        for removed_material_handle in changes.iter_removed::<Material>() {
+            self.render_scene.materials.remove(&removed_material_handle);
+        }
+
         let mut textures_to_update = Vec::new();
+        let mut linear_textures_to_update = Vec::new();
         let mut materials_to_update = Vec::new();
 
         for changed_image_handle in changes.iter::<Image>() {
@@ -592,6 +1661,13 @@ impl VisualServer {
             {
                 textures_to_update.push(changed_image_handle);
             }
+            if self
+                .render_scene
+                .linear_textures
+                .contains_key(&changed_image_handle)
+            {
+                linear_textures_to_update.push(changed_image_handle);
+            }
 
             for (&material_handle, material) in self.render_scene.materials.iter() {
                 if material.used_textures.contains(&changed_image_handle) {
@@ -600,13 +1676,25 @@ impl VisualServer {
             }
 
             if self.font_handle == Some(changed_image_handle) {
-                self.set_font_image(changed_image_handle, asset_server);
+                self.set_font_image(changed_image_handle, self.font_atlas_layout, asset_server);
+            }
+            if self.ui_atlas_handle == Some(changed_image_handle) {
+                self.set_ui_atlas_image(changed_image_handle, asset_server);
+            }
+        }
+
+        for changed_cubemap_handle in changes.iter::<Cubemap>() {
+            if self.skybox.as_ref().map(|skybox| skybox.handle) == Some(changed_cubemap_handle) {
+                self.set_skybox(changed_cubemap_handle, asset_server);
             }
         }
 
         for texture_handle in textures_to_update {
             self.update_texture(texture_handle, asset_server);
         }
+        for texture_handle in linear_textures_to_update {
+            self.update_linear_texture(texture_handle, asset_server);
+        }
         for material_handle in materials_to_update {
             self.update_render_material_data(material_handle, asset_server);
         }
@@ -616,6 +1704,9 @@ impl VisualServer {
 
         self.pipeline2d
             .notify_asset_changes(changes, &mut self.backend, asset_server);
+
+        self.render_graph
+            .notify_asset_changes(changes, &mut self.backend, asset_server);
     }
 
     fn recreate_render_targets(&mut self) {
@@ -626,14 +1717,20 @@ impl VisualServer {
         self.render_target_3d = create_render_target(
             render_resolution_3d,
             info.sample_count,
-            info.color_format,
+            self.settings.render_format,
             info.depth_format,
-            if self.settings.render_size_factor > 1.0 {
+            if self.settings.render_size_factor > 1.0
+                || self.settings.upscale_filter != UpscaleFilter::Nearest
+            {
                 &self.samplers.filtered
             } else {
                 &self.samplers.unfiltered
             },
-            self.settings.tone_mapping,
+            ShowTextureUniform::new(
+                self.settings.tone_mapping,
+                self.settings.exposure,
+                self.settings.upscale_filter,
+            ),
             &mut self.backend,
         );
 
@@ -645,12 +1742,14 @@ impl VisualServer {
             info.color_format,
             info.depth_format,
             &self.samplers.filtered,
-            ToneMapping::None,
+            ShowTextureUniform::new(ToneMapping::None, 1.0, UpscaleFilter::Bilinear),
             &mut self.backend,
         );
 
+        // 2D is drawn to its own render target, which isn't affected by the render size factor.
+        // The viewport must match that target, not the 3D one.
         let viewport_uniform = ViewportUniform {
-            size: self.backend.render_size().to_array(),
+            size: self.render_target_2d.size.to_array(),
         };
         self.backend
             .update_uniform_buffer(&self.viewport_uniform_buffer, viewport_uniform);
@@ -659,39 +1758,72 @@ impl VisualServer {
             .update_render_target_info(self.render_target_3d.info(), &mut self.backend);
         self.pipeline2d
             .update_render_target_info(self.render_target_2d.info(), &mut self.backend);
+        self.render_graph
+            .update_render_target(&self.render_target_3d, &mut self.backend);
     }
 
     fn register_mesh(&mut self, handle: Handle<Mesh>, asset_server: &AssetServer) {
-        let mut materials_to_register = Vec::new();
+        if self.render_scene.meshes.contains_key(&handle) {
+            return;
+        }
+        let mesh = asset_server.get(handle);
 
-        if let Entry::Vacant(e) = self.render_scene.meshes.entry(handle) {
-            let mesh = asset_server.get(handle);
+        let submeshes = self.create_render_submeshes(&mesh.submeshes, asset_server);
+        let mut lods: Vec<RenderMeshLod> = mesh
+            .lods
+            .iter()
+            .map(|lod| RenderMeshLod {
+                submeshes: self.create_render_submeshes(&lod.submeshes, asset_server),
+                screen_size: lod.screen_size,
+            })
+            .collect();
+        // From the most detailed, used on the largest screen sizes.
+        lods.sort_by(|a, b| b.screen_size.total_cmp(&a.screen_size));
+        let render_mesh = RenderMesh {
+            submeshes,
+            lods,
+            aabb: mesh.aabb(),
+        };
+        self.render_scene.meshes.insert(handle, render_mesh);
+    }
 
-            let mut render_submeshes = Vec::new();
-            for submesh in &mesh.submeshes {
-                let material = if let Some(material) = submesh.material {
-                    materials_to_register.push(material);
-                    material
-                } else {
-                    self.default_material.unwrap()
-                };
+    fn create_render_submeshes(
+        &mut self,
+        submeshes: &[Submesh],
+        asset_server: &AssetServer,
+    ) -> Vec<RenderSubmesh> {
+        let mut materials_to_register = Vec::new();
 
-                render_submeshes.push(RenderSubmesh {
-                    vertex_buffer: self.backend.create_vertex_buffer(&submesh.vertices),
-                    index_buffer: self.backend.create_index_buffer(&submesh.indices),
-                    index_count: submesh.indices.len() as u32,
-                    material,
-                })
-            }
-            let render_mesh = RenderMesh {
-                submeshes: render_submeshes,
+        let mut render_submeshes = Vec::new();
+        for submesh in submeshes {
+            let material = if let Some(material) = submesh.material {
+                materials_to_register.push(material);
+                material
+            } else {
+                self.default_material.unwrap()
             };
-            e.insert(render_mesh);
+
+            // Custom vertex shaders always have the attributes to read, zero if missing.
+            let uses_custom_attributes = !submesh.custom_attributes.is_empty()
+                || asset_server.get(material).vertex_shader.is_some();
+            let custom_attribute_buffer = uses_custom_attributes.then(|| {
+                let mut custom_attributes = submesh.custom_attributes.clone();
+                custom_attributes.resize(submesh.vertices.len(), [0.0; 4]);
+                self.backend.create_vertex_buffer(&custom_attributes)
+            });
+            render_submeshes.push(RenderSubmesh {
+                vertex_buffer: self.backend.create_vertex_buffer(&submesh.vertices),
+                custom_attribute_buffer,
+                index_buffer: self.backend.create_index_buffer(&submesh.indices),
+                index_count: submesh.indices.len() as u32,
+                material,
+            })
         }
 
         for material_handle in materials_to_register {
             self.register_material(material_handle, asset_server);
         }
+        render_submeshes
     }
 
     fn register_material(&mut self, handle: Handle<Material>, asset_server: &AssetServer) {
@@ -703,6 +1835,15 @@ impl VisualServer {
         if let Some(image) = material.base_color_image {
             self.register_texture(image, asset_server);
         }
+        if let Some(image) = material.metallic_roughness_image {
+            self.register_linear_texture(image, asset_server);
+        }
+        if let Some(image) = material.normal_image {
+            self.register_linear_texture(image, asset_server);
+        }
+        if let Some(image) = material.emissive_image {
+            self.register_texture(image, asset_server);
+        }
 
         self.update_render_material_data(handle, asset_server);
     }
@@ -720,8 +1861,26 @@ impl VisualServer {
         };
         let material_uniform = MaterialUniform {
             base_color: material.base_color.into(),
+            emissive: material.emissive.into(),
             billboard_mode,
             unlit: material.unlit as u8 as u32,
+            uv_scale: material.uv_scale.to_array(),
+            uv_offset: material.uv_offset.to_array(),
+            texture_mapping: match material.texture_mapping {
+                TextureMapping::Uv => 0,
+                TextureMapping::Triplanar => 1,
+            },
+            metallic: material.metallic,
+            roughness: material.roughness,
+            alpha_mode: match material.alpha_mode {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Mask(_) => 1,
+                AlphaMode::Blend => 2,
+            },
+            alpha_cutoff: match material.alpha_mode {
+                AlphaMode::Mask(cutoff) => cutoff,
+                _ => 0.0,
+            },
             _padding: Default::default(),
         };
 
@@ -734,16 +1893,46 @@ impl VisualServer {
         };
 
         let base_color_texture_ref = base_color_texture.unwrap_or(&self.white_texture);
+        let metallic_roughness_texture_ref = material
+            .metallic_roughness_image
+            .and_then(|image| self.render_scene.linear_textures.get(&image))
+            .unwrap_or(&self.white_texture);
+        let normal_texture_ref = material
+            .normal_image
+            .and_then(|image| self.render_scene.linear_textures.get(&image))
+            .unwrap_or(&self.flat_normal_texture);
+        let emissive_texture_ref = material
+            .emissive_image
+            .and_then(|image| self.render_scene.textures.get(&image))
+            .unwrap_or(&self.white_texture);
 
         let bind_group = self.backend.create_material_bind_group(
             &uniform_buffer,
             base_color_texture_ref,
+            metallic_roughness_texture_ref,
+            normal_texture_ref,
+            emissive_texture_ref,
             &self.samplers.filtered,
         );
+        if let Some(vertex_shader) = material.vertex_shader {
+            self.pipeline3d.register_custom_vertex_shader(
+                vertex_shader,
+                &mut self.backend,
+                asset_server,
+            );
+        }
         let render_material = RenderMaterial {
             bind_group,
             uniform_buffer,
-            used_textures: material.base_color_image.into_iter().collect(),
+            blend: material.alpha_mode == AlphaMode::Blend,
+            vertex_shader: material.vertex_shader,
+            used_textures: material
+                .base_color_image
+                .into_iter()
+                .chain(material.metallic_roughness_image)
+                .chain(material.normal_image)
+                .chain(material.emissive_image)
+                .collect(),
         };
 
         self.render_scene.materials.insert(handle, render_material);
@@ -758,14 +1947,117 @@ impl VisualServer {
     }
 
     fn update_texture(&mut self, handle: Handle<Image>, asset_server: &AssetServer) {
+        self.upload_texture(handle, false, asset_server);
+    }
+
+    /// For images that hold data rather than colors, which must not be converted from sRGB.
+    fn register_linear_texture(&mut self, handle: Handle<Image>, asset_server: &AssetServer) {
+        if self.render_scene.linear_textures.contains_key(&handle) {
+            return;
+        }
+
+        self.update_linear_texture(handle, asset_server);
+    }
+
+    fn update_linear_texture(&mut self, handle: Handle<Image>, asset_server: &AssetServer) {
+        self.upload_texture(handle, true, asset_server);
+    }
+
+    /// With texture streaming, only the small mip levels are uploaded here, `stream_textures`
+    /// uploads the larger ones later.
+    fn upload_texture(&mut self, handle: Handle<Image>, linear: bool, asset_server: &AssetServer) {
         let image = asset_server.get(handle);
-        let texture = self.backend.create_color_texture(
-            image.width(),
-            image.height(),
-            image.data(),
-            image.mip_level_count(),
-        );
-        self.render_scene.textures.insert(handle, texture);
+        let first_level = if self.settings.texture_streaming {
+            (0..image.mip_level_count())
+                .find(|&level| {
+                    let (width, height) = image.mip_level_size(level);
+                    width.max(height) <= Self::STREAMED_TEXTURE_INITIAL_SIZE
+                })
+                .unwrap_or(0)
+        } else {
+            0
+        };
+
+        self.upload_texture_levels(handle, linear, first_level, asset_server);
+        self.render_scene
+            .streaming_textures
+            .retain(|texture| !(texture.handle == handle && texture.linear == linear));
+        if first_level > 0 {
+            self.render_scene.streaming_textures.push(StreamingTexture {
+                handle,
+                linear,
+                first_level,
+            });
+        }
+    }
+
+    /// Makes a texture out of the mip levels of the image from `first_level` on.
+    fn upload_texture_levels(
+        &mut self,
+        handle: Handle<Image>,
+        linear: bool,
+        first_level: u32,
+        asset_server: &AssetServer,
+    ) {
+        let image = asset_server.get(handle);
+        let (width, height) = image.mip_level_size(first_level);
+        let data = image.mip_levels_data(first_level);
+        let mip_level_count = image.mip_level_count() - first_level;
+        if linear {
+            let texture =
+                self.backend
+                    .create_color_texture_linear(width, height, data, mip_level_count);
+            self.render_scene.linear_textures.insert(handle, texture);
+        } else {
+            let texture = self
+                .backend
+                .create_color_texture(width, height, data, mip_level_count);
+            self.render_scene.textures.insert(handle, texture);
+        }
+    }
+
+    /// Uploads the next larger mip level of the textures that are still streaming in, and
+    /// updates the materials using them.
+    pub fn stream_textures(&mut self, asset_server: &AssetServer) {
+        let mut streaming_textures = std::mem::take(&mut self.render_scene.streaming_textures);
+        let mut materials_to_update = Vec::new();
+        for texture in &mut streaming_textures {
+            texture.first_level -= 1;
+            self.upload_texture_levels(
+                texture.handle,
+                texture.linear,
+                texture.first_level,
+                asset_server,
+            );
+
+            for (&material_handle, material) in self.render_scene.materials.iter() {
+                if material.used_textures.contains(&texture.handle)
+                    && !materials_to_update.contains(&material_handle)
+                {
+                    materials_to_update.push(material_handle);
+                }
+            }
+        }
+        streaming_textures.retain(|texture| texture.first_level > 0);
+        self.render_scene.streaming_textures = streaming_textures;
+
+        for material_handle in materials_to_update {
+            self.update_render_material_data(material_handle, asset_server);
+        }
+    }
+
+    /// View depths at which each cascade ends, for the shader to pick the cascade of a fragment.
+    fn shadow_cascade_ends(&self) -> [f32; Self::MAX_SHADOW_CASCADES as usize] {
+        // The cascades split the frustum edges, along which the view depth grows linearly.
+        let inv_projection =
+            Mat4::from_cols_array(&self.render_scene_data.uniform.projection).inverse();
+        let near = inv_projection.project_point3(Vec3::ZERO).z;
+        let far = inv_projection.project_point3(Vec3::Z).z;
+        let mut ends = [f32::MAX; Self::MAX_SHADOW_CASCADES as usize];
+        for (end, &(_, far_ratio)) in ends.iter_mut().zip(&self.settings.shadow_cascades) {
+            *end = near + (far - near) * far_ratio;
+        }
+        ends
     }
 
     fn compute_shadow_cascade_projviews(&self, light_dir: Vec3) -> Vec<Mat4> {
@@ -775,15 +2067,19 @@ impl VisualServer {
         //   3. Compute cascade's view transformation matrix.
         //   4. Convert frustum in cascade view space.
         //   5. Compute Aabb of frustum in cascade view space.
-        //   6. Adjust min and max Z coordinates of Aabb to include more stuff to cast shadows.
+        //   6. Pull min Z of Aabb towards the light to include casters outside of the view.
         //   7. Compute orthographic projection matrix from above Aabb.
         //   8. Compute cascade's view projection matrix.
 
         let mut cascade_projviews = Vec::new();
 
         // 1.
+        let inv_projection_view = self
+            .render_scene
+            .frozen_inv_projection_view
+            .unwrap_or(self.render_scene.inv_projection_view);
         let frustum_point = |p: Vec3| {
-            let mut fp = self.render_scene.inv_projection_view * Vec4::new(p.x, p.y, p.z, 1.0);
+            let mut fp = inv_projection_view * Vec4::new(p.x, p.y, p.z, 1.0);
             fp /= fp.w;
             fp
         };
@@ -804,7 +2100,12 @@ impl VisualServer {
         let fbl_edge = ffbl - fnbl;
         let fbr_edge = ffbr - fnbr;
 
-        for &(near_ratio, far_ratio) in &self.settings.shadow_cascades {
+        for (&(near_ratio, far_ratio), &resolution) in self
+            .settings
+            .shadow_cascades
+            .iter()
+            .zip(&self.settings.shadow_map_resolutions)
+        {
             // 2.
             let cfntl = fntl + ftl_edge * near_ratio;
             let cfntr = fntr + ftr_edge * near_ratio;
@@ -823,33 +2124,33 @@ impl VisualServer {
                 .reduce(|a, b| a + b)
                 .unwrap_or(Vec4::ZERO)
                 / cascade_frustum_corners.len() as f32;
-            let cascade_view = Mat4::look_to_lh(view_center.xyz(), light_dir, Vec3::Y);
+            // The view is kept at the origin and the projection bounds are of a constant size
+            // snapped to shadow map texels, so that moving the camera doesn't move the shadow
+            // map's texels around, which would make the shadows shimmer.
+            let cascade_view = Mat4::look_to_lh(Vec3::ZERO, light_dir, Vec3::Y);
+            let radius = cascade_frustum_corners
+                .iter()
+                .map(|&corner| (corner - view_center).xyz().length())
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+            let texel_size = (2.0 * radius) / resolution as f32;
+            let center = cascade_view * view_center;
+            let center_x = (center.x / texel_size).floor() * texel_size;
+            let center_y = (center.y / texel_size).floor() * texel_size;
+            let (min_x, max_x) = (center_x - radius, center_x + radius);
+            let (min_y, max_y) = (center_y - radius, center_y + radius);
 
             // 3. & 4. & 5.
-            let (mut min_x, mut min_y, mut min_z) = (f32::MAX, f32::MAX, f32::MAX);
-            let (mut max_x, mut max_y, mut max_z) = (f32::MIN, f32::MIN, f32::MIN);
+            let (mut min_z, mut max_z) = (f32::MAX, f32::MIN);
             for &corner in cascade_frustum_corners {
                 let p = cascade_view * corner;
-                min_x = f32::min(min_x, p.x);
-                max_x = f32::max(max_x, p.x);
-                min_y = f32::min(min_y, p.y);
-                max_y = f32::max(max_y, p.y);
-                min_z = f32::min(min_z, p.z);
-                max_z = f32::max(max_z, p.z);
+                min_z = f32::min(min_z, p.z - center.z);
+                max_z = f32::max(max_z, p.z - center.z);
             }
 
             // 6.
-            let z_mult = 10.0;
-            if min_z < 0.0 {
-                min_z *= z_mult;
-            } else {
-                min_z /= z_mult;
-            }
-            if max_z < 0.0 {
-                max_z /= z_mult;
-            } else {
-                max_z *= z_mult;
-            }
+            min_z += center.z - self.settings.shadow_caster_extrusion;
+            max_z += center.z;
 
             // 7.
             let cascade_projection =
@@ -891,47 +2192,67 @@ struct SceneUniform {
     view: [f32; 16],
     camera_transform: [f32; 16],
     ambient_light: [f32; 4],
+    previous_projection_view: [f32; 16],
+    inv_projection_view: [f32; 16],
+    jitter: [f32; 2],
+    _padding: [f32; 2],
 }
 
 #[derive(Default)]
 struct RenderScene {
     inv_projection_view: Mat4,
+    // Set when shadow cascades are frozen, for debugging.
+    frozen_inv_projection_view: Option<Mat4>,
+    camera_moved: bool,
     meshes: HashMap<Handle<Mesh>, RenderMesh>,
     materials: HashMap<Handle<Material>, RenderMaterial>,
     textures: HashMap<Handle<Image>, wgpu::Texture>,
-    lights: HashMap<UniqueNodeId, RenderLight>,
+    linear_textures: HashMap<Handle<Image>, wgpu::Texture>,
+    streaming_textures: Vec<StreamingTexture>,
+    // Ordered, so that enumerating lights gives the same order every frame.
+    lights: BTreeMap<UniqueNodeId, RenderLight>,
     mesh_instances: HashMap<UniqueNodeId, RenderMeshInstance>,
+    sprites: HashMap<UniqueNodeId, RenderSprite>,
     fullscreen_texture: Option<RenderFullscreenTexture>,
 }
 
+/// A texture that only has the mip levels from `first_level` on uploaded so far.
+struct StreamingTexture {
+    handle: Handle<Image>,
+    linear: bool,
+    first_level: u32,
+}
+
 struct RenderFullscreenTexture {
     bind_group: wgpu::BindGroup,
     #[allow(unused)]
-    sampler: wgpu::Sampler,
+    sampler: Arc<wgpu::Sampler>,
+    light_id: Option<UniqueNodeId>,
 }
 
 struct RenderText {
     instance_buffer: wgpu::Buffer,
     instance_count: u32,
+    style_buffer: wgpu::Buffer,
+    style_bind_group: wgpu::BindGroup,
 }
 
 struct RenderLight {
     bind_group: wgpu::BindGroup,
-    #[allow(unused)]
     uniform_buffer: wgpu::Buffer,
     // TODO remove these comments
     // #[allow(unused)]
     // shadow_map_scene_bind_group: wgpu::BindGroup,
     // #[allow(unused)]
     // shadow_map_scene_uniform_buffer: wgpu::Buffer,
-    shadow_map: wgpu::Texture,
+    // One per cascade, none for lights that don't cast shadows, which also have no cascades.
+    shadow_maps: Vec<wgpu::Texture>,
     shadow_cascades: Vec<RenderShadowCascade>,
 }
 
 struct RenderShadowCascade {
     projview: [f32; 16],
     bind_group: wgpu::BindGroup,
-    #[allow(unused)]
     uniform_buffer: wgpu::Buffer,
 }
 
@@ -943,34 +2264,185 @@ struct ShadowCascadeUniform {
 
 struct RenderMesh {
     submeshes: Vec<RenderSubmesh>,
+    // Sorted from the most detailed.
+    lods: Vec<RenderMeshLod>,
+    aabb: Option<Aabb>,
+}
+
+impl RenderMesh {
+    /// The level of detail to draw at `screen_size`, 0 being the mesh itself.
+    fn lod_level(&self, screen_size: f32) -> usize {
+        self.lods
+            .iter()
+            .take_while(|lod| screen_size < lod.screen_size)
+            .count()
+    }
+
+    fn lod_submeshes(&self, lod_level: usize) -> &[RenderSubmesh] {
+        match lod_level {
+            0 => &self.submeshes,
+            _ => &self.lods[lod_level - 1].submeshes,
+        }
+    }
+}
+
+struct RenderMeshLod {
+    submeshes: Vec<RenderSubmesh>,
+    screen_size: f32,
 }
 
 struct RenderSubmesh {
     vertex_buffer: wgpu::Buffer,
+    custom_attribute_buffer: Option<wgpu::Buffer>,
     index_buffer: wgpu::Buffer,
     index_count: u32,
     material: Handle<Material>,
 }
 
+impl RenderSubmesh {
+    /// The material's vertex shader with the attributes it reads. Submeshes registered before
+    /// their material got a vertex shader have none to give it, and keep the default one.
+    fn custom_vertex_stage(
+        &self,
+        material: &RenderMaterial,
+    ) -> (Option<&wgpu::Buffer>, Option<Handle<ShaderSource>>) {
+        match (&self.custom_attribute_buffer, material.vertex_shader) {
+            (Some(buffer), Some(vertex_shader)) => (Some(buffer), Some(vertex_shader)),
+            _ => (None, None),
+        }
+    }
+}
+
+/// A quad drawn over the scene, rather than with its meshes.
+struct RenderSprite {
+    model_offset: u32,
+    position: Vec3,
+    material: Handle<Material>,
+}
+
 struct RenderMeshInstance {
-    model_bind_group: wgpu::BindGroup,
-    #[allow(unused)]
-    model_uniform_buffer: wgpu::Buffer,
+    /// Of its uniform in `ModelUniforms`.
+    model_offset: u32,
+    transform: Mat4,
     mesh: Handle<Mesh>,
-    material_override: Option<Handle<Material>>,
     casts_shadows: bool,
+    hidden_submeshes: Vec<usize>,
+    /// In world space. Instances without bounds are never culled.
+    aabb: Option<Aabb>,
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct ModelUniform {
     transform: [f32; 16],
+    tint: [f32; 4],
+    previous_transform: [f32; 16],
+}
+
+/// The model uniforms of every mesh instance and sprite, each in its own slot of one buffer so
+/// that a single bind group serves them all.
+struct ModelUniforms {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Uploaded to the buffer once per frame, rather than every time a uniform changes.
+    contents: Vec<u8>,
+    /// Bytes of `contents` changed since the last upload.
+    dirty: Option<Range<usize>>,
+    /// Bytes between slots, a multiple of the uniform offset alignment.
+    stride: usize,
+    slots_used: usize,
+    free_offsets: Vec<u32>,
+}
+
+impl ModelUniforms {
+    const INITIAL_SLOTS: usize = 256;
+
+    fn new(backend: &mut Backend) -> Self {
+        let alignment = backend.uniform_offset_alignment() as usize;
+        let stride = std::mem::size_of::<ModelUniform>().div_ceil(alignment) * alignment;
+        let contents = vec![0; stride * Self::INITIAL_SLOTS];
+        let buffer = backend.create_dynamic_uniform_buffer(&contents);
+        let bind_group =
+            backend.create_model_bind_group(&buffer, std::mem::size_of::<ModelUniform>() as u64);
+        Self {
+            buffer,
+            bind_group,
+            contents,
+            dirty: None,
+            stride,
+            slots_used: 0,
+            free_offsets: Vec::new(),
+        }
+    }
+
+    /// Returns the offset of the uniform's slot.
+    fn allocate(&mut self, uniform: ModelUniform, backend: &mut Backend) -> u32 {
+        let offset = self.free_offsets.pop().unwrap_or_else(|| {
+            if (self.slots_used + 1) * self.stride > self.contents.len() {
+                self.grow(backend);
+            }
+            self.slots_used += 1;
+            ((self.slots_used - 1) * self.stride) as u32
+        });
+        self.update(offset, uniform);
+        offset
+    }
+
+    fn update(&mut self, offset: u32, uniform: ModelUniform) {
+        let bytes = bytemuck::bytes_of(&uniform);
+        let range = offset as usize..offset as usize + bytes.len();
+        self.contents[range.clone()].copy_from_slice(bytes);
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+
+    /// Writes every uniform changed since the last upload at once.
+    fn upload(&mut self, backend: &mut Backend) {
+        if let Some(dirty) = self.dirty.take() {
+            backend
+                .queue
+                .write_buffer(&self.buffer, dirty.start as u64, &self.contents[dirty]);
+        }
+    }
+
+    fn free(&mut self, offset: u32) {
+        self.free_offsets.push(offset);
+    }
+
+    /// The uniform at `offset`, for instanced draws.
+    fn instance(&self, offset: u32) -> ModelInstance {
+        let offset = offset as usize;
+        let uniform: ModelUniform = bytemuck::pod_read_unaligned(
+            &self.contents[offset..offset + std::mem::size_of::<ModelUniform>()],
+        );
+        ModelInstance {
+            transform: uniform.transform,
+            tint: uniform.tint,
+            previous_transform: uniform.previous_transform,
+        }
+    }
+
+    fn free_all(&mut self) {
+        self.slots_used = 0;
+        self.free_offsets.clear();
+    }
+
+    fn grow(&mut self, backend: &mut Backend) {
+        self.contents.resize(self.contents.len() * 2, 0);
+        self.buffer = backend.create_dynamic_uniform_buffer(&self.contents);
+        self.dirty = None;
+        self.bind_group = backend
+            .create_model_bind_group(&self.buffer, std::mem::size_of::<ModelUniform>() as u64);
+    }
 }
 
 struct RenderMaterial {
     bind_group: wgpu::BindGroup,
-    #[allow(unused)]
     uniform_buffer: wgpu::Buffer,
+    blend: bool,
+    vertex_shader: Option<Handle<ShaderSource>>,
     #[allow(unused)]
     used_textures: Vec<Handle<Image>>,
 }
@@ -979,20 +2451,33 @@ struct RenderMaterial {
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct MaterialUniform {
     base_color: [f32; 4],
+    emissive: [f32; 4],
     billboard_mode: u32,
     unlit: u32,
-    _padding: [u32; 2],
+    uv_scale: [f32; 2],
+    uv_offset: [f32; 2],
+    texture_mapping: u32,
+    metallic: f32,
+    roughness: f32,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+    _padding: u32,
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct LightUniform {
     transform: [f32; 16],
-    cascades_world_to_light: [[f32; 16]; 3],
+    cascades_world_to_light: [[f32; 16]; VisualServer::MAX_SHADOW_CASCADES as usize],
+    cascade_ends: [f32; VisualServer::MAX_SHADOW_CASCADES as usize], // View depths
     color: [f32; 4],
     radius: f32,
-    kind: u32, // Directional=0, Point=1
-    _padding: [f32; 2],
+    kind: u32, // Directional=0, Point=1, Spot=2
+    shadow_filter_radius: u32,
+    shadow_cascade_blend_width: f32,
+    spot_cos_angles: [f32; 2], // Inner, outer
+    casts_shadows: u32,
+    shadow_cascade_count: u32,
 }
 
 pub struct RenderTarget {
@@ -1052,6 +2537,21 @@ impl RenderTarget {
         }
     }
 
+    pub fn vram_usage(&self) -> u64 {
+        let textures = match &self.texture {
+            RenderTargetTexture::Simple { color, depth, .. } => {
+                texture_byte_size(color) + texture_byte_size(depth)
+            }
+            RenderTargetTexture::Multisampled {
+                color,
+                depth,
+                resolve,
+                ..
+            } => texture_byte_size(color) + texture_byte_size(depth) + texture_byte_size(resolve),
+        };
+        textures + self.backend_uniform_buffer.size()
+    }
+
     pub fn render_pass_attachments(
         &self,
     ) -> (
@@ -1094,13 +2594,98 @@ impl RenderTarget {
     }
 }
 
+/// Near and far ratios of the view frustum covered by each of `count` shadow cascades. Closer
+/// cascades are shorter, so that they're sharper where it matters most.
+fn shadow_cascade_splits(count: u32) -> Vec<(f32, f32)> {
+    match count {
+        1 => vec![(0.0, 1.0)],
+        2 => vec![(0.0, 0.1), (0.1, 1.0)],
+        3 => vec![(0.0, 0.05), (0.05, 0.3), (0.3, 1.0)],
+        _ => vec![(0.0, 0.05), (0.05, 0.1), (0.1, 0.3), (0.3, 1.0)],
+    }
+}
+
+fn validate_shadow_cascades(cascades: &[(f32, f32)]) -> Result<(), String> {
+    if cascades.is_empty() || cascades.len() > VisualServer::MAX_SHADOW_CASCADES as usize {
+        return Err(format!(
+            "there must be 1 to {} shadow cascades, not {}",
+            VisualServer::MAX_SHADOW_CASCADES,
+            cascades.len()
+        ));
+    }
+    // Ratios are compared with some leeway, for the ones computed rather than written down.
+    let same = |a: f32, b: f32| (a - b).abs() <= 1e-5;
+    let mut previous_far = 0.0;
+    for (index, &(near, far)) in cascades.iter().enumerate() {
+        if !same(near, previous_far) {
+            return Err(if index == 0 {
+                format!("the first shadow cascade starts at {near} instead of 0")
+            } else {
+                format!(
+                    "shadow cascade {index} starts at {near}, not where the previous one ends, \
+                     at {previous_far}"
+                )
+            });
+        }
+        if !(near < far && far <= 1.0) {
+            return Err(format!(
+                "shadow cascade {index} goes from {near} to {far}, not forward within [0, 1]"
+            ));
+        }
+        previous_far = far;
+    }
+    if !same(previous_far, 1.0) {
+        return Err(format!(
+            "the last shadow cascade ends at {previous_far} instead of 1"
+        ));
+    }
+    Ok(())
+}
+
+/// Fraction of the screen's height covered by the bounding sphere of `aabb`.
+fn screen_size(aabb: &Aabb, projection: &Mat4, view: &Mat4) -> f32 {
+    let center = (aabb.min + aabb.max) * 0.5;
+    let radius = (aabb.max - aabb.min).length() * 0.5;
+    // The view depth for perspective projections, 1 for orthographic ones.
+    let w = (*projection * *view * center.extend(1.0)).w;
+    radius * projection.y_axis.y / w.max(f32::EPSILON)
+}
+
+/// A shadow map for every cascade the shader may sample, `fallback` for the missing ones.
+fn cascade_shadow_maps<'a>(
+    shadow_maps: &'a [wgpu::Texture],
+    fallback: &'a wgpu::Texture,
+) -> Vec<&'a wgpu::Texture> {
+    (0..VisualServer::MAX_SHADOW_CASCADES as usize)
+        .map(|cascade| shadow_maps.get(cascade).unwrap_or(fallback))
+        .collect()
+}
+
+fn create_shadow_map(size: u32, backend: &mut Backend) -> wgpu::Texture {
+    // TODO look into variance shadow maps (VSMs)
+    backend.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("shadow map texture"),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: Backend::SHADOW_MAP_SAMPLE_COUNT,
+        dimension: wgpu::TextureDimension::D2,
+        format: Backend::DEPTH_TEXTURE_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
 fn create_render_target(
     size: UVec2,
     sample_count: u32,
     color_format: wgpu::TextureFormat,
     depth_format: wgpu::TextureFormat,
     sampler: &wgpu::Sampler,
-    tone_mapping: ToneMapping,
+    show_texture_uniform: ShowTextureUniform,
     backend: &mut Backend,
 ) -> RenderTarget {
     let texture_size = wgpu::Extent3d {
@@ -1159,9 +2744,7 @@ fn create_render_target(
             depth,
         }
     };
-    let backend_uniform_buffer = backend.create_uniform_buffer(ShowTextureUniform {
-        tone_mapping: tone_mapping as u32,
-    });
+    let backend_uniform_buffer = backend.create_uniform_buffer(show_texture_uniform);
     let backend_bind_group = backend
         .device
         .create_bind_group(&wgpu::BindGroupDescriptor {
@@ -1194,24 +2777,187 @@ fn create_render_target(
     }
 }
 
+/// How glyphs are laid out in a font image. Glyphs are indexed left to right, then top to bottom.
+/// The image is split evenly into the grid, so the size of a glyph cell is the image's size divided
+/// by the number of columns and rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontAtlasLayout {
+    columns: u32,
+    rows: u32,
+}
+
+impl FontAtlasLayout {
+    pub fn new(columns: u32, rows: u32) -> Result<Self, AssetError> {
+        if columns == 0 || rows == 0 {
+            return Err(AssetError::Validation(format!(
+                "font atlas layout of {}x{} glyphs has no glyph cell",
+                columns, rows
+            )));
+        }
+        Ok(Self { columns, rows })
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// Saturates at `u32::MAX`, more glyphs than text can use anyway.
+    pub fn glyph_count(&self) -> u32 {
+        self.columns.saturating_mul(self.rows)
+    }
+}
+
+impl Default for FontAtlasLayout {
+    fn default() -> Self {
+        // A single row of the 128 ASCII characters.
+        Self {
+            columns: 128,
+            rows: 1,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextStyleUniform {
+    outline_color: [f32; 4],
+    outline_width: f32,
+    _padding: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    /// Approximate amount of memory taken by the textures and buffers the renderer created.
+    pub vram_bytes: u64,
+    /// Submeshes drawn in the last frame, not counting shadow maps.
+    pub mesh_draws: u32,
+    /// Submeshes skipped in the last frame because they were outside of the camera's view.
+    pub culled_mesh_draws: u32,
+    /// Triangles of the submeshes drawn in the last frame, not counting shadow maps.
+    pub triangles: u64,
+}
+
 pub struct TextDescriptor<'a> {
     pub text: &'a [u8],
+    /// Top left of the first line.
     pub position: Vec2,
     pub font_size: f32,
+    /// Space added between glyphs, in pixels. Can be negative to tighten the text.
+    pub letter_spacing: f32,
+    /// Lines longer than this wrap, and are aligned within it. Infinite to never wrap.
     pub max_width: f32,
+    pub align: TextAlign,
+    pub outline_color: Color,
+    /// In pixels, 0 for no outline. Limited by the distances stored in the font image.
+    pub outline_width: f32,
+}
+
+impl TextDescriptor<'_> {
+    /// The text split at newlines, and wrapped at spaces to fit in `max_width`. Words that
+    /// don't fit on a line of their own are broken anywhere.
+    pub fn lines(&self, metrics: &FontMetrics) -> Vec<&[u8]> {
+        let mut lines = Vec::new();
+        for paragraph in self.text.split(|&c| c == b'\n') {
+            let mut rest = paragraph;
+            loop {
+                // At least one glyph goes on each line, however narrow. Summed like in
+                // `line_width`, so that text fits in its own width.
+                let mut advances = 0.0;
+                let fitting = rest
+                    .iter()
+                    .take_while(|&&c| {
+                        advances += self.advance(c, metrics);
+                        advances - self.letter_spacing <= self.max_width
+                    })
+                    .count()
+                    .max(1);
+                if fitting >= rest.len() {
+                    lines.push(rest);
+                    break;
+                }
+
+                // A space right after the last glyph that fits is a fine place to break too.
+                let (line, next) = match rest[..=fitting].iter().rposition(|&c| c == b' ') {
+                    Some(space) if space > 0 => (&rest[..space], &rest[space + 1..]),
+                    _ => rest.split_at(fitting),
+                };
+                lines.push(line);
+                rest = next;
+            }
+        }
+        lines
+    }
+
+    /// Height of the lines, from the font's ascent on the first to its descent on the last.
+    pub fn height(&self, line_count: usize, metrics: &FontMetrics) -> f32 {
+        line_count.saturating_sub(1) as f32 * self.font_size
+            + self.font_size * (metrics.ascent() + metrics.descent())
+    }
+
+    /// Including the spacing after the glyph.
+    fn advance(&self, c: u8, metrics: &FontMetrics) -> f32 {
+        metrics.glyph(c).advance * self.font_size + self.letter_spacing
+    }
+
+    pub fn line_width(&self, line: &[u8], metrics: &FontMetrics) -> f32 {
+        // The last glyph has no spacing after it.
+        (line.iter().map(|&c| self.advance(c, metrics)).sum::<f32>() - self.letter_spacing).max(0.0)
+    }
+}
+
+/// Horizontal alignment of the lines of a text.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
 }
 
 struct Settings {
     render_size_factor: f32,
+    render_format: wgpu::TextureFormat,
     shadow_cascades: Vec<(f32, f32)>,
+    shadow_map_resolutions: [u32; VisualServer::MAX_SHADOW_CASCADES as usize],
+    shadow_filter_radius: u32,
+    shadow_map_filter: wgpu::FilterMode,
+    shadow_map_compare: wgpu::CompareFunction,
+    shadow_cascade_blend_width: f32,
+    shadow_caster_extrusion: f32,
     tone_mapping: ToneMapping,
+    exposure: f32,
+    upscale_filter: UpscaleFilter,
+    taa: bool,
+    camera_jitter: bool,
+    opaque_draw_order: DrawOrder,
+    color_load_op: ColorLoadOp,
+    render_2d: bool,
+    clean_render: bool,
+    texture_streaming: bool,
+}
+
+struct Passes {
+    taa: PassId<Taa>,
+    depth_of_field: PassId<DepthOfField>,
+    bloom: PassId<Bloom>,
+    auto_exposure: PassId<AutoExposure>,
+}
+
+struct Skybox {
+    handle: Handle<Cubemap>,
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
 }
 
 struct Samplers {
     #[allow(unused)]
-    unfiltered: wgpu::Sampler,
-    filtered: wgpu::Sampler,
-    shadow_map: wgpu::Sampler,
+    unfiltered: Arc<wgpu::Sampler>,
+    filtered: Arc<wgpu::Sampler>,
+    shadow_map: Arc<wgpu::Sampler>,
 }
 
 // Keep coherent with shader tyvm.
@@ -1220,4 +2966,47 @@ struct Samplers {
 pub enum ToneMapping {
     None = 0,
     Reinhard = 1,
+    /// Narkowicz's fit of the ACES filmic curve, which desaturates and rolls off highlights.
+    AcesFilmic = 2,
+    /// John Hable's filmic curve from Uncharted 2.
+    Uncharted2 = 3,
+}
+
+/// How the color of the 3d render target starts out each frame. The depth is always cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorLoadOp {
+    /// To the clear color.
+    Clear,
+    /// With the previous frame.
+    Load,
+}
+
+/// Order meshes are drawn in, by their distance from the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawOrder {
+    Unsorted,
+    FrontToBack,
+    BackToFront,
+}
+
+/// How frames are queued for the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for vertical blanks, capping the framerate to the display's refresh rate.
+    Fifo,
+    /// Waits for vertical blanks without blocking rendering, newer frames replacing the queued
+    /// one. No tearing, less latency than `Fifo`.
+    Mailbox,
+    /// Presents right away, uncapped but with tearing.
+    Immediate,
+}
+
+// Keep coherent with shader tyvm.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    Nearest = 0,
+    Bilinear = 1,
+    /// Bilinear, then sharpened by how little contrast there is around each pixel.
+    Sharpen = 2,
 }
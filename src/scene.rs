@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use glam::Affine3A;
 
@@ -6,7 +6,7 @@ use crate::{
     arena::{Arena, Handle},
     engine::Context,
     ui::UiBox,
-    Camera, Light, Mesh,
+    AnimationPlayer, Camera, Color, Light, Mesh,
 };
 
 pub type NodeId = Handle<Node>;
@@ -20,6 +20,9 @@ pub struct Scene {
     pub nodes: Arena<Node>,
     pub root: NodeId,
     pub children: HashMap<NodeId, Vec<NodeId>>,
+    pub parents: HashMap<NodeId, NodeId>,
+    // Removed nodes whose render resources still have to be dropped.
+    pub(crate) removed_nodes: Vec<UniqueNodeId>,
 }
 
 impl Scene {
@@ -31,6 +34,8 @@ impl Scene {
             nodes,
             root,
             children: Default::default(),
+            parents: Default::default(),
+            removed_nodes: Vec::new(),
         }
     }
 
@@ -45,9 +50,103 @@ impl Scene {
     pub fn add_child(&mut self, parent: NodeId, child: Node) -> NodeId {
         let child = self.nodes.allocate(child);
         self.children.entry(parent).or_default().push(child);
+        self.parents.insert(child, parent);
         child
     }
 
+    /// Detaches the node from its parent and drops its descendants, freeing their slots for
+    /// reuse. From an update function, use `Node::queue_remove` instead.
+    pub fn remove_node(&mut self, node_id: NodeId) -> Result<Node, String> {
+        if node_id == self.root {
+            return Err("the root node cannot be removed".to_string());
+        }
+        if !self.nodes.contains(node_id) {
+            return Err(format!("no such node: {:?}", node_id));
+        }
+
+        if let Some(parent) = self.parents.remove(&node_id) {
+            if let Some(siblings) = self.children.get_mut(&parent) {
+                siblings.retain(|&id| id != node_id);
+            }
+        }
+
+        let mut descendants = self.children.remove(&node_id).unwrap_or_default();
+        while let Some(id) = descendants.pop() {
+            self.parents.remove(&id);
+            descendants.extend(self.children.remove(&id).unwrap_or_default());
+            let node = self.nodes.free(id);
+            self.note_removed(id, &node);
+        }
+        let node = self.nodes.free(node_id);
+        self.note_removed(node_id, &node);
+        Ok(node)
+    }
+
+    /// Removes the nodes that got `Node::queue_remove` called on them, in this scene and
+    /// its subscenes.
+    pub(crate) fn remove_queued_nodes(&mut self) {
+        let queued: Vec<NodeId> = self
+            .nodes
+            .elements()
+            .filter(|(_, node)| node.remove_queued)
+            .map(|(id, _)| id)
+            .collect();
+        for id in queued {
+            // Already gone if an ancestor was queued too.
+            if self.nodes.contains(id) {
+                let _ = self.remove_node(id);
+            }
+        }
+
+        let subscenes: Vec<NodeId> = self
+            .nodes
+            .elements()
+            .filter(|(_, node)| matches!(node.data, NodeData::Scene(_)))
+            .map(|(id, _)| id)
+            .collect();
+        for id in subscenes {
+            if let NodeData::Scene(subscene) = &mut self.get_mut(id).data {
+                subscene.remove_queued_nodes();
+            }
+        }
+    }
+
+    /// Takes the removed nodes of this scene and its subscenes.
+    pub(crate) fn take_removed_nodes(&mut self) -> Vec<UniqueNodeId> {
+        let mut removed = std::mem::take(&mut self.removed_nodes);
+        let subscenes: Vec<NodeId> = self
+            .nodes
+            .elements()
+            .filter(|(_, node)| matches!(node.data, NodeData::Scene(_)))
+            .map(|(id, _)| id)
+            .collect();
+        for id in subscenes {
+            if let NodeData::Scene(subscene) = &mut self.get_mut(id).data {
+                removed.extend(subscene.take_removed_nodes());
+            }
+        }
+        removed
+    }
+
+    fn note_removed(&mut self, node_id: NodeId, node: &Node) {
+        if let Some(handle) = self.handle {
+            self.removed_nodes.push(UniqueNodeId(handle, node_id));
+        }
+        // Nothing will drop the resources of the subscene's nodes otherwise.
+        if let NodeData::Scene(subscene) = &node.data {
+            self.removed_nodes
+                .extend(subscene.removed_nodes.iter().copied());
+            if let Some(handle) = subscene.handle {
+                self.removed_nodes.extend(
+                    subscene
+                        .nodes
+                        .elements()
+                        .map(|(id, _)| UniqueNodeId(handle, id)),
+                );
+            }
+        }
+    }
+
     pub fn children_of(&self, node_id: NodeId) -> &[NodeId] {
         self.children
             .get(&node_id)
@@ -55,16 +154,127 @@ impl Scene {
             .unwrap_or(&[])
     }
 
+    pub fn parent_of(&self, node_id: NodeId) -> Option<NodeId> {
+        self.parents.get(&node_id).copied()
+    }
+
+    /// Transform of the node relative to the root of this scene.
+    pub fn global_transform(&self, node_id: NodeId) -> Affine3A {
+        let mut transform = self.get(node_id).transform;
+        let mut current = node_id;
+        while let Some(parent) = self.parent_of(current) {
+            transform = self.get(parent).transform * transform;
+            current = parent;
+        }
+        transform
+    }
+
+    /// Number of ancestors of the node, 0 for the root.
+    pub fn depth(&self, node_id: NodeId) -> usize {
+        self.ancestors(node_id).count()
+    }
+
+    /// The node and its ancestors, starting from the root.
+    pub fn path(&self, node_id: NodeId) -> Vec<NodeId> {
+        let mut path: Vec<NodeId> = std::iter::once(node_id)
+            .chain(self.ancestors(node_id))
+            .collect();
+        path.reverse();
+        path
+    }
+
+    /// Names along the path of the node, like "root/Sponza/floor", for debugging. Unnamed nodes
+    /// show up as their id, like "#12".
+    pub fn path_string(&self, node_id: NodeId) -> String {
+        self.path(node_id)
+            .into_iter()
+            .map(|id| match &self.get(id).name {
+                Some(name) => name.clone(),
+                None if id == self.root => "root".to_string(),
+                None => format!("#{}", id.id()),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn ancestors(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent_of(node_id), |&id| self.parent_of(id))
+    }
+
     pub fn make_unique_node_id(&self, node_id: NodeId) -> UniqueNodeId {
         UniqueNodeId(self.handle.expect("dont call this if it crashes"), node_id)
     }
+
+    /// Camera nodes of this scene, not counting those of its subscenes. Handy to pick one of
+    /// the cameras imported with a scene to render from.
+    pub fn cameras(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes
+            .elements()
+            .filter(|(_, node)| matches!(node.data, NodeData::Camera(_)))
+            .map(|(id, _)| id)
+    }
+
+    /// Counts the nodes of this scene and of its subscenes, for debugging.
+    pub fn stats(&self) -> SceneStats {
+        let mut stats = SceneStats::default();
+        for (_, node) in self.nodes.elements() {
+            stats.nodes += 1;
+            match &node.data {
+                NodeData::Camera(_) => stats.cameras += 1,
+                NodeData::Light(_) => stats.lights += 1,
+                NodeData::Mesh(_) => stats.meshes += 1,
+                NodeData::Scene(subscene) => {
+                    let substats = subscene.stats();
+                    stats.nodes += substats.nodes;
+                    stats.meshes += substats.meshes;
+                    stats.lights += substats.lights;
+                    stats.cameras += substats.cameras;
+                }
+                NodeData::Empty | NodeData::UiBox(_) => {}
+            }
+        }
+        stats
+    }
+
+    /// Meshes instanced by this scene and its subscenes.
+    pub fn meshes(&self) -> HashSet<Handle<Mesh>> {
+        let mut meshes = HashSet::new();
+        for (_, node) in self.nodes.elements() {
+            match &node.data {
+                NodeData::Mesh(mesh_instance) => {
+                    meshes.insert(mesh_instance.mesh);
+                }
+                NodeData::Scene(subscene) => meshes.extend(subscene.meshes()),
+                _ => {}
+            }
+        }
+        meshes
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    /// Every node, subscene nodes and their roots included.
+    pub nodes: u32,
+    pub meshes: u32,
+    pub lights: u32,
+    pub cameras: u32,
 }
 
 #[derive(Clone)]
 pub struct Node {
+    pub name: Option<String>,
     pub transform: Affine3A,
     pub data: NodeData,
     pub update_fn: Option<fn(&mut Node, &mut Context)>,
+    /// Nodes with a lower priority get updated first. Nodes with the same priority are updated
+    /// in scene order.
+    pub update_priority: i32,
+    /// Paused nodes are still rendered, but their update function doesn't run.
+    pub pause: PauseMode,
+    /// Animates the nodes of this node's scene, after update functions run.
+    pub animation_player: Option<AnimationPlayer>,
+    remove_queued: bool,
 }
 
 impl Node {
@@ -81,7 +291,11 @@ impl Node {
     }
 
     pub fn new_mesh(mesh: Handle<Mesh>) -> Self {
-        Self::with_data(NodeData::Mesh(mesh))
+        Self::new_mesh_instance(MeshInstance::new(mesh))
+    }
+
+    pub fn new_mesh_instance(mesh_instance: MeshInstance) -> Self {
+        Self::with_data(NodeData::Mesh(mesh_instance))
     }
 
     pub fn new_scene(scene: Scene) -> Self {
@@ -108,12 +322,27 @@ impl Node {
 
     pub fn with_data(data: NodeData) -> Self {
         Self {
+            name: None,
             transform: Default::default(),
             data,
             update_fn: None,
+            update_priority: 0,
+            pause: PauseMode::Off,
+            animation_player: None,
+            remove_queued: false,
         }
     }
 
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_animation_player(mut self, animation_player: AnimationPlayer) -> Self {
+        self.animation_player = Some(animation_player);
+        self
+    }
+
     pub fn with_transform(mut self, transform: Affine3A) -> Self {
         self.transform = transform;
         self
@@ -123,6 +352,31 @@ impl Node {
         self.update_fn = Some(update_fn);
         self
     }
+
+    pub fn with_update_priority(mut self, priority: i32) -> Self {
+        self.update_priority = priority;
+        self
+    }
+
+    pub fn with_pause(mut self, pause: PauseMode) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Removes the node and its descendants once every update function of the frame ran.
+    pub fn queue_remove(&mut self) {
+        self.remove_queued = true;
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    #[default]
+    Off,
+    /// Only this node is paused.
+    Node,
+    /// This node and all of its descendants are paused.
+    Subtree,
 }
 
 #[derive(Clone)]
@@ -130,7 +384,28 @@ pub enum NodeData {
     Empty,
     Camera(Camera),
     Light(Light),
-    Mesh(Handle<Mesh>),
+    Mesh(MeshInstance),
     Scene(Box<Scene>),
     UiBox(UiBox),
 }
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeshInstance {
+    pub mesh: Handle<Mesh>,
+    /// Multiplied with the base color of the mesh's materials.
+    pub tint: Color,
+}
+
+impl MeshInstance {
+    pub fn new(mesh: Handle<Mesh>) -> Self {
+        Self {
+            mesh,
+            tint: Color::WHITE,
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+}
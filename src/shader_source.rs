@@ -1,6 +1,9 @@
 pub use asset_shader_source::ShaderSource;
 
-use crate::asset_server::{Asset, Loadable, Loader};
+use crate::{
+    asset_server::{Asset, Loadable, Loader},
+    AssetError,
+};
 
 impl Loadable for ShaderSource {
     fn new_placeholder() -> Self {
@@ -27,8 +30,15 @@ impl ShaderSourceLoader {
 }
 
 impl Loader for ShaderSourceLoader {
-    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, String> {
-        let shader_source = ShaderSource::load_from_path(path, std::mem::take(&mut self.defines))?;
+    fn load_from_path(&mut self, path: &str) -> Result<Box<dyn Asset>, AssetError> {
+        let defines = std::mem::take(&mut self.defines);
+        #[cfg(not(target_arch = "wasm32"))]
+        let shader_source = ShaderSource::load_from_path(path, defines)?;
+        #[cfg(target_arch = "wasm32")]
+        let shader_source =
+            ShaderSource::preprocessed(embedded_shader(path)?, defines, |import| {
+                embedded_shader(&format!("src/renderer/shaders/{import}")).map(str::to_string)
+            })?;
         shader_source.validate()?;
         Ok(Box::new(shader_source))
     }
@@ -37,3 +47,32 @@ impl Loader for ShaderSourceLoader {
         true
     }
 }
+
+/// The web has no file system to load the renderer's shaders from, so they are built in there.
+#[cfg(target_arch = "wasm32")]
+fn embedded_shader(path: &str) -> Result<&'static str, AssetError> {
+    macro_rules! shaders {
+        ($($name:literal),* $(,)?) => {
+            match path.strip_prefix("src/renderer/shaders/") {
+                $(Some($name) => Ok(include_str!(concat!("renderer/shaders/", $name))),)*
+                _ => Err(AssetError::Unsupported(format!(
+                    "{path} isn't a built in shader, files can't be read on the web"
+                ))),
+            }
+        };
+    }
+    shaders!(
+        "auto_exposure.wgsl",
+        "bloom.wgsl",
+        "blur.wgsl",
+        "depth_of_field.wgsl",
+        "fullscreen.wgsl",
+        "fullscreen_texture.wgsl",
+        "render_mesh.wgsl",
+        "render_shadow_map.wgsl",
+        "skybox.wgsl",
+        "taa.wgsl",
+        "text.wgsl",
+        "uibox.wgsl",
+    )
+}
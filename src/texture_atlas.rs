@@ -0,0 +1,99 @@
+use glam::{UVec2, Vec2};
+
+use crate::{AssetError, Image};
+
+/// Where an image ended up in an atlas, in uv coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRect {
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+}
+
+pub struct TextureAtlas {
+    pub image: Image,
+    /// In the same order as the images given to `pack`.
+    pub rects: Vec<AtlasRect>,
+}
+
+impl TextureAtlas {
+    const MAX_SIZE: u32 = 4096;
+    // Keeps filtering from bleeding neighboring images into each other. Only between images, not
+    // along the edges of the atlas.
+    const PADDING: u32 = 1;
+
+    /// Packs the images in rows, tallest first, in the smallest square power of two atlas
+    /// they fit in.
+    pub fn pack(images: &[&Image]) -> Result<Self, AssetError> {
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(images[i].height()));
+
+        // Starts from the smallest size that could hold the images, and grows from there.
+        let area: u64 = images
+            .iter()
+            .map(|image| image.width() as u64 * image.height() as u64)
+            .sum();
+        let mut size = ((area as f64).sqrt().ceil() as u32).next_power_of_two();
+        let positions = loop {
+            if size > Self::MAX_SIZE {
+                return Err(AssetError::Unsupported(format!(
+                    "images don't fit in a {0}x{0} atlas",
+                    Self::MAX_SIZE
+                )));
+            }
+            if let Some(positions) = shelf_pack(images, &order, size) {
+                break positions;
+            }
+            size *= 2;
+        };
+
+        let mut data = vec![0; (size * size * 4) as usize];
+        for (image, position) in images.iter().zip(&positions) {
+            let row_len = image.width() as usize * 4;
+            for y in 0..image.height() as usize {
+                // The first mip level comes first in the image data.
+                let src = &image.data()[y * row_len..(y + 1) * row_len];
+                let dst_start =
+                    ((position.y as usize + y) * size as usize + position.x as usize) * 4;
+                data[dst_start..dst_start + row_len].copy_from_slice(src);
+            }
+        }
+
+        let rects = images
+            .iter()
+            .zip(&positions)
+            .map(|(image, &position)| {
+                let image_size = UVec2::new(image.width(), image.height());
+                AtlasRect {
+                    uv_min: position.as_vec2() / size as f32,
+                    uv_max: (position + image_size).as_vec2() / size as f32,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            image: Image::from_rgba8(size, size, data)?,
+            rects,
+        })
+    }
+}
+
+fn shelf_pack(images: &[&Image], order: &[usize], size: u32) -> Option<Vec<UVec2>> {
+    let mut positions = vec![UVec2::ZERO; images.len()];
+    let mut cursor = UVec2::ZERO;
+    let mut shelf_height = 0;
+    for &i in order {
+        let (width, height) = (images[i].width(), images[i].height());
+        if cursor.x + width > size {
+            cursor = UVec2::new(0, cursor.y + shelf_height);
+            shelf_height = 0;
+        }
+        if cursor.x + width > size || cursor.y + height > size {
+            return None;
+        }
+
+        positions[i] = cursor;
+        cursor.x += width + TextureAtlas::PADDING;
+        shelf_height = shelf_height.max(height + TextureAtlas::PADDING);
+    }
+    Some(positions)
+}
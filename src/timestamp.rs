@@ -5,10 +5,21 @@ pub struct Timestamp {
 }
 
 impl Timestamp {
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn now() -> Self {
         Self::from(std::time::SystemTime::now())
     }
 
+    // The system clock isn't available to wasm32-unknown-unknown, it panics.
+    #[cfg(target_arch = "wasm32")]
+    pub fn now() -> Self {
+        let millis = js_sys::Date::now() as u64;
+        Self {
+            seconds: (millis / 1_000) as u32,
+            millis: (millis % 1_000) as u32,
+        }
+    }
+
     pub fn seconds_since(&self) -> f64 {
         Self::now().as_seconds() - self.as_seconds()
     }
@@ -147,6 +147,7 @@ impl<'a> UiBuilder<'a> {
             },
             text: Some(String::from(text)),
             on_click,
+            interactive: true,
             ..Default::default()
         });
         if let Some(update_fn) = update {
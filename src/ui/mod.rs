@@ -5,23 +5,55 @@ use winit::event::MouseButton;
 
 use crate::{
     engine::Context,
-    renderer::{pipeline2d::uibox_instance::UiBoxInstance, visual_server::TextDescriptor},
+    renderer::{
+        pipeline2d::uibox_instance::UiBoxInstance,
+        visual_server::{TextAlign, TextDescriptor},
+    },
     scene::NodeId,
-    Color, Scene,
+    AtlasRect, Color, Scene,
 };
 
 pub mod helpers;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct UiBox {
     pub rect: Rect, // Determined by layout
     pub state: UiBoxState,
     pub layout: Layout,
     pub style: Style,
     pub text: Option<String>,
+    /// Where the box's image is in the ui atlas image set on the visual server. The image is
+    /// tinted by the box's color.
+    pub image: Option<AtlasRect>,
     pub on_click: Option<fn(&mut Context)>,
+    /// Only interactive boxes get hovered and pressed. The topmost one under the pointer
+    /// captures it, the others stay normal.
+    pub interactive: bool,
     pub active: bool,
     pub hide: bool,
+    /// The ui only gets laid out again when the window is resized or when a box is marked dirty,
+    /// so this needs to be set after changing `layout` or `hide`.
+    pub layout_dirty: bool,
+    pub canvas_size: Option<Vec2>, // Determined by layout, only on the ui root
+}
+
+impl Default for UiBox {
+    fn default() -> Self {
+        Self {
+            rect: Default::default(),
+            state: Default::default(),
+            layout: Default::default(),
+            style: Default::default(),
+            text: None,
+            image: None,
+            on_click: None,
+            interactive: false,
+            active: false,
+            hide: false,
+            layout_dirty: true,
+            canvas_size: None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -87,6 +119,12 @@ pub struct Style {
     pub pressed_color: Option<Color>,
     pub active_color: Option<Color>,
     pub font_size: f32,
+    /// Space added between the glyphs of the text, in pixels.
+    pub letter_spacing: f32,
+    pub text_align: TextAlign,
+    pub outline_color: Color,
+    /// Of the text, in pixels.
+    pub outline_width: f32,
 }
 
 impl Default for Style {
@@ -97,21 +135,45 @@ impl Default for Style {
             pressed_color: None,
             active_color: None,
             font_size: 16.0,
+            letter_spacing: 0.0,
+            text_align: TextAlign::Left,
+            outline_color: Color::BLACK,
+            outline_width: 1.0,
         }
     }
 }
 
 pub fn layout(ui_root_id: NodeId, scene: &mut Scene, context: &Context) {
+    // Every dirty flag gets cleared, even on hidden boxes, so they don't keep the ui dirty.
+    fn take_layout_dirty(node_id: NodeId, scene: &mut Scene) -> bool {
+        let Some(uibox) = scene.get_mut(node_id).as_uibox_mut() else {
+            return false;
+        };
+        let mut dirty = std::mem::take(&mut uibox.layout_dirty);
+        for child_id in scene.children_of(node_id).to_vec() {
+            dirty |= take_layout_dirty(child_id, scene);
+        }
+        dirty
+    }
+    let dirty = take_layout_dirty(ui_root_id, scene);
+
     // Layout ui root
     let Some(root_uibox) = scene.get_mut(ui_root_id).as_uibox_mut() else {
         return;
     };
 
+    let canvas_size = context.display.window_inner_size.as_vec2();
+    if !dirty && root_uibox.canvas_size == Some(canvas_size) {
+        return;
+    }
+
     if root_uibox.hide {
+        // Lay it out once it's shown again.
+        root_uibox.canvas_size = None;
         return;
     }
 
-    let canvas_size = context.display.window_inner_size.as_vec2();
+    root_uibox.canvas_size = Some(canvas_size);
     root_uibox.rect = Rect {
         pos: Vec2::ZERO,
         size: Vec2::new(
@@ -268,20 +330,32 @@ pub fn input(ui_root_id: NodeId, scene: &mut Scene, context: &mut Context) {
         }
     }
 
+    // Boxes are painted in the order they're gathered, the last one is on top.
+    let pointed_node_id = ui_nodes.iter().rev().copied().find(|&node_id| {
+        let uibox = scene.get(node_id).as_uibox().unwrap();
+        uibox.interactive && uibox.rect.contains(context.input.pointer_pos)
+    });
+
     for node_id in ui_nodes {
         let node = scene.get_mut(node_id);
         let uibox = node.as_uibox_mut().unwrap();
 
-        if uibox.rect.contains(context.input.pointer_pos) && !context.input.pointer_grabbed {
-            if context.input.is_button_pressed(MouseButton::Left) {
-                uibox.state = UiBoxState::Pressed;
+        if pointed_node_id == Some(node_id) && !context.input.pointer_grabbed {
+            // Clicks have to both start and end on the box.
+            let input = &context.input;
+            let was_pressed = uibox.state == UiBoxState::Pressed;
+            let clicked = was_pressed && input.is_button_just_released(MouseButton::Left);
+            uibox.state = if input.is_button_just_pressed(MouseButton::Left)
+                || (was_pressed && input.is_button_pressed(MouseButton::Left))
+            {
+                UiBoxState::Pressed
             } else {
-                if uibox.state == UiBoxState::Pressed {
-                    if let Some(handler) = uibox.on_click {
-                        handler(context);
-                    }
+                UiBoxState::Hovered
+            };
+            if clicked {
+                if let Some(handler) = uibox.on_click {
+                    handler(context);
                 }
-                uibox.state = UiBoxState::Hovered;
             }
         } else {
             uibox.state = UiBoxState::Normal;
@@ -322,19 +396,35 @@ pub fn paint(ui_root_id: NodeId, scene: &Scene, context: &mut Context) {
             position: uibox.rect.pos.to_array(),
             size: uibox.rect.size.to_array(),
             color: color.to_array(),
+            uv_rect: uibox.image.map_or([0.0; 4], |image| {
+                [
+                    image.uv_min.x,
+                    image.uv_min.y,
+                    image.uv_max.x,
+                    image.uv_max.y,
+                ]
+            }),
         });
 
         if let Some(text) = uibox.text.as_ref() {
             let content_rect = uibox.rect.shrunk(uibox.layout.padding);
-            context.visual_server.add_text(
-                node_id,
-                TextDescriptor {
-                    text: text.as_bytes(),
-                    position: content_rect.pos,
-                    font_size: uibox.style.font_size,
-                    max_width: uibox.rect.size.x,
-                },
-            );
+            let mut text = TextDescriptor {
+                text: text.as_bytes(),
+                position: content_rect.pos,
+                font_size: uibox.style.font_size,
+                letter_spacing: uibox.style.letter_spacing,
+                max_width: content_rect.size.x,
+                align: uibox.style.text_align,
+                outline_color: uibox.style.outline_color,
+                outline_width: uibox.style.outline_width,
+            };
+            // Center the lines vertically in the content rect, from the ascent line of the first
+            // to the descent line of the last.
+            let metrics = context.visual_server.font_metrics();
+            let height = text.height(text.lines(metrics).len(), metrics);
+            let above_ascent = (metrics.baseline() - metrics.ascent()) * text.font_size;
+            text.position.y += (content_rect.size.y - height) * 0.5 - above_ascent;
+            context.visual_server.add_text(node_id, text);
         }
 
         for &child_id in scene.children_of(node_id) {